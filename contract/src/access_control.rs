@@ -0,0 +1,126 @@
+use near_sdk::{AccountId, env};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, UnorderedSet};
+use near_sdk::serde::{Deserialize, Serialize};
+
+use crate::events::{Event, OwnershipTransferred, RoleChanged};
+
+/// Роли привилегированных аккаунтов
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum Role {
+    /// выдает и отзывает роли, может все, что могут остальные роли
+    Admin,
+    /// добавляет токены и управляет их метаданными
+    ListingManager,
+    /// управляет комиссиями биржи
+    FeeManager,
+    /// ставит подсистемы на паузу
+    Pauser,
+}
+
+/// Владелец контракта и участники ролей
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct AccessControl {
+    pub owner_id: AccountId,
+    /// новый владелец, который еще не принял владение
+    pub pending_owner_id: Option<AccountId>,
+    members: LookupMap<Role, UnorderedSet<AccountId>>,
+}
+
+impl AccessControl {
+    pub fn new(owner_id: AccountId) -> Self {
+        let mut access = Self {
+            owner_id: owner_id.clone(),
+            pending_owner_id: None,
+            members: LookupMap::new(b"rl".to_vec()),
+        };
+        access.insert_member(Role::Admin, &owner_id);
+        access
+    }
+
+    /// Есть ли у `account_id` роль `role`. Admin и сам контракт проходят любую проверку.
+    pub fn has_role(&self, role: Role, account_id: &AccountId) -> bool {
+        account_id == &env::current_account_id()
+            || self.get_members(role).contains(account_id)
+            || self.get_members(Role::Admin).contains(account_id)
+    }
+
+    pub fn assert_role(&self, role: Role) {
+        if !self.has_role(role, &env::predecessor_account_id()) {
+            env::panic(format!("Requires the {:?} role", role).as_bytes());
+        }
+    }
+
+    pub fn get_role_members(&self, role: Role) -> Vec<AccountId> {
+        self.get_members(role).to_vec()
+    }
+
+    pub fn grant_role(&mut self, role: Role, account_id: AccountId) {
+        self.assert_role(Role::Admin);
+        if self.insert_member(role, &account_id) {
+            Event::RoleGranted(vec![RoleChanged {
+                role,
+                account_id,
+                changed_by: env::predecessor_account_id(),
+            }]).emit();
+        }
+    }
+
+    pub fn revoke_role(&mut self, role: Role, account_id: AccountId) {
+        self.assert_role(Role::Admin);
+        if role == Role::Admin && account_id == self.owner_id {
+            env::panic(b"Can't revoke the Admin role from the owner");
+        }
+        let mut members = self.get_members(role);
+        if members.remove(&account_id) {
+            self.members.insert(&role, &members);
+            Event::RoleRevoked(vec![RoleChanged {
+                role,
+                account_id,
+                changed_by: env::predecessor_account_id(),
+            }]).emit();
+        }
+    }
+
+    /// Первый шаг передачи владения: владелец предлагает `new_owner_id`
+    pub fn transfer_ownership(&mut self, new_owner_id: AccountId) {
+        if env::predecessor_account_id() != self.owner_id {
+            env::panic(b"Only the owner can transfer ownership");
+        }
+        self.pending_owner_id = Some(new_owner_id);
+    }
+
+    /// Второй шаг: новый владелец принимает владение и роль Admin, старый теряет роль Admin
+    pub fn accept_ownership(&mut self) {
+        let new_owner_id = env::predecessor_account_id();
+        if self.pending_owner_id.as_ref() != Some(&new_owner_id) {
+            env::panic(b"Only the pending owner can accept ownership");
+        }
+        let old_owner_id = std::mem::replace(&mut self.owner_id, new_owner_id.clone());
+        self.pending_owner_id = None;
+        let mut admins = self.get_members(Role::Admin);
+        admins.remove(&old_owner_id);
+        self.members.insert(&Role::Admin, &admins);
+        self.insert_member(Role::Admin, &new_owner_id);
+        Event::OwnershipTransfer(vec![OwnershipTransferred {
+            old_owner_id,
+            new_owner_id,
+        }]).emit();
+    }
+
+    fn insert_member(&mut self, role: Role, account_id: &AccountId) -> bool {
+        let mut members = self.get_members(role);
+        let inserted = members.insert(account_id);
+        self.members.insert(&role, &members);
+        inserted
+    }
+
+    fn get_members(&self, role: Role) -> UnorderedSet<AccountId> {
+        self.members.get(&role).unwrap_or_else(|| {
+            let mut prefix = b"rm".to_vec();
+            prefix.push(role as u8);
+            UnorderedSet::new(prefix)
+        })
+    }
+}
@@ -1,6 +1,38 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::LookupMap;
-use near_sdk::{env, AccountId, Balance};
+use near_sdk::collections::{LookupMap, UnorderedMap};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::{env, AccountId, Balance, Timestamp};
+
+use crate::token::TokenId;
+use crate::vesting::VestingSchedule;
+
+/// Разрешение списывать токены со счета
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug, PartialEq)]
+pub struct Allowance {
+    pub amount: Balance,
+    /// после этого времени разрешение не действует, `None` - бессрочно
+    pub expires_at: Option<Timestamp>,
+}
+
+impl Allowance {
+    pub fn is_expired(&self) -> bool {
+        match self.expires_at {
+            Some(expires_at) => env::block_timestamp() >= expires_at,
+            None => false,
+        }
+    }
+}
+
+/// Выданное разрешение для view-методов
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AllowanceView {
+    pub token_id: TokenId,
+    pub escrow_account_id: AccountId,
+    pub allowance: U128,
+    pub expires_at: Option<Timestamp>,
+}
 
 /// Contains balance and allowances information for one account.
 #[derive(BorshDeserialize, BorshSerialize)]
@@ -10,31 +42,76 @@ pub struct TokenAccount {
     /// Информация о том,
     /// Сколько каждому конкретному кошельку
     /// дозволено снимать с этого счета
-    pub allowances: LookupMap<Vec<u8>, Balance>,
+    pub allowances: UnorderedMap<AccountId, Allowance>,
+    /// часть баланса, которая разблокируется со временем
+    pub vesting: Option<VestingSchedule>,
 }
 
 impl TokenAccount {
-    /// Initializes a new Account with 0 balance and no allowances for a given `account_hash`.
-    pub fn new(account_hash: Vec<u8>) -> Self {
+    /// Initializes a new Account with 0 balance and no allowances for `owner_id` and `token_id`.
+    pub fn new(owner_id: &AccountId, token_id: &TokenId) -> Self {
+        let mut prefix = b"al".to_vec();
+        prefix.extend(env::sha256(format!("{}:{}", owner_id, token_id).as_bytes()));
         Self {
             balance: 0,
-            allowances: LookupMap::new(account_hash),
+            allowances: UnorderedMap::new(prefix),
+            vesting: None,
         }
     }
 
+    /// Баланс, заблокированный вестингом
+    pub fn locked_balance(&self) -> Balance {
+        match &self.vesting {
+            Some(vesting) => vesting.locked(env::block_timestamp()),
+            None => 0,
+        }
+    }
+
+    /// Баланс, который можно перевести, выставить в ордер или сжечь
+    pub fn available_balance(&self) -> Balance {
+        self.balance.saturating_sub(self.locked_balance())
+    }
+
     /// Sets allowance for account `escrow_account_id` to `allowance`.
-    pub fn set_allowance(&mut self, escrow_account_id: &AccountId, allowance: Balance) {
-        let escrow_hash = env::sha256(escrow_account_id.as_bytes());
-        if allowance > 0 {
-            self.allowances.insert(&escrow_hash, &allowance);
+    pub fn set_allowance(&mut self, escrow_account_id: &AccountId, allowance: Allowance) {
+        if allowance.amount > 0 {
+            self.allowances.insert(escrow_account_id, &allowance);
         } else {
-            self.allowances.remove(&escrow_hash);
+            self.allowances.remove(escrow_account_id);
         }
     }
 
-    /// Returns the allowance of account `escrow_account_id`.
+    /// Returns the allowance of account `escrow_account_id`, expired allowance is 0.
     pub fn get_allowance(&self, escrow_account_id: &AccountId) -> Balance {
-        let escrow_hash = env::sha256(escrow_account_id.as_bytes());
-        self.allowances.get(&escrow_hash).unwrap_or(0)
+        match self.allowances.get(escrow_account_id) {
+            Some(allowance) if !allowance.is_expired() => allowance.amount,
+            _ => 0,
+        }
+    }
+
+    /// Returns all allowances that are not expired yet.
+    pub fn get_allowances(&self) -> Vec<(AccountId, Allowance)> {
+        self.allowances
+            .iter()
+            .filter(|(_, allowance)| !allowance.is_expired())
+            .collect()
+    }
+}
+
+/// Счет токена в раскладке версии 1: разрешения лежали в карте, общей для всех токенов владельца
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TokenAccountV1 {
+    pub balance: Balance,
+    pub allowances: LookupMap<Vec<u8>, Balance>,
+}
+
+impl TokenAccountV1 {
+    /// Разрешения версии 1 действовали сразу на все токены владельца и не перечисляются,
+    /// поэтому не переносятся: владелец выдает их заново.
+    pub fn into_current(self, owner_id: &AccountId, token_id: &TokenId) -> TokenAccount {
+        TokenAccount {
+            balance: self.balance,
+            ..TokenAccount::new(owner_id, token_id)
+        }
     }
 }
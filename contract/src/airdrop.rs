@@ -0,0 +1,78 @@
+use near_sdk::{AccountId, Balance, Gas};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::UnorderedSet;
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+
+use crate::snapshot::SnapshotId;
+use crate::token::TokenId;
+
+pub type AirdropId = u64;
+
+/// Сколько получателей максимум обрабатывается за один вызов
+pub const MAX_BATCH_SIZE: u64 = 50;
+/// Запас газа на одного получателя, чтобы успеть сохранить прогресс раздачи
+pub const GAS_PER_AIRDROP_TRANSFER: Gas = 5_000_000_000_000;
+
+/// Раздача `amount` токенов держателям `basis_token_id` пропорционально их балансам на момент
+/// снимка `snapshot_id`. Токены лежат на контракте, пока раздача не закончится.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Airdrop {
+    pub owner_id: AccountId,
+    pub token_id: TokenId,
+    pub amount: Balance,
+    pub snapshot_id: SnapshotId,
+    pub basis_token_id: TokenId,
+    pub total_basis: Balance,
+    /// сколько позиций индекса держателей еще не пройдено, индекс проходится с конца
+    pub remaining: u64,
+    /// первая непройденная позиция журнала выбывших держателей, он проходится после индекса
+    pub next_exit: u64,
+    /// кому доля уже выплачена: перестановки в индексе держателей не дают заплатить дважды.
+    /// После выплат отметки снимаются.
+    pub paid: UnorderedSet<AccountId>,
+    /// сколько токенов уже роздано
+    pub distributed: Balance,
+}
+
+impl Airdrop {
+    pub fn new(airdrop_id: AirdropId, owner_id: AccountId, token_id: TokenId, amount: Balance, snapshot_id: SnapshotId, basis_token_id: TokenId) -> Self {
+        let mut prefix = b"adp".to_vec();
+        prefix.extend(airdrop_id.to_le_bytes().iter());
+        Self {
+            owner_id,
+            token_id,
+            amount,
+            snapshot_id,
+            basis_token_id,
+            total_basis: 0,
+            remaining: 0,
+            next_exit: 0,
+            paid: UnorderedSet::new(prefix),
+            distributed: 0,
+        }
+    }
+
+    /// Доля держателя с балансом `basis`
+    pub fn share(&self, basis: Balance) -> Balance {
+        self.amount * basis / self.total_basis
+    }
+
+    /// `exits` - текущая длина журнала выбывших держателей
+    pub fn is_finished(&self, exits: u64) -> bool {
+        self.remaining == 0 && self.next_exit >= exits && self.paid.is_empty()
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AirdropView {
+    pub airdrop_id: AirdropId,
+    pub owner_id: AccountId,
+    pub token_id: TokenId,
+    pub amount: U128,
+    pub snapshot_id: SnapshotId,
+    pub basis_token_id: TokenId,
+    pub remaining: u64,
+    pub distributed: U128,
+}
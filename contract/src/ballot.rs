@@ -1,18 +1,23 @@
 use near_sdk::{AccountId, Balance, env, Timestamp};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::serde::{Serialize, Deserialize};
-use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet};
+use near_sdk::collections::{LookupMap, UnorderedMap};
 
 use orderbook::{new_sequence_gen, TradeSequence};
 
 use crate::{Request, RequestId, Token, Vote};
-use crate::request::RequestStatus;
+use crate::events::{Event, RequestStatusChanged};
+use crate::governance::VotingTerms;
+use crate::request::{Listing, RequestKind, RequestStatus, VoteOption};
+use crate::token::TokenMetadata;
+use crate::vesting::VestingTerms;
 
 /// Metadata on the individual token level.
 #[derive(BorshDeserialize, BorshSerialize)]
 pub struct BallotHandler {
     pub requests: UnorderedMap<RequestId, Request>,
-    pub votes: LookupMap<RequestId, UnorderedSet<Vote>>,
+    /// голоса по заявке, у каждой заявки свой префикс
+    pub votes: LookupMap<RequestId, UnorderedMap<AccountId, Vote>>,
     seq: TradeSequence,
 }
 
@@ -22,13 +27,43 @@ pub struct StakeInfo {
     pub created_time: Timestamp
 }
 
+/// Итог голосования по заявке: вес голосов каждого варианта
+#[derive(Default, Debug, PartialEq)]
+pub struct VoteTally {
+    pub yes: Balance,
+    pub no: Balance,
+    pub abstain: Balance,
+}
+
+impl VoteTally {
+    /// Вес всех поданных голосов, идет в кворум
+    pub fn voted(&self) -> Balance {
+        self.yes + self.no + self.abstain
+    }
+
+    /// Вес голосов "за" и "против", от него считается одобрение
+    pub fn decided(&self) -> Balance {
+        self.yes + self.no
+    }
+
+    pub fn add(&mut self, result: VoteOption, weight: Balance) {
+        match result {
+            VoteOption::Yes => self.yes += weight,
+            VoteOption::No => self.no += weight,
+            VoteOption::Abstain => self.abstain += weight,
+        }
+    }
+}
+
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
 pub struct LaunchPad {
     pub price: Balance,
     /// продано
     pub sell_supply: u128,
     pub launched_time: Timestamp,
-    pub token: Token
+    pub token: Token,
+    /// вестинг непроданных токенов эмитента после завершения
+    pub vesting: Option<VestingTerms>,
 }
 
 
@@ -40,6 +75,10 @@ pub struct UserRequest {
 
     pub price: Balance,
     pub supply: Balance,
+    /// максимальный выпуск токена после листинга
+    pub max_supply: Option<Balance>,
+    /// метаданные токена, проверяются при запуске лаунчпада
+    pub meta: TokenMetadata,
 
     // полный текст заявки
     pub hash: String,
@@ -47,31 +86,77 @@ pub struct UserRequest {
 
 impl BallotHandler {
     pub fn new() -> Self {
-        let handler = BallotHandler {
+        BallotHandler {
             requests: UnorderedMap::new(b"req".to_vec()),
             votes: LookupMap::new(b"v".to_vec()),
             seq: new_sequence_gen(0, 1000000),
-        };
-
-        handler
+        }
     }
 
-    pub fn add_new_request(&mut self, request: UserRequest) {
+    pub fn add_new_request(&mut self, request: UserRequest, terms: VotingTerms) -> RequestId {
         let request = Request {
             id: self.seq.next_id(),
             owner_id: env::predecessor_account_id(),
             status: RequestStatus::PENDING,
 
-            token_id: request.token_id,
             title: request.title,
             description: request.description,
-            
-            price: request.price,
-            supply: request.supply,
+            kind: RequestKind::Listing(Listing {
+                token_id: request.token_id,
+                price: request.price,
+                supply: request.supply,
+                max_supply: request.max_supply,
+                meta: request.meta,
+                vesting: None,
+            }),
+
             hash: request.hash,
             created_time: env::block_timestamp(),
+            terms,
+            approved_time: None,
         };
+        let request_id = request.id;
+        self.insert_new_request(request);
+        request_id
+    }
+
+    /// Заявка любого вида, кроме листинга
+    pub fn add_proposal(&mut self, title: String, description: String, kind: RequestKind, terms: VotingTerms) -> RequestId {
+        let request = Request {
+            id: self.seq.next_id(),
+            owner_id: env::predecessor_account_id(),
+            status: RequestStatus::PENDING,
+
+            title,
+            description,
+            kind,
+
+            hash: String::new(),
+            created_time: env::block_timestamp(),
+            terms,
+            approved_time: None,
+        };
+        let request_id = request.id;
+        self.insert_new_request(request);
+        request_id
+    }
+
+    fn insert_new_request(&mut self, request: Request) {
         self.requests.insert(&request.id, &request);
+        // коллекция голосов заводится вместе с заявкой, голосующие платят только за свой голос
+        self.votes.insert(&request.id, &self.get_votes(&request.id));
+        Event::RequestStatus(vec![RequestStatusChanged {
+            request_id: request.id,
+            status: request.status,
+        }]).emit();
+    }
+
+    /// Одобренная, но еще не примененная заявка на обновление до кода с хешем `code_hash`
+    pub fn find_approved_upgrade(&self, code_hash: &[u8]) -> Option<Request> {
+        self.requests.values().find(|request| {
+            request.status == RequestStatus::APPROVED
+                && matches!(&request.kind, RequestKind::Upgrade { code_hash: hash } if hash.0 == code_hash)
+        })
     }
 
     pub fn get_all_requests(&self) -> Vec<Request> {
@@ -86,51 +171,94 @@ impl BallotHandler {
         self.requests.get(&request_id)
     }
 
-    fn get_votes(&self, request_id: &RequestId) -> UnorderedSet<Vote>{
-        self.votes.get(request_id).unwrap_or(UnorderedSet::new(b"vot".to_vec()))
+    fn get_votes(&self, request_id: &RequestId) -> UnorderedMap<AccountId, Vote> {
+        self.votes.get(request_id).unwrap_or_else(|| {
+            let mut prefix = b"vm".to_vec();
+            prefix.extend(&request_id.to_le_bytes());
+            UnorderedMap::new(prefix)
+        })
     }
 
     pub fn get_all_votes(&self, request_id: RequestId) -> Vec<Vote>{
-        self.get_votes(&request_id).to_vec()
+        self.get_votes(&request_id).values().collect()
     }
 
     pub fn is_vote(&self, request_id: RequestId, voter_id: AccountId) -> bool{
-        let votes = self.get_votes(&request_id);
-
-        votes.contains(&Vote{
-            owner_id: voter_id.clone(),
-            result: true
-        }) || votes.contains(&Vote{
-            owner_id: voter_id,
-            result: false
-        })
+        self.get_votes(&request_id).get(&voter_id).is_some()
     }
 
-    pub fn vote(&mut self, request_id: RequestId, vote: bool){
-        let mut votes = self.get_votes(&request_id);
-
-        votes.insert(&Vote{
+    /// Засчитывает голос аккаунта с весом `weight` - его стейком и делегированным ему на момент голосования.
+    /// Повторный голос заменяет прежний.
+    pub fn vote(&mut self, request_id: RequestId, vote: VoteOption, weight: Balance){
+        self.insert_vote(request_id, Vote{
             owner_id: env::predecessor_account_id(),
-            result: vote
+            result: vote,
+            weight,
         });
+    }
+
+    /// Убирает голос `voter_id`, например чтобы вернуть ему место после завершения
+    pub fn remove_vote(&mut self, request_id: RequestId, voter_id: &AccountId) {
+        let mut votes = self.get_votes(&request_id);
+        if votes.remove(voter_id).is_some() {
+            self.votes.insert(&request_id, &votes);
+        }
+    }
 
+    pub fn insert_vote(&mut self, request_id: RequestId, vote: Vote) {
+        let mut votes = self.get_votes(&request_id);
+        votes.insert(&vote.owner_id, &vote);
         self.votes.insert(&request_id, &votes);
     }
 
+    /// Итог голосования, `weight` - полный вес голоса, например с делегированным стейком
+    pub fn tally<F: Fn(&Vote) -> Balance>(&self, request_id: RequestId, weight: F) -> VoteTally {
+        let mut tally = VoteTally::default();
+        for vote in self.get_votes(&request_id).values() {
+            tally.add(vote.result, weight(&vote));
+        }
+        tally
+    }
+
+    fn clear_votes(&mut self, request_id: &RequestId) {
+        if let Some(mut votes) = self.votes.remove(request_id) {
+            votes.clear();
+        }
+    }
+
     pub fn reject_request(&mut self, request_id: RequestId){
         self.update_request_status(&request_id, RequestStatus::REJECTED);
-        self.votes.remove(&request_id);
+        self.clear_votes(&request_id);
     }
 
-    pub fn approve_request(&mut self, request_id: RequestId){
+    pub fn approve_request(&mut self, request_id: RequestId, vesting: Option<VestingTerms>){
+        let mut request = self.requests.get(&request_id).unwrap();
+        if let RequestKind::Listing(listing) = &mut request.kind {
+            listing.vesting = vesting;
+        }
+        request.approved_time = Some(env::block_timestamp());
+        self.requests.insert(&request_id, &request);
         self.update_request_status(&request_id, RequestStatus::APPROVED);
-        self.votes.remove(&request_id);
+        self.clear_votes(&request_id);
+    }
+
+    pub fn fail_request(&mut self, request_id: RequestId){
+        self.update_request_status(&request_id, RequestStatus::EXECUTION_FAILED);
+        self.clear_votes(&request_id);
+    }
+
+    pub fn launch_request(&mut self, request_id: RequestId){
+        self.update_request_status(&request_id, RequestStatus::LAUNCHED);
     }
 
     fn update_request_status(&mut self, request_id: &RequestId, new_status: RequestStatus) {
-        let mut request = self.requests.get(&request_id).unwrap();
-        request.status = new_status;
-        self.requests.insert(&request_id, &request);
+        let mut request = self.requests.get(request_id).unwrap();
+        request.status = new_status.clone();
+        self.requests.insert(request_id, &request);
+        Event::RequestStatus(vec![RequestStatusChanged {
+            request_id: *request_id,
+            status: new_status,
+        }]).emit();
     }
 }
 
@@ -138,9 +266,12 @@ impl BallotHandler {
 #[cfg(test)]
 mod tests {
     use near_sdk::{AccountId, Gas, testing_env, VMContext, MockedBlockchain};
-    use crate::ballot::UserRequest;
+    use crate::ballot::{UserRequest, VoteTally};
     use crate::{BallotHandler, Request, Vote};
-    use crate::request::RequestStatus;
+    use crate::NANOSEC_IN_DAY;
+    use crate::governance::VotingTerms;
+    use crate::request::{Listing, RequestKind, RequestStatus, VoteOption};
+    use crate::token::TokenMetadata;
 
     fn alice() -> AccountId {
         "alice.near".to_string()
@@ -162,20 +293,33 @@ mod tests {
             description: "ПРИВЕТИКИ".to_string(),
             price: 23,
             supply: 100000,
+            max_supply: None,
+            meta: TokenMetadata::new("XDHO", "XDHO", 2),
             hash: "сосисочка".to_string()
         }
     }
 
+    fn test_terms() -> VotingTerms {
+        VotingTerms { voting_end: NANOSEC_IN_DAY, quorum_percent: 33, approval_threshold: 50 }
+    }
+
     fn test_request() -> Request {
         Request{
             id: 0,
-            token_id: "XDHO".to_string(),
             title: "Тестовое название".to_string(),
             description: "ПРИВЕТИКИ".to_string(),
-            price: 23,
-            supply: 100000,
+            kind: RequestKind::Listing(Listing {
+                token_id: "XDHO".to_string(),
+                price: 23,
+                supply: 100000,
+                max_supply: None,
+                meta: TokenMetadata::new("XDHO", "XDHO", 2),
+                vesting: None,
+            }),
             status: RequestStatus::PENDING,
             created_time: 0,
+            terms: test_terms(),
+            approved_time: None,
             owner_id: bob(),
             hash: "сосисочка".to_string(),
         }
@@ -208,7 +352,7 @@ mod tests {
 
     fn get_handler_with_requests() -> BallotHandler{
         let mut handler = BallotHandler::new();
-        handler.add_new_request(test_user_request());
+        handler.add_new_request(test_user_request(), test_terms());
         handler
     }
 
@@ -226,20 +370,46 @@ mod tests {
 
         assert_eq!(handler.is_vote(0, bob()), false);
 
-        handler.vote(0, true);
-        assert_eq!( handler.get_all_votes(0)[0], Vote{ owner_id: bob(), result: true });
+        handler.vote(0, VoteOption::Yes, 10);
+        assert_eq!( handler.get_all_votes(0)[0], Vote{ owner_id: bob(), result: VoteOption::Yes, weight: 10 });
         assert_eq!(handler.is_vote(0, bob()), true);
 
-        handler.vote(0, false);
-        assert_eq!( handler.get_all_votes(0)[0], Vote{ owner_id: bob(), result: true });
+        // повторный голос заменяет прежний
+        handler.vote(0, VoteOption::No, 20);
+        assert_eq!( handler.get_all_votes(0)[0].result, VoteOption::No);
+        assert_eq!( handler.get_all_votes(0)[0].weight, 20);
+        assert_eq!( handler.get_all_votes(0).len(), 1);
         assert_eq!(handler.is_vote(0, bob()), true);
     }
 
+    #[test]
+    fn test_tally(){
+        testing_env!(get_context(bob()));
+        let mut handler = get_handler_with_requests();
+        handler.add_new_request(test_user_request(), test_terms());
+        handler.vote(0, VoteOption::Yes, 10);
+        testing_env!(get_context(carol()));
+        handler.vote(0, VoteOption::No, 30);
+        testing_env!(get_context(ivan()));
+        handler.vote(0, VoteOption::Yes, 5);
+        testing_env!(get_context(alice()));
+        handler.vote(0, VoteOption::Abstain, 7);
+
+        let tally = handler.tally(0, |vote| vote.weight);
+        assert_eq!(tally, VoteTally { yes: 15, no: 30, abstain: 7 });
+        assert_eq!(tally.voted(), 52);
+        assert_eq!(tally.decided(), 45);
+        assert_eq!(handler.tally(0, |vote| vote.weight * 2).voted(), 104);
+        // голоса разных заявок не смешиваются
+        assert_eq!(handler.is_vote(1, bob()), false);
+        assert_eq!(handler.tally(1, |vote| vote.weight), VoteTally::default());
+    }
+
     #[test]
     fn test_reject_request(){
         testing_env!(get_context(bob()));
         let mut handler = get_handler_with_requests();
-        handler.vote(0, true);
+        handler.vote(0, VoteOption::Yes, 10);
 
         assert_eq!(handler.get_request(0).unwrap().status, RequestStatus::PENDING);
         handler.reject_request(0);
@@ -251,10 +421,10 @@ mod tests {
     fn test_approve_request(){
         testing_env!(get_context(bob()));
         let mut handler = get_handler_with_requests();
-        handler.vote(0, true);
+        handler.vote(0, VoteOption::Yes, 10);
 
         assert_eq!(handler.get_request(0).unwrap().status, RequestStatus::PENDING);
-        handler.approve_request(0);
+        handler.approve_request(0, None);
         assert_eq!(handler.get_request(0).unwrap().status, RequestStatus::APPROVED);
         assert_eq!( handler.get_all_votes(0).len(), 0);
     }
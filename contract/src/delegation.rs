@@ -0,0 +1,126 @@
+use near_sdk::{AccountId, Balance, env};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, UnorderedSet};
+
+/// Сколько звеньев может быть в цепочке делегирования. Заодно ограничивает обход цепочки
+/// при изменении стейка и подсчете голосов.
+pub const MAX_DELEGATION_DEPTH: usize = 3;
+
+/// Граф делегирования голосов: кто кому передал голос, обратные ребра и накопленный
+/// делегированный стейк. Стейк пересчитывается по цепочке при каждом изменении, а не обходом делегаторов.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Delegations {
+    /// делегат каждого аккаунта
+    delegates: LookupMap<AccountId, AccountId>,
+    /// прямые делегаторы каждого делегата
+    delegators: LookupMap<AccountId, UnorderedSet<AccountId>>,
+    /// стейк всех делегаторов аккаунта, прямых и по цепочке
+    delegated_stake: LookupMap<AccountId, Balance>,
+}
+
+impl Delegations {
+    pub fn new() -> Self {
+        Self {
+            delegates: LookupMap::new(b"dg".to_vec()),
+            delegators: LookupMap::new(b"dl".to_vec()),
+            delegated_stake: LookupMap::new(b"ds".to_vec()),
+        }
+    }
+
+    pub fn get_delegate(&self, account_id: &AccountId) -> Option<AccountId> {
+        self.delegates.get(account_id)
+    }
+
+    pub fn get_delegators(&self, account_id: &AccountId) -> Vec<AccountId> {
+        self.get_delegator_set(account_id).to_vec()
+    }
+
+    /// Делегаты `account_id` по цепочке: сначала прямой, последним - тот, кто никому не делегировал
+    pub fn get_chain(&self, account_id: &AccountId) -> Vec<AccountId> {
+        let mut chain = Vec::new();
+        let mut current = account_id.clone();
+        while let Some(delegate_id) = self.delegates.get(&current) {
+            chain.push(delegate_id.clone());
+            current = delegate_id;
+        }
+        chain
+    }
+
+    pub fn get_delegated_stake(&self, account_id: &AccountId) -> Balance {
+        self.delegated_stake.get(account_id).unwrap_or(0)
+    }
+
+    /// Стейк `account_id` вырос на `amount`, его делегаты по цепочке получают столько же
+    pub fn add_stake(&mut self, account_id: &AccountId, amount: Balance) {
+        for delegate_id in self.get_chain(account_id) {
+            let delegated = self.get_delegated_stake(&delegate_id) + amount;
+            self.delegated_stake.insert(&delegate_id, &delegated);
+        }
+    }
+
+    /// Стейк `account_id` уменьшился на `amount`
+    pub fn remove_stake(&mut self, account_id: &AccountId, amount: Balance) {
+        for delegate_id in self.get_chain(account_id) {
+            match self.get_delegated_stake(&delegate_id) - amount {
+                0 => self.delegated_stake.remove(&delegate_id),
+                delegated => self.delegated_stake.insert(&delegate_id, &delegated),
+            };
+        }
+    }
+
+    /// Передает голос `delegator_id` со стейком `staked` аккаунту `delegate_id`,
+    /// прежний делегат теряет этот голос. Возвращает прежнего делегата.
+    pub fn delegate(&mut self, delegator_id: &AccountId, delegate_id: &AccountId, staked: Balance) -> Option<AccountId> {
+        if delegator_id == delegate_id {
+            env::panic(b"Can't delegate votes to yourself");
+        }
+        let upstream = self.get_chain(delegate_id);
+        if upstream.contains(delegator_id) {
+            env::panic(b"Delegation would create a cycle");
+        }
+        // цепочка от самых дальних делегаторов `delegator_id` до конца цепочки `delegate_id`
+        if self.depth(delegator_id) + 1 + upstream.len() > MAX_DELEGATION_DEPTH {
+            env::panic(b"Delegation chain is too long");
+        }
+
+        let old_delegate_id = self.undelegate(delegator_id, staked);
+        self.delegates.insert(delegator_id, delegate_id);
+        let mut delegators = self.get_delegator_set(delegate_id);
+        delegators.insert(delegator_id);
+        self.delegators.insert(delegate_id, &delegators);
+        self.add_stake(delegator_id, staked + self.get_delegated_stake(delegator_id));
+        old_delegate_id
+    }
+
+    /// Возвращает голос `delegator_id` со стейком `staked` ему самому. Возвращает прежнего делегата.
+    pub fn undelegate(&mut self, delegator_id: &AccountId, staked: Balance) -> Option<AccountId> {
+        self.delegates.get(delegator_id)?;
+        self.remove_stake(delegator_id, staked + self.get_delegated_stake(delegator_id));
+        let delegate_id = self.delegates.remove(delegator_id)?;
+        let mut delegators = self.get_delegator_set(&delegate_id);
+        delegators.remove(delegator_id);
+        if delegators.is_empty() {
+            self.delegators.remove(&delegate_id);
+        } else {
+            self.delegators.insert(&delegate_id, &delegators);
+        }
+        Some(delegate_id)
+    }
+
+    /// Длина самой длинной цепочки делегаторов, которая заканчивается на `account_id`
+    fn depth(&self, account_id: &AccountId) -> usize {
+        self.get_delegator_set(account_id)
+            .iter()
+            .map(|delegator_id| self.depth(&delegator_id) + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn get_delegator_set(&self, account_id: &AccountId) -> UnorderedSet<AccountId> {
+        self.delegators.get(account_id).unwrap_or_else(|| {
+            let mut prefix = b"de".to_vec();
+            prefix.extend(env::sha256(account_id.as_bytes()));
+            UnorderedSet::new(prefix)
+        })
+    }
+}
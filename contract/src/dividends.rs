@@ -0,0 +1,34 @@
+use near_sdk::{AccountId, Balance, env};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+
+use crate::token::TokenId;
+
+/// Точность накопленного дивиденда на один токен
+pub const DIVIDEND_PRECISION: u128 = 1_000_000_000_000;
+
+/// Дивиденды держателя по одному токену.
+/// Начисление идет через накопитель `dividend_per_share` токена: при каждом изменении
+/// баланса держателю досчитывается `balance * (dividend_per_share - per_share_paid)`.
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct DividendAccount {
+    /// значение накопителя на момент последнего расчета
+    pub per_share_paid: u128,
+    /// начислено, но еще не выведено (в стандартном токене)
+    pub unclaimed: Balance,
+}
+
+impl DividendAccount {
+    /// Досчитывает дивиденды на `balance` до текущего `per_share`
+    pub fn settle(&mut self, balance: Balance, per_share: u128) {
+        let earned = match balance.checked_mul(per_share - self.per_share_paid) {
+            Some(earned) => earned / DIVIDEND_PRECISION,
+            None => env::panic(b"Dividend overflow"),
+        };
+        self.unclaimed += earned;
+        self.per_share_paid = per_share;
+    }
+}
+
+pub fn dividend_account_key(owner_id: &AccountId, token_id: &TokenId) -> Vec<u8> {
+    env::sha256(format!("{}:{}", owner_id, token_id).as_bytes())
+}
@@ -0,0 +1,359 @@
+use near_sdk::{AccountId, Balance, env, Timestamp};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json;
+
+use orderbook::{OrderSide, OrderType};
+
+use crate::access_control::Role;
+use crate::fees::FeeRates;
+use crate::governance::GovernanceConfig;
+use crate::pause::{MarketStatus, Subsystem};
+use crate::request::{RequestId, RequestStatus, VoteOption};
+use crate::token::{TokenId, TokenMetadata};
+
+/// Стандарт и версия событий токенов: все токены биржи живут в одном контракте, как в мульти-токене
+pub const MT_STANDARD: &str = "nep245";
+pub const MT_VERSION: &str = "1.0.0";
+/// Стандарт и версия событий биржи
+pub const DEX_STANDARD: &str = "xdex";
+pub const DEX_VERSION: &str = "1.0.0";
+
+/// Событие в формате NEP-297
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    MtTransfer(Vec<MtTransfer>),
+    MtMint(Vec<MtMint>),
+    MtBurn(Vec<MtBurn>),
+    AllowanceSet(Vec<AllowanceSet>),
+    OrderAccepted(Vec<OrderAccepted>),
+    OrderFilled(Vec<OrderFilled>),
+    OrderCancelled(Vec<OrderCancelled>),
+    Stake(Vec<Stake>),
+    Unstake(Vec<Stake>),
+    StakingRewardsDeposit(Vec<Stake>),
+    Vote(Vec<VoteCast>),
+    RequestStatus(Vec<RequestStatusChanged>),
+    LaunchpadPurchase(Vec<LaunchpadPurchase>),
+    TokenMetadataUpdate(Vec<TokenMetadataUpdated>),
+    DividendsDeposit(Vec<DividendsDeposit>),
+    DividendsClaim(Vec<DividendsClaim>),
+    RoleGranted(Vec<RoleChanged>),
+    RoleRevoked(Vec<RoleChanged>),
+    OwnershipTransfer(Vec<OwnershipTransferred>),
+    PauseChange(Vec<PauseChanged>),
+    GovernanceConfigChange(Vec<GovernanceConfigChanged>),
+    FeeRatesChange(Vec<FeeRatesChanged>),
+    MarketStatusChange(Vec<MarketStatusChanged>),
+    TreasuryDeposit(Vec<TreasuryTransfer>),
+    TreasurySpend(Vec<TreasuryTransfer>),
+    Delegate(Vec<DelegationChanged>),
+    Undelegate(Vec<DelegationChanged>),
+    VoteCommit(Vec<VoteCommitted>),
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+struct EventLog<'a> {
+    standard: &'a str,
+    version: &'a str,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+impl Event {
+    fn standard(&self) -> (&'static str, &'static str) {
+        match self {
+            Event::MtTransfer(_) | Event::MtMint(_) | Event::MtBurn(_) => (MT_STANDARD, MT_VERSION),
+            _ => (DEX_STANDARD, DEX_VERSION),
+        }
+    }
+
+    /// Пишет событие в лог как `EVENT_JSON:{...}`
+    pub fn emit(&self) {
+        let (standard, version) = self.standard();
+        let log = EventLog {
+            standard,
+            version,
+            event: self,
+        };
+        env::log(format!("EVENT_JSON:{}", serde_json::to_string(&log).unwrap()).as_bytes());
+    }
+}
+
+/// Пишет `mt_mint` о зачислении `amount` новых токенов на `owner_id`
+pub fn emit_mt_mint(owner_id: &AccountId, token_id: &TokenId, amount: Balance) {
+    Event::MtMint(vec![MtMint {
+        owner_id: owner_id.clone(),
+        token_ids: vec![token_id.clone()],
+        amounts: vec![U128(amount)],
+        memo: None,
+    }]).emit();
+}
+
+/// `token_ids` и `amounts` идут парами
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MtTransfer {
+    pub old_owner_id: AccountId,
+    pub new_owner_id: AccountId,
+    pub token_ids: Vec<TokenId>,
+    pub amounts: Vec<U128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+impl MtTransfer {
+    /// Перевод одного токена
+    pub fn new(old_owner_id: &AccountId, new_owner_id: &AccountId, token_id: &TokenId, amount: Balance, memo: Option<String>) -> Self {
+        Self {
+            old_owner_id: old_owner_id.clone(),
+            new_owner_id: new_owner_id.clone(),
+            token_ids: vec![token_id.clone()],
+            amounts: vec![U128(amount)],
+            memo,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MtMint {
+    pub owner_id: AccountId,
+    pub token_ids: Vec<TokenId>,
+    pub amounts: Vec<U128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MtBurn {
+    pub owner_id: AccountId,
+    pub token_ids: Vec<TokenId>,
+    pub amounts: Vec<U128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AllowanceSet {
+    pub owner_id: AccountId,
+    pub escrow_account_id: AccountId,
+    pub token_id: TokenId,
+    pub allowance: U128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<Timestamp>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OrderAccepted {
+    pub order_id: u64,
+    pub token_id: TokenId,
+    pub order_type: OrderType,
+    pub order_creator: AccountId,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OrderFilled {
+    pub order_id: u64,
+    pub token_id: TokenId,
+    pub side: OrderSide,
+    pub order_type: OrderType,
+    pub price: f64,
+    pub qty: U128,
+    pub order_creator: AccountId,
+    /// ордер исполнен не полностью
+    pub partial: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OrderCancelled {
+    pub order_id: u64,
+    pub token_id: TokenId,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Stake {
+    pub account_id: AccountId,
+    pub amount: U128,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VoteCast {
+    pub request_id: RequestId,
+    pub voter_id: AccountId,
+    pub vote: VoteOption,
+    pub weight: U128,
+}
+
+/// Скрытый голос, вариант станет известен при раскрытии
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VoteCommitted {
+    pub request_id: RequestId,
+    pub voter_id: AccountId,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RequestStatusChanged {
+    pub request_id: RequestId,
+    pub status: RequestStatus,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LaunchpadPurchase {
+    pub token_id: TokenId,
+    pub buyer_id: AccountId,
+    pub amount: U128,
+    pub price: U128,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenMetadataUpdated {
+    pub token_id: TokenId,
+    pub updated_by: AccountId,
+    pub meta: TokenMetadata,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DividendsDeposit {
+    pub token_id: TokenId,
+    pub amount: U128,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DividendsClaim {
+    pub account_id: AccountId,
+    pub token_id: TokenId,
+    pub amount: U128,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RoleChanged {
+    pub role: Role,
+    pub account_id: AccountId,
+    pub changed_by: AccountId,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OwnershipTransferred {
+    pub old_owner_id: AccountId,
+    pub new_owner_id: AccountId,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PauseChanged {
+    pub subsystem: Subsystem,
+    pub paused: bool,
+    pub changed_by: AccountId,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GovernanceConfigChanged {
+    pub request_id: RequestId,
+    pub config: GovernanceConfig,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeRatesChanged {
+    pub fees: FeeRates,
+    pub changed_by: AccountId,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MarketStatusChanged {
+    pub token_id: TokenId,
+    /// `None` - торги возобновлены
+    pub status: Option<MarketStatus>,
+}
+
+/// Поступление в казну от `account_id` или выплата из казны `account_id`
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TreasuryTransfer {
+    pub account_id: AccountId,
+    pub token_id: TokenId,
+    pub amount: U128,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DelegationChanged {
+    pub delegator_id: AccountId,
+    pub delegate_id: AccountId,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use near_sdk::{MockedBlockchain, testing_env, VMContext};
+    use near_sdk::test_utils::get_logs;
+
+    use crate::events::{Event, MtTransfer, RequestStatusChanged};
+    use crate::request::RequestStatus;
+
+    fn get_context() -> VMContext {
+        VMContext {
+            current_account_id: "alice.near".to_string(),
+            signer_account_id: "bob.near".to_string(),
+            signer_account_pk: vec![0, 1, 2],
+            predecessor_account_id: "bob.near".to_string(),
+            input: vec![],
+            block_index: 0,
+            block_timestamp: 0,
+            account_balance: 100,
+            account_locked_balance: 0,
+            storage_usage: 10u64.pow(6),
+            attached_deposit: 0,
+            prepaid_gas: 10u64.pow(18),
+            random_seed: vec![0, 1, 2],
+            is_view: false,
+            output_data_receivers: vec![],
+            epoch_height: 0,
+        }
+    }
+
+    #[test]
+    fn mt_transfer_log() {
+        testing_env!(get_context());
+        Event::MtTransfer(vec![MtTransfer::new(&"bob.near".to_string(), &"carol.near".to_string(), &"XDHO".to_string(), 100, None)]).emit();
+        assert_eq!(
+            get_logs(),
+            vec![r#"EVENT_JSON:{"standard":"nep245","version":"1.0.0","event":"mt_transfer","data":[{"old_owner_id":"bob.near","new_owner_id":"carol.near","token_ids":["XDHO"],"amounts":["100"]}]}"#]
+        );
+    }
+
+    #[test]
+    fn request_status_log() {
+        testing_env!(get_context());
+        Event::RequestStatus(vec![RequestStatusChanged {
+            request_id: 3,
+            status: RequestStatus::APPROVED,
+        }]).emit();
+        assert_eq!(
+            get_logs(),
+            vec![r#"EVENT_JSON:{"standard":"xdex","version":"1.0.0","event":"request_status","data":[{"request_id":3,"status":"APPROVED"}]}"#]
+        );
+    }
+}
@@ -0,0 +1,34 @@
+use near_sdk::{Balance, env};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+
+/// Знаменатель ставок комиссий: ставки задаются в базисных пунктах
+pub const FEE_DENOMINATOR: u128 = 10_000;
+/// Ставка комиссии не может быть больше 10%
+pub const MAX_FEE_BPS: u32 = 1_000;
+
+/// Комиссии биржи в базисных пунктах, уходят в казну
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeRates {
+    /// с объема рыночной заявки в стандартном токене, платит тейкер
+    pub trading_fee_bps: u32,
+    /// с оплаты покупки на лаунчпаде, удерживается из выручки эмитента
+    pub launchpad_fee_bps: u32,
+}
+
+impl FeeRates {
+    pub fn assert_valid(&self) {
+        if self.trading_fee_bps > MAX_FEE_BPS || self.launchpad_fee_bps > MAX_FEE_BPS {
+            env::panic(b"Fee rate can't be more than 10%");
+        }
+    }
+
+    pub fn trading_fee(&self, volume: Balance) -> Balance {
+        volume * self.trading_fee_bps as u128 / FEE_DENOMINATOR
+    }
+
+    pub fn launchpad_fee(&self, payment: Balance) -> Balance {
+        payment * self.launchpad_fee_bps as u128 / FEE_DENOMINATOR
+    }
+}
@@ -0,0 +1,44 @@
+use near_sdk::{ext_contract, Gas};
+use near_sdk::json_types::U128;
+
+use crate::token::TokenId;
+
+pub const GAS_FOR_RESOLVE_TRANSFER: Gas = 5_000_000_000_000;
+pub const GAS_FOR_FT_TRANSFER_CALL: Gas = 25_000_000_000_000 + GAS_FOR_RESOLVE_TRANSFER;
+pub const GAS_FOR_FT_TRANSFER: Gas = 10_000_000_000_000;
+pub const NO_DEPOSIT: u128 = 0;
+
+/// Интерфейс внешнего NEP-141 токена
+#[ext_contract(ext_fungible_token)]
+pub trait FungibleToken {
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+}
+
+/// Интерфейс контракта-получателя для `ft_transfer_call`.
+/// Так как биржа хранит много токенов, в колбэк дополнительно передается `token_id` (как в NEP-245).
+#[ext_contract(ext_ft_receiver)]
+pub trait FungibleTokenReceiver {
+    /// Возвращает количество неиспользованных токенов, которые нужно вернуть отправителю.
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        token_id: TokenId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128>;
+}
+
+#[ext_contract(ext_self)]
+pub trait FungibleTokenResolver {
+    fn ft_resolve_transfer(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        amount: U128,
+    ) -> U128;
+
+    fn ft_resolve_withdraw(&mut self, account_id: AccountId, token_id: TokenId, amount: U128);
+
+    fn near_resolve_withdraw(&mut self, account_id: AccountId, amount: U128);
+}
@@ -0,0 +1,91 @@
+use near_sdk::{env, Timestamp};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+
+use crate::NANOSEC_IN_DAY;
+use crate::request::{ProposalType, RequestKind};
+
+/// Параметры голосования по заявкам. Меняются только через одобренную заявку.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GovernanceConfig {
+    /// сколько длится голосование от создания заявки
+    pub voting_period: u64,
+    /// какой процент всего стейка должен проголосовать
+    pub quorum_percent: u32,
+    /// заявка одобрена, если вес голосов "за" больше этого процента от проголосовавших
+    pub approval_threshold: u32,
+    /// залог в XDHO за подачу заявки
+    pub proposal_deposit: U128,
+    /// минимальный стейк, чтобы голосовать
+    pub min_stake: U128,
+    /// виды заявок с тайным голосованием: сначала подается хеш голоса, после голосования он раскрывается
+    pub secret_ballot: Vec<ProposalType>,
+    /// сколько длится раскрытие тайных голосов после окончания голосования
+    pub reveal_period: u64,
+}
+
+impl Default for GovernanceConfig {
+    /// Сутки на открытое голосование, кворум в треть стейка и простое большинство
+    fn default() -> Self {
+        Self {
+            voting_period: NANOSEC_IN_DAY,
+            quorum_percent: 33,
+            approval_threshold: 50,
+            proposal_deposit: U128(0),
+            min_stake: U128(0),
+            secret_ballot: vec![],
+            reveal_period: NANOSEC_IN_DAY,
+        }
+    }
+}
+
+impl GovernanceConfig {
+    pub fn assert_valid(&self) {
+        if self.voting_period == 0 {
+            env::panic(b"Voting period must be positive");
+        }
+        if self.quorum_percent > 100 || self.approval_threshold >= 100 {
+            env::panic(b"Quorum must be at most 100% and approval threshold below 100%");
+        }
+        if !self.secret_ballot.is_empty() && self.reveal_period == 0 {
+            env::panic(b"Reveal period must be positive for secret ballots");
+        }
+    }
+
+    /// Голосуют ли по заявке такого вида тайно
+    pub fn is_secret(&self, kind: &RequestKind) -> bool {
+        self.secret_ballot.contains(&kind.proposal_type())
+    }
+
+    /// Условия голосования по заявке, поданной сейчас
+    pub fn voting_terms(&self) -> VotingTerms {
+        VotingTerms {
+            voting_end: env::block_timestamp() + self.voting_period,
+            quorum_percent: self.quorum_percent,
+            approval_threshold: self.approval_threshold,
+        }
+    }
+}
+
+/// Условия голосования, зафиксированные в заявке при подаче: смена параметров их не меняет
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VotingTerms {
+    pub voting_end: Timestamp,
+    pub quorum_percent: u32,
+    pub approval_threshold: u32,
+}
+
+impl VotingTerms {
+    /// Набран ли кворум, если проголосовало `voted` из `total_staked`
+    pub fn has_quorum(&self, voted: u128, total_staked: u128) -> bool {
+        voted * 100 >= total_staked * self.quorum_percent as u128
+    }
+
+    /// Одобрена ли заявка, если `positive` из `voted` проголосовали "за"
+    pub fn is_approved(&self, positive: u128, voted: u128) -> bool {
+        positive * 100 > voted * self.approval_threshold as u128
+    }
+}
@@ -1,28 +1,57 @@
 extern crate core;
 
 
+use std::collections::HashMap;
 use std::vec;
 
-use near_sdk::{AccountId, Balance, env, near_bindgen, Promise, Timestamp};
+use near_sdk::{AccountId, assert_one_yocto, Balance, env, ext_contract, Gas, near_bindgen, Promise, PromiseOrValue, PromiseResult, StorageUsage, Timestamp};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LookupMap, UnorderedMap};
-use near_sdk::json_types::U128;
+use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet, Vector};
+use near_sdk::json_types::{Base64VecU8, U128};
 use num_traits::cast::ToPrimitive;
 
-use orderbook::{Failed, Order, Orderbook, orders, OrderSide, OrderType, Success};
+use orderbook::{Failed, Order, OrderIndex, Orderbook, orders, OrderSide, OrderType, Success};
 
-use crate::account::TokenAccount;
+use crate::access_control::{AccessControl, Role};
+use crate::account::{Allowance, AllowanceView, TokenAccount};
+use crate::airdrop::{Airdrop, AirdropId, AirdropView, GAS_PER_AIRDROP_TRANSFER, MAX_BATCH_SIZE};
 use crate::ballot::{BallotHandler, LaunchPad, StakeInfo, UserRequest};
-use crate::request::{Request, RequestId, Vote};
+use crate::delegation::Delegations;
+use crate::dividends::{DIVIDEND_PRECISION, DividendAccount, dividend_account_key};
+use crate::events::{AllowanceSet, DelegationChanged, DividendsClaim, DividendsDeposit, emit_mt_mint, Event, FeeRatesChanged, GovernanceConfigChanged, LaunchpadPurchase, MarketStatusChanged, MtBurn, MtTransfer, OrderAccepted, OrderCancelled, OrderFilled, PauseChanged, Stake as StakeEvent, TokenMetadataUpdated, TreasuryTransfer, VoteCast, VoteCommitted};
+use crate::fees::FeeRates;
+use crate::fungible_token::{ext_fungible_token, ext_ft_receiver, ext_self, GAS_FOR_FT_TRANSFER, GAS_FOR_FT_TRANSFER_CALL, GAS_FOR_RESOLVE_TRANSFER, NO_DEPOSIT};
+use crate::governance::GovernanceConfig;
+use crate::migration::{read_state_version, VersionedContract, write_state_version};
+use crate::pause::{MarketStatus, PauseFlags, Subsystem};
+use crate::request::{Request, RequestId, RequestKind, Vote, VoteOption};
 use crate::request::RequestStatus;
-use crate::token::{Token, TokenId, TokenMetadata};
+use crate::secret_ballot::{SecretBallots, VoteCommit};
+use crate::snapshot::{Snapshot, snapshot_balance_key, SnapshotId, SnapshotView};
+use crate::storage::{MIN_STORAGE_BYTES, StorageAccount, StorageBalance, StorageBalanceBounds};
+use crate::token::{Token, TokenId, TokenMetadata, TokenMetadataUpdate, TokenOrigin};
+use crate::vesting::{VestingSchedule, VestingTerms, VestingView};
 use crate::wallet::TokenWallet;
 
+mod access_control;
 mod account;
+mod airdrop;
+mod migration;
+mod pause;
 mod request;
+mod secret_ballot;
+mod snapshot;
 
 mod ballot;
+mod delegation;
+mod dividends;
+mod events;
+mod fees;
+mod fungible_token;
+mod governance;
+mod storage;
 mod token;
+mod vesting;
 mod wallet;
 
 #[global_allocator]
@@ -30,13 +59,24 @@ static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 const NANOSEC_IN_DAY: u64 = 86_400_000_000_000;
 const NANOSEC_IN_DAY_F64: f64 = 86_400_000_000_000.0;
 const PERCENT_STAKING_PER_YEAR: f64 = 0.20;
-const YOKTO_NEAR: u128 = 1_000_000_000_000_000_000_000_000;
 const STAKING_PERCENT: f64 = PERCENT_STAKING_PER_YEAR / 365.0 / NANOSEC_IN_DAY_F64;
-/// за наносекунду
-const VOTING_TIME: u64 = NANOSEC_IN_DAY;
+/// сколько после одобрения заявки ждать, прежде чем развернуть новый код
+const UPGRADE_TIMELOCK: u64 = 2 * NANOSEC_IN_DAY;
+/// сколько заявок один аккаунт может держать на голосовании одновременно
+const MAX_OPEN_REQUESTS: u32 = 3;
+const GAS_FOR_MIGRATE: Gas = 100_000_000_000_000;
+const GAS_FOR_RESOLVE_UPGRADE: Gas = 5_000_000_000_000;
+/// как часто эмитент может менять метаданные токена
+const METADATA_UPDATE_COOLDOWN: u64 = NANOSEC_IN_DAY;
 //const SINGLE_CALL_GAS: u64 = 20_000_000_000_000; // 2 x 10^14
 //const TRANSFER_FROM_NEAR_COST: u128 = 36_500_000_000_000_000_000_000; // 365 x 10^20
 
+/// Колбэк после развертывания нового кода, вызывается уже в нем
+#[ext_contract(ext_upgrade)]
+pub trait UpgradeResolver {
+    fn resolve_upgrade(&mut self, request_id: RequestId);
+}
+
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize)]
 pub struct Contract {
@@ -48,8 +88,57 @@ pub struct Contract {
     pub ballot_handler: BallotHandler,
     pub staking: UnorderedMap<Vec<u8>, StakeInfo>,
     pub launchpad: UnorderedMap<TokenId, LaunchPad>,
-    /// владелец контракта
-    owner_id: AccountId,
+    /// контракт внешнего NEP-141 токена -> его токен на бирже
+    pub bridged_tokens: LookupMap<AccountId, TokenId>,
+    /// sha256(AccountID) -> депозит за хранилище
+    pub storage_accounts: LookupMap<Vec<u8>, StorageAccount>,
+    /// время последнего изменения метаданных токена эмитентом
+    pub metadata_updated: LookupMap<TokenId, Timestamp>,
+    /// незавершенные раздачи токенов
+    pub airdrops: UnorderedMap<AirdropId, Airdrop>,
+    pub next_airdrop_id: AirdropId,
+    /// аккаунты с ненулевым балансом каждого токена
+    pub holders: LookupMap<TokenId, UnorderedSet<AccountId>>,
+    /// держатели, выбывшие из индекса после снимка токена, в порядке выбытия
+    pub holder_exits: LookupMap<TokenId, Vector<AccountId>>,
+    pub snapshots: Vector<Snapshot>,
+    /// последний снимок каждого токена
+    pub latest_snapshot: LookupMap<TokenId, SnapshotId>,
+    /// снимки каждого токена по возрастанию, у каждого токена свой префикс
+    pub token_snapshots: LookupMap<TokenId, Vector<SnapshotId>>,
+    /// snapshot_balance_key(SnapshotId, AccountId) -> баланс на момент снимка
+    pub snapshot_balances: LookupMap<Vec<u8>, Balance>,
+    /// накопленный дивиденд на один токен, умноженный на `DIVIDEND_PRECISION`
+    pub dividend_per_share: LookupMap<TokenId, u128>,
+    /// dividend_account_key(AccountId, TokenId) -> дивиденды держателя
+    pub dividend_accounts: LookupMap<Vec<u8>, DividendAccount>,
+    /// вестинг непроданных токенов эмитента для одобряемых заявок
+    pub launchpad_vesting: Option<VestingTerms>,
+    /// владелец контракта и роли
+    pub access: AccessControl,
+    /// какие подсистемы остановлены
+    pub paused: PauseFlags,
+    /// параметры голосования по заявкам
+    pub governance: GovernanceConfig,
+    pub fees: FeeRates,
+    /// средства биржи по токенам
+    pub treasury: UnorderedMap<TokenId, Balance>,
+    /// остановленные и снятые с торгов рынки
+    pub markets: LookupMap<TokenId, MarketStatus>,
+    /// залоги незавершенных заявок, лежат на счете контракта
+    pub proposal_deposits: LookupMap<RequestId, Balance>,
+    /// сколько заявок каждого автора еще на голосовании
+    pub open_requests: LookupMap<AccountId, u32>,
+    /// кто кому передал свой голос
+    pub delegations: Delegations,
+    /// нераскрытые голоса заявок с тайным голосованием
+    pub secret_ballots: SecretBallots,
+    /// XDHO на счете контракта, из которых платятся награды за стейкинг
+    pub staking_rewards: Balance,
+    /// по скольким незавершенным заявкам аккаунт голосовал, пока их больше нуля - стейк не снять
+    pub pending_votes: LookupMap<AccountId, u32>,
+    /// order_escrow_key(TokenId, OrderId) -> XDHO, которые бид из стакана еще держит на контракте
+    pub order_escrow: LookupMap<Vec<u8>, Balance>,
 }
 
 impl Default for Contract {
@@ -69,36 +158,127 @@ impl Contract {
             wallets: LookupMap::new(b"w".to_vec()),
             tokens: UnorderedMap::new(b"t".to_vec()),
             ballot_handler: BallotHandler::new(),
-            owner_id: owner_id.clone(),
             staking: UnorderedMap::new(b"s".to_vec()),
             launchpad: UnorderedMap::new(b"launch".to_vec()),
+            bridged_tokens: LookupMap::new(b"b".to_vec()),
+            storage_accounts: LookupMap::new(b"sa".to_vec()),
+            metadata_updated: LookupMap::new(b"mu".to_vec()),
+            airdrops: UnorderedMap::new(b"ad".to_vec()),
+            next_airdrop_id: 0,
+            holders: LookupMap::new(b"hd".to_vec()),
+            holder_exits: LookupMap::new(b"hx".to_vec()),
+            snapshots: Vector::new(b"sn".to_vec()),
+            latest_snapshot: LookupMap::new(b"ls".to_vec()),
+            token_snapshots: LookupMap::new(b"ts".to_vec()),
+            snapshot_balances: LookupMap::new(b"sb".to_vec()),
+            dividend_per_share: LookupMap::new(b"dps".to_vec()),
+            dividend_accounts: LookupMap::new(b"da".to_vec()),
+            launchpad_vesting: None,
+            access: AccessControl::new(owner_id.clone()),
+            paused: PauseFlags::default(),
+            governance: GovernanceConfig::default(),
+            fees: FeeRates::default(),
+            treasury: UnorderedMap::new(b"tr".to_vec()),
+            markets: LookupMap::new(b"ms".to_vec()),
+            proposal_deposits: LookupMap::new(b"pd".to_vec()),
+            open_requests: LookupMap::new(b"or".to_vec()),
+            delegations: Delegations::new(),
+            secret_ballots: SecretBallots::new(),
+            staking_rewards: 0,
+            pending_votes: LookupMap::new(b"pv".to_vec()),
+            order_escrow: LookupMap::new(b"oe".to_vec()),
         };
-        contract.add_token(Token {
+        contract.internal_add_token(Token {
             token_id: "XDHO".to_string(),
             owner_id: owner_id.clone(),
-            supply: 100_000_000_000,
-            meta: None,
+            total_supply: 100_000_000_000,
+            max_supply: None,
+            meta: TokenMetadata::new("XDHO", "XDHO", 2),
+            origin: TokenOrigin::Native,
         });
+        contract.register_token(&Token {
+            token_id: contract.get_near_token(),
+            owner_id: env::current_account_id(),
+            total_supply: 0,
+            max_supply: None,
+            meta: TokenMetadata::new("Wrapped NEAR", "wNEAR", 24),
+            origin: TokenOrigin::Near,
+        });
+        write_state_version();
+
+        contract
+    }
 
+    /// Поднимает сохраненное состояние до текущей версии, вызывается после обновления кода.
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Only the contract itself can migrate its state"
+        );
+        let contract = VersionedContract::read().into_current();
+        write_state_version();
         contract
     }
 
+    pub fn get_state_version(&self) -> u32 {
+        read_state_version()
+    }
+
     /// Устанавливает допустимое кол-во `allowance` для `escrow_account_id` которое он сможет
-    /// списывать с владельца этого аккаунта (`predecessor_id`)
+    /// списывать с владельца этого аккаунта (`predecessor_id`) до `expires_at`.
+    /// Перезаписывает прежнее значение, поэтому лучше использовать `increase_allowance`/`decrease_allowance`.
     pub fn set_allowance(
         &mut self,
         escrow_account_id: AccountId,
         token_id: TokenId,
         allowance: U128,
+        expires_at: Option<Timestamp>,
     ) {
-        let allowance = allowance.into();
+        let initial_storage = env::storage_usage();
         let owner_id = env::predecessor_account_id();
-        if escrow_account_id == owner_id {
-            env::panic(b"Can't set allowance for yourself");
+        self.internal_set_allowance(&owner_id, escrow_account_id, token_id, Allowance {
+            amount: allowance.into(),
+            expires_at,
+        });
+        self.charge_storage(&owner_id, initial_storage);
+    }
+
+    /// Увеличивает allowance `escrow_account_id` на `amount`.
+    /// Если `expires_at` не задан, срок действия остается прежним.
+    pub fn increase_allowance(
+        &mut self,
+        escrow_account_id: AccountId,
+        token_id: TokenId,
+        amount: U128,
+        expires_at: Option<Timestamp>,
+    ) {
+        let initial_storage = env::storage_usage();
+        let owner_id = env::predecessor_account_id();
+        let current = self.get_allowance_entry(&owner_id, &escrow_account_id, &token_id);
+        let allowance = match current.amount.checked_add(amount.0) {
+            Some(allowance) => allowance,
+            None => env::panic(b"Allowance overflow"),
+        };
+        self.internal_set_allowance(&owner_id, escrow_account_id, token_id, Allowance {
+            amount: allowance,
+            expires_at: expires_at.or(current.expires_at),
+        });
+        self.charge_storage(&owner_id, initial_storage);
+    }
+
+    /// Уменьшает allowance `escrow_account_id` на `amount`.
+    pub fn decrease_allowance(&mut self, escrow_account_id: AccountId, token_id: TokenId, amount: U128) {
+        let owner_id = env::predecessor_account_id();
+        let current = self.get_allowance_entry(&owner_id, &escrow_account_id, &token_id);
+        if current.amount < amount.0 {
+            env::panic(b"Allowance can't be decreased below zero");
         }
-        let mut account = self.get_account(&owner_id, token_id.clone());
-        account.set_allowance(&escrow_account_id, allowance);
-        self.set_account(&owner_id, &account, &token_id);
+        self.internal_set_allowance(&owner_id, escrow_account_id, token_id, Allowance {
+            amount: current.amount - amount.0,
+            expires_at: current.expires_at,
+        });
     }
 
     /// Transfers the `amount` of tokens from `owner_id` to the `new_owner_id`.
@@ -115,62 +295,57 @@ impl Contract {
         token_id: TokenId,
         amount: U128,
     ) {
-        println!(
-            "Перепожу {} {} от {} к {}. Иниц: {}",
-            amount.0,
-            token_id.clone(),
-            owner_id,
+        self.paused.assert_not_paused(Subsystem::Transfers);
+        let initial_storage = env::storage_usage();
+        self.internal_transfer_from(owner_id, new_owner_id, token_id, amount);
+        self.charge_storage(&env::predecessor_account_id(), initial_storage);
+    }
+
+    /// Transfer `amount` of tokens from the caller of the contract (`predecessor_id`) to
+    /// `new_owner_id`.
+    /// Act the same was as `transfer_from` with `owner_id` equal to the caller of the contract
+    /// (`predecessor_id`).
+    pub fn transfer(&mut self, new_owner_id: AccountId, token_id: TokenId, amount: U128) {
+        self.transfer_from(
+            env::predecessor_account_id(),
             new_owner_id,
-            env::predecessor_account_id()
+            token_id,
+            amount,
         );
+    }
+
+    /// `transfer_from` без списания платы за хранилище, для вызовов внутри контракта.
+    fn internal_transfer_from(
+        &mut self,
+        owner_id: AccountId,
+        new_owner_id: AccountId,
+        token_id: TokenId,
+        amount: U128,
+    ) {
         let amount = amount.into();
         if amount == 0 {
             env::panic(b"Can't transfer 0 tokens");
         }
-        // Retrieving the account from the state.
-        let mut account = self.get_account(&owner_id, token_id.clone());
-
-        // Checking and updating unlocked balance
-        if account.balance < amount {
-            env::panic(b"Not enough balance");
-        }
-        account.balance -= amount;
 
         // If transferring by escrow, need to check and update allowance.
         let escrow_account_id = env::predecessor_account_id();
         if escrow_account_id != owner_id {
-            let allowance = account.get_allowance(&escrow_account_id);
-            if allowance < amount {
+            let allowance = self.get_allowance_entry(&owner_id, &escrow_account_id, &token_id);
+            if allowance.amount < amount {
                 env::panic(b"Not enough allowance");
             }
-            account.set_allowance(&escrow_account_id, allowance - amount);
+            self.internal_set_allowance(&owner_id, escrow_account_id, token_id.clone(), Allowance {
+                amount: allowance.amount - amount,
+                expires_at: allowance.expires_at,
+            });
         }
 
-        // Saving the account back to the state.
-        self.set_account(&owner_id, &account, &token_id);
-
-        // Deposit amount to the new owner and save the new account to the state.
-        let mut new_account = self.get_account(&new_owner_id, token_id.clone());
-        new_account.balance += amount;
-        self.set_account(&new_owner_id, &new_account, &token_id);
-    }
-
-    /// Transfer `amount` of tokens from the caller of the contract (`predecessor_id`) to
-    /// `new_owner_id`.
-    /// Act the same was as `transfer_from` with `owner_id` equal to the caller of the contract
-    /// (`predecessor_id`).
-    pub fn transfer(&mut self, new_owner_id: AccountId, token_id: TokenId, amount: U128) {
-        self.transfer_from(
-            env::predecessor_account_id(),
-            new_owner_id,
-            token_id,
-            amount,
-        );
+        self.internal_transfer(&owner_id, &new_owner_id, &token_id, amount, None);
     }
 
-    /// Returns total supply of tokens.
-    pub fn get_total_supply(&self) -> Balance {
-        100_000_000_000
+    /// Returns total supply of `token_id`.
+    pub fn get_total_supply(&self, token_id: TokenId) -> U128 {
+        self.ft_total_supply(token_id)
     }
 
     /// Returns balance of the `owner_id` account.
@@ -202,30 +377,110 @@ impl Contract {
             .get_allowance(&escrow_account_id)
             .into()
     }
+
+    /// Returns all unexpired allowances granted by `owner_id` for `token_id` or for every token.
+    pub fn get_allowances(&self, owner_id: AccountId, token_id: Option<TokenId>) -> Vec<AllowanceView> {
+        let token_ids = match token_id {
+            Some(token_id) => vec![token_id],
+            None => self.tokens.keys().collect(),
+        };
+        let mut result = Vec::new();
+        for token_id in token_ids {
+            for (escrow_account_id, allowance) in self.get_account(&owner_id, token_id.clone()).get_allowances() {
+                result.push(AllowanceView {
+                    token_id: token_id.clone(),
+                    escrow_account_id,
+                    allowance: U128(allowance.amount),
+                    expires_at: allowance.expires_at,
+                });
+            }
+        }
+        result
+    }
+
+    /// Действующее разрешение `escrow_account_id`, истекшее считается нулевым.
+    fn get_allowance_entry(&self, owner_id: &AccountId, escrow_account_id: &AccountId, token_id: &TokenId) -> Allowance {
+        match self.get_account(owner_id, token_id.clone()).allowances.get(escrow_account_id) {
+            Some(allowance) if !allowance.is_expired() => allowance,
+            _ => Allowance {
+                amount: 0,
+                expires_at: None,
+            },
+        }
+    }
+
+    fn internal_set_allowance(&mut self, owner_id: &AccountId, escrow_account_id: AccountId, token_id: TokenId, allowance: Allowance) {
+        if &escrow_account_id == owner_id {
+            env::panic(b"Can't set allowance for yourself");
+        }
+        if let Some(expires_at) = allowance.expires_at {
+            if expires_at <= env::block_timestamp() {
+                env::panic(b"Allowance expiration must be in the future");
+            }
+        }
+        let mut account = self.get_account(owner_id, token_id.clone());
+        account.set_allowance(&escrow_account_id, allowance.clone());
+        self.set_account(owner_id, &account, &token_id);
+        Event::AllowanceSet(vec![AllowanceSet {
+            owner_id: owner_id.clone(),
+            escrow_account_id,
+            token_id,
+            allowance: U128(allowance.amount),
+            expires_at: allowance.expires_at,
+        }]).emit();
+    }
 }
 
+/// Секция работы с NEAR: внесенные NEAR хранятся на бирже как токен wNEAR,
+/// цена которого определяется стаканом wNEAR/XDHO
 #[near_bindgen]
 impl Contract {
+    /// Зачисляет прикрепленные NEAR на баланс wNEAR вызывающего.
     #[payable]
-    pub fn pay_standard_token(&mut self, amount: U128, to: AccountId) -> Promise {
-        if(env::account_balance() < amount.0 * YOKTO_NEAR){
-            env::panic(b"Not enough NEAR balance");
+    pub fn deposit_near(&mut self) -> U128 {
+        let amount = env::attached_deposit();
+        if amount == 0 {
+            env::panic(b"Attach NEAR to deposit");
         }
-        if(env::attached_deposit() < amount.0 * YOKTO_NEAR){
-            env::panic(b"Not enough attached NEAR");
+        let initial_storage = env::storage_usage();
+        let account_id = env::predecessor_account_id();
+        self.internal_deposit(&account_id, &self.get_near_token(), amount);
+        self.charge_storage(&account_id, initial_storage);
+        self.get_balance(account_id, self.get_near_token())
+    }
+
+    /// Списывает `amount` wNEAR и переводит столько же NEAR вызывающему.
+    /// Если перевод не прошел, wNEAR возвращается на баланс в `near_resolve_withdraw`.
+    #[payable]
+    pub fn withdraw_near(&mut self, amount: U128) -> Promise {
+        assert_one_yocto();
+        if amount.0 == 0 {
+            env::panic(b"Can't withdraw 0 NEAR");
         }
-        self.transfer_from_user(
-            self.owner_id.clone(),
-            to.clone(),
-            self.get_standard_token(),
-            U128::from(self.get_standard_price() * amount.0),
-        );
+        let account_id = env::predecessor_account_id();
+        self.internal_withdraw(&account_id, &self.get_near_token(), amount.0);
+        Promise::new(account_id.clone())
+            .transfer(amount.0)
+            .then(ext_self::near_resolve_withdraw(
+                account_id,
+                amount,
+                &env::current_account_id(),
+                NO_DEPOSIT,
+                GAS_FOR_RESOLVE_TRANSFER,
+            ))
+    }
 
-        Promise::new(self.owner_id.clone()).transfer((amount.0 * YOKTO_NEAR).to_u128().unwrap())
+    /// Возвращает wNEAR на баланс, если перевод NEAR не удался.
+    #[private]
+    pub fn near_resolve_withdraw(&mut self, account_id: AccountId, amount: U128) {
+        match env::promise_result(0) {
+            PromiseResult::Successful(_) => {}
+            _ => self.internal_deposit(&account_id, &self.get_near_token(), amount.0),
+        }
     }
 
-    pub fn get_standard_price(&self) -> Balance {
-        97u128 // монет за один NEAR
+    pub fn get_near_token(&self) -> TokenId {
+        "wNEAR".to_string()
     }
 }
 
@@ -233,18 +488,27 @@ impl Contract {
 #[near_bindgen]
 impl Contract {
     pub fn stake(&mut self, amount: Balance) {
+        self.paused.assert_not_paused(Subsystem::Staking);
         let old_staking = self.get_staking(env::predecessor_account_id());
         if old_staking.staked != 0 {
             env::panic(b"You have staked tokens. First do the unstaking");
         }
 
-        self.transfer_from(
+        let initial_storage = env::storage_usage();
+        // стейк лежит на счете контракта до `unstake`
+        self.internal_transfer_from(
             env::predecessor_account_id(),
-            self.owner_id.clone(),
+            env::current_account_id(),
             "XDHO".to_string(),
             U128(amount),
         );
         self.set_staking(env::predecessor_account_id(), amount);
+        self.delegations.add_stake(&env::predecessor_account_id(), amount);
+        Event::Stake(vec![StakeEvent {
+            account_id: env::predecessor_account_id(),
+            amount: U128(amount),
+        }]).emit();
+        self.charge_storage(&env::predecessor_account_id(), initial_storage);
     }
 
     pub fn get_staking(&self, owner_id: AccountId) -> StakeInfo {
@@ -284,26 +548,69 @@ impl Contract {
         if old_staking.staked == 0 {
             env::panic(b"You did't have staked tokens. First do the staking");
         }
+        // иначе те же токены проголосуют еще раз с другого аккаунта
+        if self.pending_votes.get(&env::predecessor_account_id()).unwrap_or(0) > 0 {
+            env::panic(b"You have votes on pending requests, unstake after they are finalized");
+        }
+        let initial_storage = env::storage_usage();
         let created_time = env::block_timestamp() - old_staking.created_time;
         let mut amount = created_time.to_f64().unwrap() * STAKING_PERCENT + 1.0;
         amount *= old_staking.staked.to_f64().unwrap();
-        self.transfer_from_user(
-            self.owner_id.clone(),
-            env::predecessor_account_id(),
-            "XDHO".to_string(),
-            U128(amount.to_u128().unwrap()),
+        // награда платится из резерва, пока он не исчерпан, стейк возвращается всегда
+        let reward = amount.to_u128().unwrap()
+            .saturating_sub(old_staking.staked)
+            .min(self.staking_rewards);
+        self.staking_rewards -= reward;
+        let amount = old_staking.staked + reward;
+        self.internal_transfer(
+            &env::current_account_id(),
+            &env::predecessor_account_id(),
+            &"XDHO".to_string(),
+            amount,
+            None,
         );
         let account_hash = env::sha256(env::predecessor_account_id().as_bytes());
         self.staking.remove(&account_hash);
+        self.delegations.remove_stake(&env::predecessor_account_id(), old_staking.staked);
+        Event::Unstake(vec![StakeEvent {
+            account_id: env::predecessor_account_id(),
+            amount: U128(amount),
+        }]).emit();
+        self.charge_storage(&env::predecessor_account_id(), initial_storage);
+    }
+
+    /// Пополняет резерв наград за стейкинг своими XDHO
+    pub fn deposit_staking_rewards(&mut self, amount: U128) {
+        if amount.0 == 0 {
+            env::panic(b"The amount should be a positive number");
+        }
+        self.internal_transfer_from(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            self.get_standard_token(),
+            amount,
+        );
+        self.staking_rewards += amount.0;
+        Event::StakingRewardsDeposit(vec![StakeEvent {
+            account_id: env::predecessor_account_id(),
+            amount,
+        }]).emit();
+    }
+
+    pub fn get_staking_rewards(&self) -> U128 {
+        U128(self.staking_rewards)
     }
 
     pub fn add_new_request(&mut self, request: UserRequest) {
+        self.paused.assert_not_paused(Subsystem::Voting);
         if self.tokens.get(&request.token_id).is_some() {
             env::panic(b"A token with this ID already exists");
         }
 
-        //TODO: брать плату за запросики
-        self.ballot_handler.add_new_request(request);
+        let initial_storage = env::storage_usage();
+        let request_id = self.ballot_handler.add_new_request(request, self.governance.voting_terms());
+        self.open_request(request_id);
+        self.charge_storage(&env::predecessor_account_id(), initial_storage);
     }
 
     pub fn get_all_requests(&self) -> Vec<Request> {
@@ -314,20 +621,123 @@ impl Contract {
         self.ballot_handler.get_all_votes(request_id)
     }
 
-    pub fn vote(&mut self, request_id: RequestId, vote: bool) {
+    /// Голос "за", "против" или "воздержался". До конца голосования его можно изменить.
+    /// Голос делегата несет и стейк его делегаторов, весь стейк берется на момент завершения.
+    pub fn vote(&mut self, request_id: RequestId, vote: VoteOption) {
+        self.paused.assert_not_paused(Subsystem::Voting);
         let request = self.get_request(request_id);
-        if request.created_time + VOTING_TIME < env::block_timestamp() {
+        if request.terms.voting_end < env::block_timestamp() {
             //TODO: финализировать тута
             env::panic(b"The voting has already ended");
         }
-        let staked = self.get_staking(env::predecessor_account_id());
-        if staked.staked == 0 {
+        if self.is_secret_ballot(request_id) {
+            env::panic(b"The request uses a secret ballot, call commit_vote");
+        }
+        let weight = self.voter_weight();
+        let initial_storage = env::storage_usage();
+        if !self.ballot_handler.is_vote(request_id, env::predecessor_account_id()) {
+            self.add_pending_vote(&env::predecessor_account_id());
+        }
+        self.ballot_handler.vote(request_id, vote, weight);
+        self.charge_storage(&env::predecessor_account_id(), initial_storage);
+        Event::Vote(vec![VoteCast {
+            request_id,
+            voter_id: env::predecessor_account_id(),
+            vote,
+            weight: U128(weight),
+        }]).emit();
+    }
+
+    /// Скрытый голос по заявке с тайным голосованием: `hash` - sha256 от borsh-кортежа
+    /// `(request_id, голосующий, вариант, соль)`, где вариант - байт 0 ("против"), 1 ("за") или 2 ("воздержался").
+    /// До конца голосования хеш можно заменить.
+    pub fn commit_vote(&mut self, request_id: RequestId, hash: Base64VecU8) {
+        self.paused.assert_not_paused(Subsystem::Voting);
+        let request = self.get_request(request_id);
+        if request.terms.voting_end < env::block_timestamp() {
+            env::panic(b"The voting has already ended");
+        }
+        if !self.is_secret_ballot(request_id) {
+            env::panic(b"The request uses an open ballot, call vote");
+        }
+        if hash.0.len() != 32 {
+            env::panic(b"The vote hash must be a sha256 hash");
+        }
+        let weight = self.voter_weight();
+        let voter_id = env::predecessor_account_id();
+        let initial_storage = env::storage_usage();
+        if !self.secret_ballots.is_committed(request_id, &voter_id) {
+            self.add_pending_vote(&voter_id);
+        }
+        self.secret_ballots.commit(request_id, &voter_id, VoteCommit { hash: hash.0, weight });
+        self.charge_storage(&voter_id, initial_storage);
+        Event::VoteCommit(vec![VoteCommitted {
+            request_id,
+            voter_id,
+        }]).emit();
+    }
+
+    /// Раскрывает скрытый голос после окончания голосования. Нераскрытые голоса считаются воздержавшимися.
+    pub fn reveal_vote(&mut self, request_id: RequestId, vote: VoteOption, salt: String) {
+        self.paused.assert_not_paused(Subsystem::Voting);
+        let request = self.get_request(request_id);
+        let reveal_period = match self.secret_ballots.get_reveal_period(request_id) {
+            Some(reveal_period) => reveal_period,
+            None => env::panic(b"The request uses an open ballot"),
+        };
+        let voting_end = request.terms.voting_end;
+        if voting_end >= env::block_timestamp() {
+            env::panic(b"The voting is not over yet");
+        }
+        if voting_end + reveal_period < env::block_timestamp() {
+            env::panic(b"The reveal period has already ended");
+        }
+        let voter_id = env::predecessor_account_id();
+        let initial_storage = env::storage_usage();
+        // вес голоса записан при подаче хеша
+        let weight = self.secret_ballots.reveal(request_id, &voter_id, vote, &salt);
+        self.ballot_handler.vote(request_id, vote, weight);
+        self.charge_storage(&voter_id, initial_storage);
+        Event::Vote(vec![VoteCast {
+            request_id,
+            voter_id,
+            vote,
+            weight: U128(weight),
+        }]).emit();
+    }
+
+    pub fn is_secret_ballot(&self, request_id: RequestId) -> bool {
+        self.secret_ballots.get_reveal_period(request_id).is_some()
+    }
+
+    pub fn is_vote_committed(&self, voter_id: AccountId, request_id: RequestId) -> bool {
+        self.secret_ballots.is_committed(request_id, &voter_id)
+    }
+
+    /// Вес голоса: собственный стейк голосующего вместе со всем делегированным ему стейком
+    fn voter_weight(&self) -> Balance {
+        let staked = self.get_staking(env::predecessor_account_id()).staked;
+        let weight = staked + self.delegations.get_delegated_stake(&env::predecessor_account_id());
+        if weight == 0 {
             env::panic(b"You did't have staked tokens. First do the staking");
         }
-        if self.is_voting(env::predecessor_account_id(), request_id) {
-            env::panic(b"You have already voted for this request");
+        if weight < self.governance.min_stake.0 {
+            env::panic(b"Your stake is below the minimum required to vote");
         }
-        self.ballot_handler.vote(request_id, vote);
+        weight
+    }
+
+    fn add_pending_vote(&mut self, voter_id: &AccountId) {
+        let count = self.pending_votes.get(voter_id).unwrap_or(0);
+        self.pending_votes.insert(voter_id, &(count + 1));
+    }
+
+    /// Снимает блокировку стейка с голосовавшего по завершенной заявке
+    fn release_pending_vote(&mut self, voter_id: &AccountId) {
+        match self.pending_votes.get(voter_id).unwrap_or(0) {
+            0 | 1 => self.pending_votes.remove(voter_id),
+            count => self.pending_votes.insert(voter_id, &(count - 1)),
+        };
     }
 
     pub fn get_request(&self, request_id: RequestId) -> Request {
@@ -342,72 +752,369 @@ impl Contract {
         self.ballot_handler.is_vote(request_id, voter_id)
     }
 
+    /// Передает свой голос `delegate_id` во всех заявках, где не голосуешь сам
+    pub fn delegate_votes(&mut self, delegate_id: AccountId) {
+        self.paused.assert_not_paused(Subsystem::Voting);
+        let delegator_id = env::predecessor_account_id();
+        let initial_storage = env::storage_usage();
+        let staked = self.get_staking(delegator_id.clone()).staked;
+        if let Some(old_delegate_id) = self.delegations.delegate(&delegator_id, &delegate_id, staked) {
+            Event::Undelegate(vec![DelegationChanged {
+                delegator_id: delegator_id.clone(),
+                delegate_id: old_delegate_id,
+            }]).emit();
+        }
+        self.charge_storage(&delegator_id, initial_storage);
+        Event::Delegate(vec![DelegationChanged {
+            delegator_id,
+            delegate_id,
+        }]).emit();
+    }
+
+    pub fn undelegate(&mut self) {
+        let delegator_id = env::predecessor_account_id();
+        let initial_storage = env::storage_usage();
+        let staked = self.get_staking(delegator_id.clone()).staked;
+        let delegate_id = match self.delegations.undelegate(&delegator_id, staked) {
+            Some(delegate_id) => delegate_id,
+            None => env::panic(b"You haven't delegated your votes"),
+        };
+        self.charge_storage(&delegator_id, initial_storage);
+        Event::Undelegate(vec![DelegationChanged {
+            delegator_id,
+            delegate_id,
+        }]).emit();
+    }
+
+    pub fn get_delegate(&self, account_id: AccountId) -> Option<AccountId> {
+        self.delegations.get_delegate(&account_id)
+    }
+
+    /// Прямые делегаторы аккаунта
+    pub fn get_delegators(&self, account_id: AccountId) -> Vec<AccountId> {
+        self.delegations.get_delegators(&account_id)
+    }
+
+    /// Цепочка делегатов аккаунта, последний в ней голосует за всех
+    pub fn get_delegation_chain(&self, account_id: AccountId) -> Vec<AccountId> {
+        self.delegations.get_chain(&account_id)
+    }
+
+    /// Стейк всех делегаторов аккаунта, прямых и по цепочке
+    pub fn get_delegated_stake(&self, account_id: AccountId) -> U128 {
+        U128(self.delegations.get_delegated_stake(&account_id))
+    }
+
+    /// Итоговые веса голосов `votes` - пар из голосующего и веса, записанного при голосовании:
+    /// стейка голосующего и его делегаторов по цепочке на тот момент. Вес делегатора,
+    /// проголосовавшего самому, уходит из голоса ближайшего голосовавшего делегата.
+    /// Обход ограничен длиной цепочки, а не числом делегаторов.
+    fn final_weights(&self, votes: &[(AccountId, Balance)]) -> HashMap<AccountId, Balance> {
+        let mut weights: HashMap<AccountId, Balance> = votes.iter().cloned().collect();
+        for (voter_id, weight) in votes {
+            let delegate_id = self.delegations.get_chain(voter_id)
+                .into_iter()
+                .find(|delegate_id| weights.contains_key(delegate_id));
+            if let Some(delegate_id) = delegate_id {
+                let delegate_weight = weights.get_mut(&delegate_id).unwrap();
+                *delegate_weight = delegate_weight.saturating_sub(*weight);
+            }
+        }
+        weights
+    }
+
     pub fn finalize_request(&mut self, request_id: RequestId) {
         let request = self.get_request(request_id);
-        if request.created_time + VOTING_TIME > env::block_timestamp() {
+        if request.status != RequestStatus::PENDING {
+            env::panic(b"The request is already finalized");
+        }
+        let reveal_period = self.secret_ballots.get_reveal_period(request_id).unwrap_or(0);
+        if request.terms.voting_end + reveal_period > env::block_timestamp() {
             env::panic(b"The voting is not over yet");
         }
-        // время вышло
-        let votes = self.ballot_handler.get_all_votes(request_id);
-        let count_stakers: i32 = self.get_count_stakers();
-        let voters_count = votes.len();
+        // время вышло, голоса взвешены стейком, и собственным, и делегированным, на момент голосования
+        let unrevealed = self.secret_ballots.get_unrevealed(request_id);
+        let mut votes: Vec<(AccountId, Balance)> = self.get_all_votes(request_id)
+            .into_iter()
+            .map(|vote| (vote.owner_id, vote.weight))
+            .collect();
+        votes.extend(unrevealed.iter().cloned());
+        let weights = self.final_weights(&votes);
+        let mut tally = self.ballot_handler.tally(request_id, |vote| weights[&vote.owner_id]);
+        for (voter_id, _) in &unrevealed {
+            tally.add(VoteOption::Abstain, weights[voter_id]);
+        }
+        // голосовавшим возвращается место под их голоса
+        for (voter_id, _) in &votes {
+            let initial_storage = env::storage_usage();
+            self.ballot_handler.remove_vote(request_id, voter_id);
+            self.secret_ballots.remove_commit(request_id, voter_id);
+            self.release_pending_vote(voter_id);
+            self.charge_storage(voter_id, initial_storage);
+        }
+        self.secret_ballots.close(request_id);
 
-        // голосов меньше, чем 1/3
-        if count_stakers / 3 > voters_count.try_into().unwrap() {
+        if !request.terms.has_quorum(tally.voted(), self.get_all_staked()) {
+            // за заявку без кворума залог уходит в казну
             self.reject_request(request_id);
+            self.close_request(&request, true);
+        } else if request.terms.is_approved(tally.yes, tally.decided()) {
+            // залог возвращается до исполнения, его исход на залог не влияет
+            self.close_request(&request, false);
+            self.approve_request(request_id);
         } else {
-            let mut positive_count = 0;
-            for vote in votes {
-                if vote.result {
-                    positive_count += 1;
-                }
-            }
-            if positive_count <= voters_count / 2 {
-                self.reject_request(request_id);
+            self.reject_request(request_id);
+            self.close_request(&request, false);
+        }
+    }
+
+    /// Учитывает новую заявку автора, берет с него залог в XDHO и выбирает вид голосования
+    fn open_request(&mut self, request_id: RequestId) {
+        let owner_id = env::predecessor_account_id();
+        let open_count = self.open_requests.get(&owner_id).unwrap_or(0);
+        if open_count >= MAX_OPEN_REQUESTS {
+            env::panic(b"Too many open requests, wait for the voting on them to end");
+        }
+        self.open_requests.insert(&owner_id, &(open_count + 1));
+
+        let deposit = self.governance.proposal_deposit.0;
+        if deposit > 0 {
+            self.internal_transfer(&owner_id, &env::current_account_id(), &self.get_standard_token(), deposit, None);
+            self.proposal_deposits.insert(&request_id, &deposit);
+        }
+
+        // вид голосования фиксируется при подаче заявки
+        let request = self.get_request(request_id);
+        if self.governance.is_secret(&request.kind) {
+            self.secret_ballots.open(request_id, self.governance.reveal_period);
+        }
+    }
+
+    /// Закрывает заявку после голосования: залог возвращается автору или уходит в казну
+    fn close_request(&mut self, request: &Request, slash: bool) {
+        match self.open_requests.get(&request.owner_id).unwrap_or(0) {
+            0 | 1 => self.open_requests.remove(&request.owner_id),
+            open_count => self.open_requests.insert(&request.owner_id, &(open_count - 1)),
+        };
+
+        if let Some(deposit) = self.proposal_deposits.remove(&request.id) {
+            let standard_token = self.get_standard_token();
+            if slash {
+                self.internal_to_treasury(&env::current_account_id(), &standard_token, deposit);
             } else {
-                self.approve_request(request_id);
+                self.internal_transfer(&env::current_account_id(), &request.owner_id, &standard_token, deposit, None);
             }
         }
     }
 
+    /// Залог, удерживаемый за незавершенную заявку
+    pub fn get_proposal_deposit(&self, request_id: RequestId) -> U128 {
+        U128(self.proposal_deposits.get(&request_id).unwrap_or(0))
+    }
+
+    pub fn get_open_requests_count(&self, account_id: AccountId) -> u32 {
+        self.open_requests.get(&account_id).unwrap_or(0)
+    }
+
     fn reject_request(&mut self, request_id: RequestId) {
         self.ballot_handler.reject_request(request_id);
     }
 
+    /// Одобренная заявка, которую уже нельзя исполнить, завершается со статусом `EXECUTION_FAILED`
     fn approve_request(&mut self, request_id: RequestId) {
-        self.ballot_handler.approve_request(request_id);
+        let request = self.get_request(request_id);
+        if self.check_request_kind(&request.kind, true).is_err() {
+            self.ballot_handler.fail_request(request_id);
+            return;
+        }
+        self.ballot_handler.approve_request(request_id, self.launchpad_vesting.clone());
+        self.execute_request(request);
+    }
+
+    /// Обработчики одобренных заявок
+    fn execute_request(&mut self, request: Request) {
+        match request.kind {
+            // лаунчпад запускает автор заявки через `start_launchpad`
+            RequestKind::Listing(_) => {}
+            RequestKind::Delist { token_id } => {
+                self.internal_set_market_status(&token_id, Some(MarketStatus::Delisted));
+            }
+            RequestKind::ChangeFees { fees } => {
+                self.internal_set_fee_rates(fees);
+            }
+            RequestKind::ChangeGovernanceConfig { config } => {
+                self.governance = config.clone();
+                Event::GovernanceConfigChange(vec![GovernanceConfigChanged {
+                    request_id: request.id,
+                    config,
+                }]).emit();
+            }
+            RequestKind::TreasurySpend { receiver_id, token_id, amount } => {
+                self.internal_spend_treasury(&receiver_id, &token_id, amount.0);
+            }
+            RequestKind::HaltMarket { token_id, halted } => {
+                let status = if halted { Some(MarketStatus::Halted) } else { None };
+                self.internal_set_market_status(&token_id, status);
+            }
+            // код разворачивается через `upgrade` после таймлока
+            RequestKind::Upgrade { .. } => {}
+            RequestKind::OverrideTokenMetadata { token_id, meta } => {
+                self.internal_set_metadata(token_id, meta, env::current_account_id());
+            }
+            RequestKind::SetLaunchpadVesting { terms } => {
+                self.launchpad_vesting = terms;
+            }
+        }
+    }
+
+    /// Выносит на голосование заявку любого вида, кроме листинга (для него есть `add_new_request`)
+    pub fn add_proposal(&mut self, title: String, description: String, kind: RequestKind) -> RequestId {
+        self.paused.assert_not_paused(Subsystem::Voting);
+        self.assert_valid_proposal(&kind);
+        let initial_storage = env::storage_usage();
+        let request_id = self.ballot_handler.add_proposal(title, description, kind, self.governance.voting_terms());
+        self.open_request(request_id);
+        self.charge_storage(&env::predecessor_account_id(), initial_storage);
+        request_id
+    }
+
+    fn assert_valid_proposal(&self, kind: &RequestKind) {
+        match kind {
+            RequestKind::Listing(_) => env::panic(b"Use add_new_request to list a token"),
+            RequestKind::ChangeFees { fees } => fees.assert_valid(),
+            RequestKind::ChangeGovernanceConfig { config } => config.assert_valid(),
+            RequestKind::TreasurySpend { amount, .. } => {
+                if amount.0 == 0 {
+                    env::panic(b"The amount should be a positive number");
+                }
+            }
+            RequestKind::Upgrade { code_hash } => {
+                if code_hash.0.len() != 32 {
+                    env::panic(b"Code hash must be 32 bytes");
+                }
+            }
+            RequestKind::OverrideTokenMetadata { meta, .. } => meta.assert_valid(),
+            RequestKind::SetLaunchpadVesting { terms } => {
+                if let Some(terms) = terms {
+                    terms.assert_valid();
+                }
+            }
+            RequestKind::Delist { .. } | RequestKind::HaltMarket { .. } => {}
+        }
+        if let Err(message) = self.check_request_kind(kind, false) {
+            env::panic(message.as_bytes());
+        }
+    }
+
+    /// Условия, которые зависят от состояния контракта: проверяются при подаче и снова перед исполнением,
+    /// так как за время голосования состояние могло измениться. Средства казны - только перед исполнением.
+    fn check_request_kind(&self, kind: &RequestKind, executing: bool) -> Result<(), &'static str> {
+        match kind {
+            RequestKind::Delist { token_id } => {
+                if self.tokens.get(token_id).is_none() {
+                    return Err("Token not found");
+                }
+                if *token_id == self.get_standard_token() || *token_id == self.get_near_token() {
+                    return Err("This token can't be delisted");
+                }
+                if self.markets.get(token_id) == Some(MarketStatus::Delisted) {
+                    return Err("This token is delisted");
+                }
+            }
+            RequestKind::TreasurySpend { token_id, amount, .. } => {
+                if self.tokens.get(token_id).is_none() {
+                    return Err("Token not found");
+                }
+                if executing && self.treasury.get(token_id).unwrap_or(0) < amount.0 {
+                    return Err("Not enough funds in the treasury");
+                }
+            }
+            RequestKind::HaltMarket { token_id, .. } => {
+                if self.order_books.get(token_id).is_none() {
+                    return Err("There is no market for this token");
+                }
+                if self.markets.get(token_id) == Some(MarketStatus::Delisted) {
+                    return Err("This token is delisted");
+                }
+            }
+            RequestKind::OverrideTokenMetadata { token_id, .. } => {
+                if self.tokens.get(token_id).is_none() {
+                    return Err("Token not found");
+                }
+            }
+            RequestKind::Listing(_) | RequestKind::ChangeFees { .. }
+            | RequestKind::ChangeGovernanceConfig { .. } | RequestKind::Upgrade { .. }
+            | RequestKind::SetLaunchpadVesting { .. } => {}
+        }
+        Ok(())
+    }
+
+    pub fn get_governance_config(&self) -> GovernanceConfig {
+        self.governance.clone()
+    }
+
+    /// Разворачивает одобренный голосованием код по истечении `UPGRADE_TIMELOCK` с одобрения и вызывает `migrate`.
+    /// Вызвать может кто угодно: код сверяется с хешем из заявки.
+    pub fn upgrade(&mut self, #[serializer(borsh)] code: Vec<u8>) -> Promise {
+        let request = match self.ballot_handler.find_approved_upgrade(&env::sha256(&code)) {
+            Some(request) => request,
+            None => env::panic(b"There is no approved upgrade with this code hash"),
+        };
+        if request.approved_time.unwrap() + UPGRADE_TIMELOCK > env::block_timestamp() {
+            env::panic(b"The upgrade timelock has not elapsed yet");
+        }
+        Promise::new(env::current_account_id())
+            .deploy_contract(code)
+            .function_call(b"migrate".to_vec(), vec![], NO_DEPOSIT, GAS_FOR_MIGRATE)
+            .then(ext_upgrade::resolve_upgrade(
+                request.id,
+                &env::current_account_id(),
+                NO_DEPOSIT,
+                GAS_FOR_RESOLVE_UPGRADE,
+            ))
+    }
+
+    /// Заявка считается исполненной, только когда код развернут и `migrate` прошел.
+    /// Если пакет упал, код остался прежним, а заявка - одобренной, и `upgrade` можно повторить.
+    #[private]
+    pub fn resolve_upgrade(&mut self, request_id: RequestId) {
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            self.ballot_handler.launch_request(request_id);
+        }
     }
 
     /// создает новый токен, продажи, которого начнутся в запланированное время
     pub fn start_launchpad(&mut self, request_id: RequestId, launched_time: Timestamp) {
-        let mut request = self.get_request(request_id);
+        self.paused.assert_not_paused(Subsystem::Launchpad);
+        let request = self.get_request(request_id);
         if request.owner_id != env::predecessor_account_id() {
             env::panic(b"To start the launchpad, you need to be the creator of the request")
         }
+        let listing = match request.kind {
+            RequestKind::Listing(listing) => listing,
+            _ => env::panic(b"Only a listing request can start the launchpad"),
+        };
         if request.status != RequestStatus::APPROVED {
             env::panic(b"To start the launchpad, the request must be approved by a vote.")
         }
+        listing.meta.assert_valid();
         let token = Token {
-            token_id: request.token_id,
+            token_id: listing.token_id,
             owner_id: request.owner_id,
-            supply: request.supply,
-            meta: Some(TokenMetadata {
-                title: Some(request.title),
-                description: Some(request.description),
-                //TODO: поменять на картиночку
-                icon: Some(request.hash),
-            }),
+            total_supply: listing.supply,
+            max_supply: listing.max_supply,
+            meta: listing.meta,
+            origin: TokenOrigin::Native,
         };
 
-        request.status = RequestStatus::LAUNCHED
-
-        self.ballot_handler.requests.insert(&request_id, &request);
+        self.ballot_handler.launch_request(request_id);
 
         self.insert_launchpad_tokens(&LaunchPad {
-            price: request.price,
-            sell_supply: request.supply,
+            price: listing.price,
+            sell_supply: listing.supply,
             launched_time,
             token,
+            vesting: listing.vesting,
         })
     }
 
@@ -428,6 +1135,8 @@ impl Contract {
     }
 
     pub fn buy_tokens_on_launchpad(&mut self, token_id: TokenId, amount: Balance) {
+        self.paused.assert_not_paused(Subsystem::Launchpad);
+        let initial_storage = env::storage_usage();
         let mut launchpad = self.get_launchpad(token_id.clone());
         let current_time = env::block_timestamp();
         if launchpad.launched_time > current_time{
@@ -437,27 +1146,40 @@ impl Contract {
             env::panic(b"There are not so many tokens left on the launchpad");
         }
 
-        self.transfer(
+        let payment = launchpad.price.checked_mul(amount).expect("Overflow");
+        let fee = self.fees.launchpad_fee(payment);
+        self.internal_transfer_from(
+            env::predecessor_account_id(),
             launchpad.token.owner_id.clone(),
             self.get_standard_token(),
-            U128(launchpad.price * amount)
+            U128(payment - fee)
         );
+        if fee > 0 {
+            self.internal_to_treasury(&env::predecessor_account_id(), &self.get_standard_token(), fee);
+        }
 
         let mut account = self.get_account(&env::predecessor_account_id(), token_id.clone());
         account.balance += amount;
         self.set_account(&env::predecessor_account_id(), &account, &token_id.clone());
+        emit_mt_mint(&env::predecessor_account_id(), &token_id, amount);
 
         launchpad.sell_supply -= amount;
         self.insert_launchpad_tokens(&launchpad);
+        Event::LaunchpadPurchase(vec![LaunchpadPurchase {
+            token_id: token_id.clone(),
+            buyer_id: env::predecessor_account_id(),
+            amount: U128(amount),
+            price: U128(launchpad.price),
+        }]).emit();
 
         if launchpad.sell_supply == 0{
             self.finalize_launchpad(token_id);
         }
+        self.charge_storage(&env::predecessor_account_id(), initial_storage);
     }
 
     pub fn finalize_my_launchpad(&mut self, token_id: TokenId) {
         let launchpad = self.get_launchpad(token_id.clone());
-        println!("{} {}", launchpad.token.owner_id, env::predecessor_account_id());
         if launchpad.token.owner_id != env::predecessor_account_id() {
             env::panic(b"To end the launchpad, you need to be the creator of the launchpad")
         }
@@ -468,16 +1190,20 @@ impl Contract {
     fn finalize_launchpad(&mut self, token_id: TokenId) {
         let launchpad = self.get_launchpad(token_id.clone());
 
-        let excess = launchpad.token.supply - launchpad.sell_supply;
-        let owner_launchpad = launchpad.token.owner_id.clone();
-        self.add_token(launchpad.token);
-
-        self.transfer_from_user(
-            owner_launchpad,
-            "000000000".to_string(),
-            token_id.clone(),
-            U128(excess)
-        );
+        // проданные токены уже на кошельках покупателей, непроданные остаются у эмитента
+        self.register_token(&launchpad.token);
+        let owner_id = launchpad.token.owner_id.clone();
+        let mut account = self.get_account(&owner_id, token_id.clone());
+        account.balance += launchpad.sell_supply;
+        if let Some(terms) = launchpad.vesting {
+            account.vesting = Some(VestingSchedule {
+                total: launchpad.sell_supply,
+                start: env::block_timestamp(),
+                terms,
+            });
+        }
+        self.set_account(&owner_id, &account, &token_id);
+        emit_mt_mint(&owner_id, &token_id, launchpad.sell_supply);
 
         self.launchpad.remove(&token_id.clone());
     }
@@ -492,29 +1218,107 @@ impl Contract {
     pub fn get_tokens(&self) -> Vec<Token> {
         let mut result: Vec<Token> = Vec::new();
         for token in self.tokens.values() {
-            result.push(token);
+            if self.markets.get(&token.token_id) != Some(MarketStatus::Delisted) {
+                result.push(token);
+            }
         }
         result
     }
 
-    #[private]
     pub fn add_token(&mut self, token: Token) {
-        self.tokens.insert(&token.token_id, &token);
+        self.access.assert_role(Role::ListingManager);
+        self.internal_add_token(token);
+    }
+
+    /// Регистрирует токен и зачисляет весь выпуск эмитенту
+    fn internal_add_token(&mut self, token: Token) {
+        self.register_token(&token);
         let token_id = token.token_id;
         let owner_id = &token.owner_id;
         let mut account = self.get_account(owner_id, token_id.clone());
-        account.balance = token.supply;
+        account.balance = token.total_supply;
         self.set_account(&token.owner_id, &account, &token_id.clone());
-        let orderbook = Orderbook::new(token_id.clone(), self.get_standard_token());
-        self.order_books.insert(&token_id.clone(), &orderbook);
+        emit_mt_mint(owner_id, &token_id, token.total_supply);
     }
 
-    fn get_standard_token(&self) -> String {
-        "XDHO".to_string()
+    /// Выпускает `amount` новых токенов на кошелек `receiver_id` (по умолчанию эмитента).
+    /// Доступно только эмитенту токена и ограничено `max_supply`.
+    pub fn mint(&mut self, token_id: TokenId, amount: U128, receiver_id: Option<AccountId>) {
+        self.paused.assert_not_paused(Subsystem::Transfers);
+        let token = self.get_token(&token_id);
+        if token.owner_id != env::predecessor_account_id() {
+            env::panic(b"Only the token issuer can mint");
+        }
+        if token.origin != TokenOrigin::Native {
+            env::panic(b"Only native tokens can be minted");
+        }
+        if amount.0 == 0 {
+            env::panic(b"Can't mint 0 tokens");
+        }
+        if let Some(max_supply) = token.max_supply {
+            if token.total_supply + amount.0 > max_supply {
+                env::panic(b"Mint exceeds the max supply of the token");
+            }
+        }
+        let initial_storage = env::storage_usage();
+        let receiver_id = receiver_id.unwrap_or(token.owner_id);
+        self.internal_deposit(&receiver_id, &token_id, amount.0);
+        self.charge_storage(&env::predecessor_account_id(), initial_storage);
+    }
+
+    /// Сжигает `amount` токенов с кошелька вызывающего.
+    pub fn burn(&mut self, token_id: TokenId, amount: U128) {
+        self.paused.assert_not_paused(Subsystem::Transfers);
+        if self.get_token(&token_id).origin != TokenOrigin::Native {
+            env::panic(b"Only native tokens can be burned");
+        }
+        if amount.0 == 0 {
+            env::panic(b"Can't burn 0 tokens");
+        }
+        self.internal_withdraw(&env::predecessor_account_id(), &token_id, amount.0);
+    }
+
+    /// Эмитент меняет описание, иконку и внешние ссылки токена,
+    /// не чаще раза в `METADATA_UPDATE_COOLDOWN`.
+    pub fn update_token_metadata(&mut self, token_id: TokenId, update: TokenMetadataUpdate) {
+        let token = self.get_token(&token_id);
+        if token.owner_id != env::predecessor_account_id() {
+            env::panic(b"Only the token issuer can update metadata");
+        }
+        if let Some(updated) = self.metadata_updated.get(&token_id) {
+            if env::block_timestamp() < updated + METADATA_UPDATE_COOLDOWN {
+                env::panic(b"Metadata was updated recently, try again later");
+            }
+        }
+        let initial_storage = env::storage_usage();
+        let mut meta = token.meta;
+        meta.apply(update);
+        self.metadata_updated.insert(&token_id, &env::block_timestamp());
+        self.internal_set_metadata(token_id, meta, env::predecessor_account_id());
+        self.charge_storage(&env::predecessor_account_id(), initial_storage);
+    }
+
+    /// `updated_by` - эмитент или сама биржа, если метаданные заменены голосованием
+    fn internal_set_metadata(&mut self, token_id: TokenId, meta: TokenMetadata, updated_by: AccountId) {
+        let mut token = self.get_token(&token_id);
+        token.meta = meta;
+        self.tokens.insert(&token_id, &token);
+        Event::TokenMetadataUpdate(vec![TokenMetadataUpdated {
+            token_id,
+            updated_by,
+            meta: token.meta,
+        }]).emit();
+    }
+
+    /// Сохраняет токен и создает для него стакан против стандартного токена
+    fn register_token(&mut self, token: &Token) {
+        self.tokens.insert(&token.token_id, token);
+        let orderbook = Orderbook::new(token.token_id.clone(), self.get_standard_token());
+        self.order_books.insert(&token.token_id, &orderbook);
     }
 
-    fn get_standard_decimal(&self) -> i8 {
-        2
+    fn get_standard_token(&self) -> String {
+        "XDHO".to_string()
     }
 
     fn get_wallet(&self, owner_id: &AccountId) -> TokenWallet {
@@ -527,971 +1331,4327 @@ impl Contract {
     /// Helper method to get the account details for `owner_id`.
     fn get_account(&self, owner_id: &AccountId, token_id: TokenId) -> TokenAccount {
         self.get_wallet(owner_id)
-            .get_account(&token_id)
-            .unwrap_or(TokenAccount::new(env::sha256(owner_id.as_bytes())))
+            .get_account(owner_id, &token_id)
+            .unwrap_or_else(|| TokenAccount::new(owner_id, &token_id))
     }
 
     /// Helper method to set the account details for `owner_id` to the state.
+    /// Заодно обновляет список держателей и сохраняет старый баланс для последнего снимка.
     fn set_account(
         &mut self,
         owner_id: &AccountId,
         account: &TokenAccount,
         token_id: &TokenId,
     ) {
+        let old_balance = self.get_account(owner_id, token_id.clone()).balance;
+        if old_balance != account.balance {
+            self.record_snapshot_balance(owner_id, token_id, old_balance);
+            self.settle_dividends(owner_id, token_id, old_balance);
+            if (old_balance == 0) != (account.balance == 0) {
+                let mut holders = self.get_holder_set(token_id);
+                if account.balance > 0 {
+                    holders.insert(owner_id);
+                } else {
+                    holders.remove(owner_id);
+                    self.record_holder_exit(owner_id, token_id);
+                }
+                self.holders.insert(token_id, &holders);
+            }
+        }
         self.get_wallet(owner_id).set_account(
             token_id,
             &account,
         )
     }
-}
 
-fn parse_side(side: &str) -> Option<OrderSide> {
-    match side {
-        "Ask" => Some(OrderSide::Ask),
-        "Bid" => Some(OrderSide::Bid),
-        _ => env::panic(b"Side not parsed!"),
-    }
-}
+    /// Переводит `amount` токенов от `owner_id` к `new_owner_id` без проверки allowance.
+    fn internal_transfer(
+        &mut self,
+        owner_id: &AccountId,
+        new_owner_id: &AccountId,
+        token_id: &TokenId,
+        amount: Balance,
+        memo: Option<String>,
+    ) {
+        // Retrieving the account from the state.
+        let mut account = self.get_account(owner_id, token_id.clone());
 
-fn get_current_time() -> u64 {
-    return env::block_timestamp();
+        // Checking and updating unlocked balance
+        if account.available_balance() < amount {
+            env::panic(b"Not enough balance");
+        }
+        account.balance -= amount;
+
+        // Saving the account back to the state.
+        self.set_account(owner_id, &account, token_id);
+
+        // Deposit amount to the new owner and save the new account to the state.
+        let mut new_account = self.get_account(new_owner_id, token_id.clone());
+        new_account.balance += amount;
+        self.set_account(new_owner_id, &new_account, token_id);
+
+        Event::MtTransfer(vec![MtTransfer::new(owner_id, new_owner_id, token_id, amount, memo)]).emit();
+    }
 }
 
+/// Секция массовых переводов и раздач токенов
 #[near_bindgen]
 impl Contract {
-    pub fn new_ask_limit_order(&mut self, token_id: TokenId, price: f64, quantity: u128) -> Vec<Result<Success, Failed>> {
-        self.new_limit_order(token_id, price, quantity, "Ask".to_string())
+    /// Переводит токены сразу нескольким получателям, баланс отправителя проверяется один раз.
+    pub fn batch_transfer(&mut self, token_id: TokenId, transfers: Vec<(AccountId, U128)>) {
+        self.paused.assert_not_paused(Subsystem::Transfers);
+        if transfers.is_empty() || transfers.len() as u64 > MAX_BATCH_SIZE {
+            env::panic(format!("Batch must contain from 1 to {} transfers", MAX_BATCH_SIZE).as_bytes());
+        }
+        let initial_storage = env::storage_usage();
+        let owner_id = env::predecessor_account_id();
+        let transfers = transfers.into_iter().map(|(receiver_id, amount)| (receiver_id, amount.0)).collect();
+        self.internal_batch_transfer(&owner_id, &token_id, transfers);
+        self.charge_storage(&owner_id, initial_storage);
     }
 
-    pub fn new_bid_limit_order(&mut self, token_id: TokenId, price: f64, quantity: u128) -> Vec<Result<Success, Failed>> {
-        self.new_limit_order(token_id, price, quantity, "Bid".to_string())
+    /// Эмитент `token_id` раздает `amount` токенов держателям токена снимка `snapshot_id`
+    /// пропорционально их балансам на момент снимка. Получатели берутся из индекса держателей
+    /// и журнала выбывших из него после снимка, так что долю получает и тот, кто уже все продал.
+    /// Токены сразу списываются на контракт, сама раздача идет частями через `process_airdrop`.
+    pub fn create_airdrop(&mut self, token_id: TokenId, amount: U128, snapshot_id: SnapshotId) -> AirdropId {
+        self.paused.assert_not_paused(Subsystem::Transfers);
+        let owner_id = env::predecessor_account_id();
+        if self.get_token(&token_id).owner_id != owner_id {
+            env::panic(b"Only the token issuer can create an airdrop");
+        }
+        if amount.0 == 0 {
+            env::panic(b"Can't airdrop 0 tokens");
+        }
+        let snapshot = self.get_snapshot_internal(snapshot_id);
+        let initial_storage = env::storage_usage();
+
+        let airdrop_id = self.next_airdrop_id;
+        self.next_airdrop_id += 1;
+        let mut airdrop = Airdrop::new(airdrop_id, owner_id.clone(), token_id.clone(), amount.0, snapshot_id, snapshot.token_id.clone());
+        // токены на счете самого контракта в раздаче не участвуют
+        airdrop.total_basis = snapshot.total_supply - self.internal_balance_at(snapshot_id, &env::current_account_id());
+        airdrop.remaining = self.get_holder_set(&snapshot.token_id).len();
+        airdrop.next_exit = snapshot.holder_exits;
+        if airdrop.total_basis == 0 {
+            env::panic(b"Nobody held the basis token at the snapshot");
+        }
+        if amount.0.checked_mul(airdrop.total_basis).is_none() {
+            env::panic(b"Airdrop amount is too large");
+        }
+        self.internal_transfer(&owner_id, &env::current_account_id(), &token_id, amount.0, None);
+        self.airdrops.insert(&airdrop_id, &airdrop);
+        self.charge_storage(&owner_id, initial_storage);
+        airdrop_id
     }
 
-    /// Создает новый лимитный ордер:
-    /// * 'side':
-    /// Ask - заявка на продажу
-    /// Bid - заявка на покупку
-    pub fn new_limit_order(&mut self, token_id: TokenId, price: f64, quantity: u128, side: String) -> Vec<Result<Success, Failed>> {
-        let side = parse_side(side.as_str()).unwrap();
-        let token = match side {
-            OrderSide::Bid => self.get_standard_token(),
-            OrderSide::Ask => token_id.clone(),
-        };
-        let amount = match side {
-            OrderSide::Bid => (price * quantity.to_f64().unwrap()).to_u128().unwrap(),
-            OrderSide::Ask => quantity,
+    /// Делает следующие `limit` шагов раздачи (не больше `MAX_BATCH_SIZE`), пока хватает газа.
+    /// Может вызвать кто угодно. Когда пройдены индекс держателей и журнал выбывших, остаток
+    /// возвращается эмитенту, а отметки о выплатах снимаются. Возвращает `true`, если раздача закончена.
+    pub fn process_airdrop(&mut self, airdrop_id: AirdropId, limit: Option<u64>) -> bool {
+        self.paused.assert_not_paused(Subsystem::Transfers);
+        let mut airdrop = match self.airdrops.get(&airdrop_id) {
+            Some(airdrop) => airdrop,
+            None => env::panic(b"Airdrop not found"),
         };
-        /**
-        Цена бид – это цена спроса или максимальная цена, по которой покупатель согласен купить товар.
-        Покупатель не хочет покупать дорого. Это логика закона спроса и предложения
-
-        Цена аск – это цена предложения или наименьшая цена, по которой продавец согласен продать товар.
-        Продавец не хочет продавать дешево
-         */
-        match side {
-            OrderSide::Ask => {
-                println!(
-                    "New limit order на продажу {} ${} по цене {} от signer: {}",
-                    quantity,
-                    token_id,
-                    price,
-                    env::signer_account_id(),
-                );
+        let initial_storage = env::storage_usage();
+        let limit = limit.unwrap_or(MAX_BATCH_SIZE).min(MAX_BATCH_SIZE);
+        let holders = self.get_holder_set(&airdrop.basis_token_id);
+        let exits = self.get_holder_exits(&airdrop.basis_token_id);
+        let mut transfers = Vec::new();
+        let mut steps = 0;
+        while !airdrop.is_finished(exits.len()) && steps < limit {
+            if env::used_gas() + GAS_PER_AIRDROP_TRANSFER * (steps + 2) > env::prepaid_gas() {
+                break;
             }
-            OrderSide::Bid => {
-                println!(
-                    "New limit order на покупку {} ${} по цене {} от signer: {}",
-                    quantity,
-                    token_id,
-                    price,
-                    env::signer_account_id(),
-                );
+            steps += 1;
+            // с конца: новые держатели добавляются в конец, а на место удаленного встает последний,
+            // сам удаленный попадает в журнал выбывших
+            airdrop.remaining = airdrop.remaining.min(holders.len());
+            let holder_id = if airdrop.remaining > 0 {
+                airdrop.remaining -= 1;
+                holders.as_vector().get(airdrop.remaining).unwrap()
+            } else if airdrop.next_exit < exits.len() {
+                airdrop.next_exit += 1;
+                exits.get(airdrop.next_exit - 1).unwrap()
+            } else {
+                let last = airdrop.paid.as_vector().get(airdrop.paid.len() - 1).unwrap();
+                airdrop.paid.remove(&last);
+                continue;
+            };
+            if holder_id == env::current_account_id() {
+                continue;
+            }
+            let basis = self.internal_balance_at(airdrop.snapshot_id, &holder_id);
+            if basis == 0 || !airdrop.paid.insert(&holder_id) {
+                continue;
             }
+            let share = airdrop.share(basis).min(airdrop.amount - airdrop.distributed);
+            if share > 0 {
+                transfers.push((holder_id, share));
+                airdrop.distributed += share;
+            }
+        }
+        if airdrop.remaining == 0 && airdrop.next_exit >= exits.len() && airdrop.distributed < airdrop.amount {
+            transfers.push((airdrop.owner_id.clone(), airdrop.amount - airdrop.distributed));
+            airdrop.distributed = airdrop.amount;
+        }
+        if !transfers.is_empty() {
+            self.internal_batch_transfer(&env::current_account_id(), &airdrop.token_id, transfers);
         }
 
-        self.transfer_from(
-            env::signer_account_id(),
-            env::current_account_id(),
-            token,
-            U128(amount),
-        );
-        self.post_transfer(token_id, price, quantity, side)
+        let finished = airdrop.is_finished(exits.len());
+        if finished {
+            self.airdrops.remove(&airdrop_id);
+        } else {
+            self.airdrops.insert(&airdrop_id, &airdrop);
+        }
+        // новые кошельки получателей оплачивает эмитент
+        self.charge_storage(&airdrop.owner_id, initial_storage);
+        finished
     }
 
-    #[private]
-    fn post_transfer(&mut self, token_id: TokenId, price: f64, quantity: u128, side: OrderSide) -> Vec<Result<Success, Failed>> {
-        env::log(b"Token Transfer Successful.");
-        let order = orders::new_limit_order_request(
-            token_id.clone(),
-            self.get_standard_token(),
-            side,
-            price,
-            quantity,
-            env::signer_account_id(),
-            get_current_time(),
-        );
+    pub fn get_airdrop(&self, airdrop_id: AirdropId) -> AirdropView {
+        let airdrop = match self.airdrops.get(&airdrop_id) {
+            Some(airdrop) => airdrop,
+            None => env::panic(b"Airdrop not found"),
+        };
+        AirdropView {
+            airdrop_id,
+            owner_id: airdrop.owner_id.clone(),
+            token_id: airdrop.token_id.clone(),
+            amount: U128(airdrop.amount),
+            snapshot_id: airdrop.snapshot_id,
+            basis_token_id: airdrop.basis_token_id.clone(),
+            remaining: airdrop.remaining,
+            distributed: U128(airdrop.distributed),
+        }
+    }
 
-        let mut order_book = self.order_books.get(&token_id).unwrap();
-        let res = order_book.process_order(order);
-        self.order_books.insert(&token_id, &order_book);
+    /// Списывает сумму всех переводов с `owner_id` одним разом и зачисляет получателям.
+    /// Пишет одно событие `mt_transfer` на все переводы.
+    fn internal_batch_transfer(&mut self, owner_id: &AccountId, token_id: &TokenId, transfers: Vec<(AccountId, Balance)>) {
+        let mut total: Balance = 0;
+        for (_, amount) in transfers.iter() {
+            if *amount == 0 {
+                env::panic(b"Can't transfer 0 tokens");
+            }
+            total = match total.checked_add(*amount) {
+                Some(total) => total,
+                None => env::panic(b"Total amount overflow"),
+            };
+        }
+        let mut account = self.get_account(owner_id, token_id.clone());
+        if account.available_balance() < total {
+            env::panic(b"Not enough balance");
+        }
+        account.balance -= total;
+        self.set_account(owner_id, &account, token_id);
+
+        let mut events = Vec::new();
+        for (receiver_id, amount) in transfers {
+            let mut receiver = self.get_account(&receiver_id, token_id.clone());
+            receiver.balance += amount;
+            self.set_account(&receiver_id, &receiver, token_id);
+            events.push(MtTransfer::new(owner_id, &receiver_id, token_id, amount, None));
+        }
+        Event::MtTransfer(events).emit();
+    }
+}
 
-        self.process_orderbook_result(token_id, res)
+/// Секция держателей токенов и снимков балансов
+#[near_bindgen]
+impl Contract {
+    pub fn get_holder_count(&self, token_id: TokenId) -> u64 {
+        self.get_holder_set(&token_id).len()
     }
 
-    /// Создает новый рыночный ордер:
-    /// * 'side':
-    /// Ask - заявка на продажу
-    /// Bid - заявка на покупку
-    pub fn new_market_order(&mut self, token_id: TokenId, quantity: u128, side: String) -> Vec<Result<Success, Failed>> {
-        let side = parse_side(side.as_str()).unwrap();
-        println!(
-            "New рыночн order на {} {} ${} от signer: {}",
-            match side {
-                OrderSide::Ask => { "продажу" }
-                OrderSide::Bid => { "покупку" }
-            },
-            quantity,
-            token_id,
-            env::signer_account_id(),
-        );
+    /// Возвращает держателей `token_id` с их балансами, начиная с `from`.
+    pub fn get_holders(&self, token_id: TokenId, from: u64, limit: u64) -> Vec<(AccountId, U128)> {
+        let holders = self.get_holder_set(&token_id);
+        let holders = holders.as_vector();
+        (from..std::cmp::min(from.saturating_add(limit), holders.len()))
+            .filter_map(|index| holders.get(index))
+            .map(|owner_id| {
+                let balance = self.get_account(&owner_id, token_id.clone()).balance;
+                (owner_id, U128(balance))
+            })
+            .collect()
+    }
 
-        // для продажи сразу переводим, для покупки будем переводить потом, когда будем знать цену
-        match side {
-            OrderSide::Ask => {
-                self.transfer_from(
-                    env::signer_account_id(),
-                    env::current_account_id(),
-                    token_id.clone(),
-                    U128::from(quantity),
-                );
-            }
-            _ => {}
-        };
+    /// Фиксирует балансы `token_id` на текущем блоке. Доступно эмитенту и самому контракту.
+    pub fn snapshot(&mut self, token_id: TokenId) -> SnapshotId {
+        let token = self.get_token(&token_id);
+        let caller = env::predecessor_account_id();
+        if caller != token.owner_id && caller != env::current_account_id() {
+            env::panic(b"Only the token issuer can take a snapshot");
+        }
+        let initial_storage = env::storage_usage();
+        let snapshot_id = self.internal_snapshot(&token_id);
+        if caller != env::current_account_id() {
+            self.charge_storage(&caller, initial_storage);
+        }
+        snapshot_id
+    }
 
-        let order = orders::new_market_order_request(
-            token_id.clone(),
-            self.get_standard_token(),
-            side,
-            quantity,
-            env::signer_account_id(),
-            get_current_time(),
-        );
+    pub fn get_snapshot(&self, snapshot_id: SnapshotId) -> SnapshotView {
+        let snapshot = self.get_snapshot_internal(snapshot_id);
+        SnapshotView {
+            snapshot_id,
+            token_id: snapshot.token_id,
+            block_index: snapshot.block_index,
+            timestamp: snapshot.timestamp,
+            total_supply: U128(snapshot.total_supply),
+        }
+    }
 
-        let mut order_book = self.order_books.get(&token_id.clone()).unwrap();
-        let res = order_book.process_order(order);
-        self.order_books.insert(&token_id.clone(), &order_book);
+    /// Баланс `owner_id` на момент снимка `snapshot_id`.
+    pub fn get_balance_at(&self, snapshot_id: SnapshotId, owner_id: AccountId) -> U128 {
+        U128(self.internal_balance_at(snapshot_id, &owner_id))
+    }
 
-        self.process_orderbook_result(token_id, res)
+    fn internal_snapshot(&mut self, token_id: &TokenId) -> SnapshotId {
+        let snapshot_id = self.snapshots.len();
+        self.snapshots.push(&Snapshot {
+            token_id: token_id.clone(),
+            block_index: env::block_index(),
+            timestamp: env::block_timestamp(),
+            total_supply: self.get_token(token_id).total_supply,
+            holder_exits: self.get_holder_exits(token_id).len(),
+        });
+        let mut token_snapshots = self.get_token_snapshots(token_id);
+        token_snapshots.push(&snapshot_id);
+        self.token_snapshots.insert(token_id, &token_snapshots);
+        self.latest_snapshot.insert(token_id, &snapshot_id);
+        snapshot_id
     }
 
-    pub fn cancel_limit_order(
-        &mut self,
-        token_id: TokenId,
-        id: u64,
-        side: String,
-    ) -> Vec<Result<Success, Failed>> {
-        let order = orders::limit_order_cancel_request(id, parse_side(&side).unwrap());
-        let mut order_book = self.order_books.get(&token_id).unwrap();
-        let res = order_book.process_order(order);
-        self.order_books.insert(&token_id, &order_book);
-        res
+    fn get_snapshot_internal(&self, snapshot_id: SnapshotId) -> Snapshot {
+        match self.snapshots.get(snapshot_id) {
+            Some(snapshot) => snapshot,
+            None => env::panic(b"Snapshot not found"),
+        }
     }
 
-    pub fn get_ask_orders(&self, token_id: TokenId) -> Vec<Order> {
-        let order_book = self.order_books.get(&token_id).unwrap();
-        Vec::from_iter(order_book.ask_queue.clone().orders.into_values())
+    /// Ищет первое сохраненное значение в этом или более позднем снимке токена.
+    /// Если баланс не менялся после снимка, это текущий баланс.
+    fn internal_balance_at(&self, snapshot_id: SnapshotId, owner_id: &AccountId) -> Balance {
+        let token_id = self.get_snapshot_internal(snapshot_id).token_id;
+        let token_snapshots = self.get_token_snapshots(&token_id);
+        // позиция снимка в истории токена: история отсортирована, ищем делением пополам
+        let (mut low, mut high) = (0, token_snapshots.len());
+        while low < high {
+            let middle = (low + high) / 2;
+            if token_snapshots.get(middle).unwrap() < snapshot_id {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        for index in low..token_snapshots.len() {
+            let id = token_snapshots.get(index).unwrap();
+            if let Some(balance) = self.snapshot_balances.get(&snapshot_balance_key(id, owner_id)) {
+                return balance;
+            }
+        }
+        self.get_account(owner_id, token_id).balance
     }
 
-    pub fn get_orders(&self, account_id: AccountId, token_id: TokenId, side: String) -> Vec<Order> {
-        let side = parse_side(side.as_str()).unwrap();
-        let order_book = self.order_books.get(&token_id);
-        match order_book {
-            Some(t) => (t.get_orders(account_id, side)),
-            None => env::panic(b"OrderBook not init!"),
+    /// Сохраняет баланс до первого изменения после последнего снимка токена
+    fn record_snapshot_balance(&mut self, owner_id: &AccountId, token_id: &TokenId, old_balance: Balance) {
+        let last_snapshot = match self.latest_snapshot.get(token_id) {
+            Some(snapshot_id) => snapshot_id,
+            None => return,
+        };
+        let key = snapshot_balance_key(last_snapshot, owner_id);
+        if !self.snapshot_balances.contains_key(&key) {
+            self.snapshot_balances.insert(&key, &old_balance);
         }
     }
 
-    pub fn get_bid_orders(&self, token_id: TokenId) -> Vec<Order> {
-        let order_book = self.order_books.get(&token_id).unwrap();
-        Vec::from_iter(order_book.bid_queue.clone().orders.into_values())
+    fn get_token_snapshots(&self, token_id: &TokenId) -> Vector<SnapshotId> {
+        self.token_snapshots.get(token_id).unwrap_or_else(|| {
+            let mut prefix = b"th".to_vec();
+            prefix.extend(env::sha256(token_id.as_bytes()));
+            Vector::new(prefix)
+        })
     }
 
-    pub fn get_current_spread(&self, token_id: TokenId) -> Vec<f64> {
-        let order_book = self.order_books.get(&token_id).unwrap();
-        if let Some((bid, ask)) = order_book.clone().current_spread() {
-            vec![ask, bid]
-        } else {
-            vec![0.0, 0.0]
+    /// Запоминает выбывшего держателя, если у токена есть снимки: по ним ему еще положены раздачи
+    fn record_holder_exit(&mut self, owner_id: &AccountId, token_id: &TokenId) {
+        if !self.latest_snapshot.contains_key(token_id) {
+            return;
         }
+        let mut exits = self.get_holder_exits(token_id);
+        exits.push(owner_id);
+        self.holder_exits.insert(token_id, &exits);
     }
 
-    fn transfer_from_contract(&mut self, new_owner_id: AccountId, token_id: TokenId, amount: U128) {
-        self.transfer_from_user(env::current_account_id(), new_owner_id, token_id, amount);
+    fn get_holder_exits(&self, token_id: &TokenId) -> Vector<AccountId> {
+        self.holder_exits.get(token_id).unwrap_or_else(|| {
+            let mut prefix = b"he".to_vec();
+            prefix.extend(env::sha256(token_id.as_bytes()));
+            Vector::new(prefix)
+        })
     }
 
-    fn transfer_from_user(&mut self, owner_id: AccountId, new_owner_id: AccountId, token_id: TokenId, amount: U128) {
-        let mut acc = self.get_account(&owner_id.clone(), token_id.clone());
-        let in_acc = env::predecessor_account_id();
-        let allownce = acc.get_allowance(&in_acc.clone());
-        acc.set_allowance(&in_acc.clone(), allownce + amount.0);
-        self.set_account(&owner_id.clone(), &acc, &token_id.clone());
+    fn get_holder_set(&self, token_id: &TokenId) -> UnorderedSet<AccountId> {
+        self.holders.get(token_id).unwrap_or_else(|| {
+            let mut prefix = b"hs".to_vec();
+            prefix.extend(env::sha256(token_id.as_bytes()));
+            UnorderedSet::new(prefix)
+        })
+    }
+}
 
-        self.transfer_from(
-            owner_id,
-            new_owner_id,
-            token_id.clone(),
+/// Секция дивидендов: эмитент делится выручкой в стандартном токене с держателями своего токена
+#[near_bindgen]
+impl Contract {
+    /// Эмитент вносит `amount` стандартного токена, которые делятся между держателями `token_id`
+    /// пропорционально балансам на момент внесения. Токены в эскроу биржи в расчете не участвуют.
+    pub fn deposit_dividends(&mut self, token_id: TokenId, amount: U128) {
+        let owner_id = env::predecessor_account_id();
+        let token = self.get_token(&token_id);
+        if token.owner_id != owner_id {
+            env::panic(b"Only the token issuer can deposit dividends");
+        }
+        if token_id == self.get_standard_token() {
+            env::panic(b"Dividends can't be paid on the standard token");
+        }
+        if amount.0 == 0 {
+            env::panic(b"Can't deposit 0 dividends");
+        }
+        let shares = token.total_supply - self.get_account(&env::current_account_id(), token_id.clone()).balance;
+        if shares == 0 {
+            env::panic(b"The token has no holders");
+        }
+        let per_share_increase = match amount.0.checked_mul(DIVIDEND_PRECISION) {
+            Some(value) => value / shares,
+            None => env::panic(b"Dividend overflow"),
+        };
+        let initial_storage = env::storage_usage();
+        self.internal_transfer(&owner_id, &env::current_account_id(), &self.get_standard_token(), amount.0, None);
+        let per_share = self.dividend_per_share.get(&token_id).unwrap_or(0) + per_share_increase;
+        self.dividend_per_share.insert(&token_id, &per_share);
+        Event::DividendsDeposit(vec![DividendsDeposit {
+            token_id,
             amount,
-        );
+        }]).emit();
+        self.charge_storage(&owner_id, initial_storage);
     }
 
-    fn process_orderbook_result(
+    /// Выводит начисленные дивиденды по `token_id` на кошелек вызывающего.
+    pub fn claim_dividends(&mut self, token_id: TokenId) -> U128 {
+        let account_id = env::predecessor_account_id();
+        let initial_storage = env::storage_usage();
+        let balance = self.get_account(&account_id, token_id.clone()).balance;
+        self.settle_dividends(&account_id, &token_id, balance);
+
+        let key = dividend_account_key(&account_id, &token_id);
+        let mut dividends = self.dividend_accounts.get(&key).unwrap_or_default();
+        let amount = dividends.unclaimed;
+        if amount == 0 {
+            env::panic(b"No dividends to claim");
+        }
+        dividends.unclaimed = 0;
+        self.dividend_accounts.insert(&key, &dividends);
+        self.internal_transfer(&env::current_account_id(), &account_id, &self.get_standard_token(), amount, None);
+        Event::DividendsClaim(vec![DividendsClaim {
+            account_id: account_id.clone(),
+            token_id,
+            amount: U128(amount),
+        }]).emit();
+        self.charge_storage(&account_id, initial_storage);
+        U128(amount)
+    }
+
+    /// Сколько дивидендов по `token_id` может вывести `account_id`.
+    pub fn get_unclaimed_dividends(&self, account_id: AccountId, token_id: TokenId) -> U128 {
+        let mut dividends = self.dividend_accounts
+            .get(&dividend_account_key(&account_id, &token_id))
+            .unwrap_or_default();
+        let per_share = self.dividend_per_share.get(&token_id).unwrap_or(0);
+        dividends.settle(self.get_account(&account_id, token_id).balance, per_share);
+        U128(dividends.unclaimed)
+    }
+
+    /// Досчитывает дивиденды на баланс `balance`, который был до его изменения
+    fn settle_dividends(&mut self, account_id: &AccountId, token_id: &TokenId, balance: Balance) {
+        if account_id == &env::current_account_id() {
+            return;
+        }
+        let per_share = match self.dividend_per_share.get(token_id) {
+            Some(per_share) => per_share,
+            None => return,
+        };
+        let key = dividend_account_key(account_id, token_id);
+        let mut dividends = self.dividend_accounts.get(&key).unwrap_or_default();
+        if dividends.per_share_paid == per_share {
+            return;
+        }
+        dividends.settle(balance, per_share);
+        self.dividend_accounts.insert(&key, &dividends);
+    }
+}
+
+/// Секция вестинга: заблокированные токены эмитента и команды
+#[near_bindgen]
+impl Contract {
+    /// Эмитент переводит `amount` токенов `account_id`, которые разблокируются по `terms`
+    /// начиная со `start` (по умолчанию сейчас).
+    pub fn create_vesting(
         &mut self,
+        account_id: AccountId,
         token_id: TokenId,
-        order: Vec<Result<Success, Failed>>,
-    ) -> Vec<Result<Success, Failed>> {
-        for temp_variable in &order {
-            let success = temp_variable.as_ref().unwrap();
-
-            match success {
-                Success::Accepted {
-                    id,
-                    order_type: _,
-                    order_creator,
-                    ts: _,
-                } => {
-                    println!("Принят №{} ордер ${} от {}", id, token_id, order_creator);
+        amount: U128,
+        terms: VestingTerms,
+        start: Option<Timestamp>,
+    ) {
+        self.paused.assert_not_paused(Subsystem::Transfers);
+        let owner_id = env::predecessor_account_id();
+        if self.get_token(&token_id).owner_id != owner_id {
+            env::panic(b"Only the token issuer can create vesting");
+        }
+        if account_id == owner_id {
+            env::panic(b"Can't create vesting for yourself");
+        }
+        if amount.0 == 0 {
+            env::panic(b"Can't vest 0 tokens");
+        }
+        terms.assert_valid();
+        if self.get_account(&account_id, token_id.clone()).locked_balance() > 0 {
+            env::panic(b"The account already has an active vesting");
+        }
+        let initial_storage = env::storage_usage();
+        self.internal_transfer(&owner_id, &account_id, &token_id, amount.0, None);
+        let mut account = self.get_account(&account_id, token_id.clone());
+        account.vesting = Some(VestingSchedule {
+            total: amount.0,
+            start: start.unwrap_or_else(env::block_timestamp),
+            terms,
+        });
+        self.set_account(&account_id, &account, &token_id);
+        self.charge_storage(&owner_id, initial_storage);
+    }
+
+    /// Эмитент забирает еще не разблокированные токены `account_id`, если вестинг отзываемый.
+    pub fn revoke_vesting(&mut self, account_id: AccountId, token_id: TokenId) -> U128 {
+        let owner_id = env::predecessor_account_id();
+        if self.get_token(&token_id).owner_id != owner_id {
+            env::panic(b"Only the token issuer can revoke vesting");
+        }
+        let mut account = self.get_account(&account_id, token_id.clone());
+        let revocable = match &account.vesting {
+            Some(vesting) => vesting.terms.revocable,
+            None => env::panic(b"The account has no vesting"),
+        };
+        if !revocable {
+            env::panic(b"The vesting is not revocable");
+        }
+        let locked = account.locked_balance();
+        account.vesting = None;
+        self.set_account(&account_id, &account, &token_id);
+        if locked > 0 {
+            self.internal_transfer(&account_id, &owner_id, &token_id, locked, None);
+        }
+        U128(locked)
+    }
+
+    /// Вестинг по `token_id` на счете `account_id`.
+    pub fn get_vesting(&self, account_id: AccountId, token_id: TokenId) -> Option<VestingView> {
+        self.get_account(&account_id, token_id).vesting.as_ref().map(VestingView::from)
+    }
+
+    /// Вестинг непроданных токенов для заявок, которые будут одобрены после этого.
+    /// Меняется заявкой `SetLaunchpadVesting`.
+    pub fn get_launchpad_vesting(&self) -> Option<VestingTerms> {
+        self.launchpad_vesting.clone()
+    }
+}
+
+/// Секция управления доступом: роли и владение контрактом
+#[near_bindgen]
+impl Contract {
+    /// Admin выдает `role` аккаунту `account_id`.
+    pub fn grant_role(&mut self, role: Role, account_id: AccountId) {
+        self.access.grant_role(role, account_id);
+    }
+
+    /// Admin отзывает `role` у аккаунта `account_id`.
+    pub fn revoke_role(&mut self, role: Role, account_id: AccountId) {
+        self.access.revoke_role(role, account_id);
+    }
+
+    pub fn has_role(&self, role: Role, account_id: AccountId) -> bool {
+        self.access.has_role(role, &account_id)
+    }
+
+    pub fn get_role_members(&self, role: Role) -> Vec<AccountId> {
+        self.access.get_role_members(role)
+    }
+
+    /// Предлагает `new_owner_id` стать владельцем, владение переходит после `accept_ownership`.
+    pub fn transfer_ownership(&mut self, new_owner_id: AccountId) {
+        self.access.transfer_ownership(new_owner_id);
+    }
+
+    pub fn accept_ownership(&mut self) {
+        self.access.accept_ownership();
+    }
+
+    pub fn get_owner(&self) -> AccountId {
+        self.access.owner_id.clone()
+    }
+
+    pub fn get_pending_owner(&self) -> Option<AccountId> {
+        self.access.pending_owner_id.clone()
+    }
+
+    /// Pauser ставит `subsystem` на паузу или снимает с нее.
+    pub fn set_paused(&mut self, subsystem: Subsystem, paused: bool) {
+        self.access.assert_role(Role::Pauser);
+        self.paused.set(subsystem, paused);
+        Event::PauseChange(vec![PauseChanged {
+            subsystem,
+            paused,
+            changed_by: env::predecessor_account_id(),
+        }]).emit();
+    }
+
+    pub fn get_paused(&self) -> PauseFlags {
+        self.paused.clone()
+    }
+}
+
+/// Секция казны, комиссий и рынков. Казна пополняется комиссиями и тратится только по заявкам.
+#[near_bindgen]
+impl Contract {
+    /// FeeManager меняет ставки комиссий, их же можно поменять заявкой `ChangeFees`.
+    pub fn set_fee_rates(&mut self, fees: FeeRates) {
+        self.access.assert_role(Role::FeeManager);
+        fees.assert_valid();
+        self.internal_set_fee_rates(fees);
+    }
+
+    pub fn get_fee_rates(&self) -> FeeRates {
+        self.fees.clone()
+    }
+
+    pub fn get_treasury_balance(&self, token_id: TokenId) -> U128 {
+        U128(self.treasury.get(&token_id).unwrap_or(0))
+    }
+
+    pub fn get_treasury(&self) -> Vec<(TokenId, U128)> {
+        self.treasury.iter().map(|(token_id, amount)| (token_id, U128(amount))).collect()
+    }
+
+    pub fn get_market_status(&self, token_id: TokenId) -> Option<MarketStatus> {
+        self.markets.get(&token_id)
+    }
+
+    fn internal_set_fee_rates(&mut self, fees: FeeRates) {
+        self.fees = fees.clone();
+        Event::FeeRatesChange(vec![FeeRatesChanged {
+            fees,
+            changed_by: env::predecessor_account_id(),
+        }]).emit();
+    }
+
+    /// Переводит `amount` со счета `account_id` в казну
+    fn internal_to_treasury(&mut self, account_id: &AccountId, token_id: &TokenId, amount: Balance) {
+        let mut account = self.get_account(account_id, token_id.clone());
+        if account.available_balance() < amount {
+            env::panic(b"Not enough balance");
+        }
+        account.balance -= amount;
+        self.set_account(account_id, &account, token_id);
+        self.treasury.insert(token_id, &(self.treasury.get(token_id).unwrap_or(0) + amount));
+        Event::TreasuryDeposit(vec![TreasuryTransfer {
+            account_id: account_id.clone(),
+            token_id: token_id.clone(),
+            amount: U128(amount),
+        }]).emit();
+    }
+
+    fn internal_spend_treasury(&mut self, receiver_id: &AccountId, token_id: &TokenId, amount: Balance) {
+        let treasury_balance = self.treasury.get(token_id).unwrap_or(0);
+        if treasury_balance < amount {
+            env::panic(b"Not enough funds in the treasury");
+        }
+        self.treasury.insert(token_id, &(treasury_balance - amount));
+        let mut account = self.get_account(receiver_id, token_id.clone());
+        account.balance += amount;
+        self.set_account(receiver_id, &account, token_id);
+        Event::TreasurySpend(vec![TreasuryTransfer {
+            account_id: receiver_id.clone(),
+            token_id: token_id.clone(),
+            amount: U128(amount),
+        }]).emit();
+    }
+
+    fn internal_set_market_status(&mut self, token_id: &TokenId, status: Option<MarketStatus>) {
+        if self.markets.get(token_id) == Some(MarketStatus::Delisted) {
+            env::panic(b"This token is delisted");
+        }
+        match status {
+            Some(status) => self.markets.insert(token_id, &status),
+            None => self.markets.remove(token_id),
+        };
+        Event::MarketStatusChange(vec![MarketStatusChanged {
+            token_id: token_id.clone(),
+            status,
+        }]).emit();
+    }
+
+    fn assert_market_open(&self, token_id: &TokenId) {
+        match self.markets.get(token_id) {
+            Some(MarketStatus::Halted) => env::panic(b"The market of this token is halted"),
+            Some(MarketStatus::Delisted) => env::panic(b"This token is delisted"),
+            None => {}
+        }
+    }
+}
+
+/// Секция NEP-141 для каждого токена биржи
+#[near_bindgen]
+impl Contract {
+    /// Переводит `amount` токенов `token_id` от вызывающего к `receiver_id`.
+    /// Требует прикрепить ровно 1 yoctoNEAR.
+    #[payable]
+    pub fn ft_transfer(&mut self, receiver_id: AccountId, token_id: TokenId, amount: U128, memo: Option<String>) {
+        self.paused.assert_not_paused(Subsystem::Transfers);
+        assert_one_yocto();
+        let initial_storage = env::storage_usage();
+        let sender_id = env::predecessor_account_id();
+        self.internal_ft_transfer(&sender_id, &receiver_id, &token_id, amount, memo);
+        self.charge_storage(&sender_id, initial_storage);
+    }
+
+    /// Переводит токены к `receiver_id` и вызывает у него `ft_on_transfer`.
+    /// Неиспользованную получателем часть возвращает отправителю в `ft_resolve_transfer`.
+    #[payable]
+    pub fn ft_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        amount: U128,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        self.paused.assert_not_paused(Subsystem::Transfers);
+        assert_one_yocto();
+        if env::prepaid_gas() <= GAS_FOR_FT_TRANSFER_CALL {
+            env::panic(b"More gas is required");
+        }
+        let initial_storage = env::storage_usage();
+        let sender_id = env::predecessor_account_id();
+        self.internal_ft_transfer(&sender_id, &receiver_id, &token_id, amount, memo);
+        self.charge_storage(&sender_id, initial_storage);
+
+        ext_ft_receiver::ft_on_transfer(
+            sender_id.clone(),
+            token_id.clone(),
+            amount,
+            msg,
+            &receiver_id,
+            NO_DEPOSIT,
+            env::prepaid_gas() - GAS_FOR_FT_TRANSFER_CALL,
+        )
+            .then(ext_self::ft_resolve_transfer(
+                sender_id,
+                receiver_id,
+                token_id,
+                amount,
+                &env::current_account_id(),
+                NO_DEPOSIT,
+                GAS_FOR_RESOLVE_TRANSFER,
+            ))
+            .into()
+    }
+
+    /// Возвращает отправителю неиспользованные получателем токены.
+    /// Результат - сколько токенов в итоге осталось у получателя.
+    #[private]
+    pub fn ft_resolve_transfer(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        token_id: TokenId,
+        amount: U128,
+    ) -> U128 {
+        let amount = amount.0;
+        let unused_amount = match env::promise_result(0) {
+            PromiseResult::NotReady => env::panic(b"Promise result is not ready"),
+            PromiseResult::Successful(value) => {
+                match near_sdk::serde_json::from_slice::<U128>(&value) {
+                    Ok(unused_amount) => std::cmp::min(amount, unused_amount.0),
+                    Err(_) => amount,
                 }
-                Success::Filled {
-                    order_id: id,
-                    side,
-                    order_type,
-                    price,
-                    qty,
-                    order_creator,
-                    ts: _,
-                } => {
-                    let token = match *side {
-                        OrderSide::Ask => self.get_standard_token(),
-                        OrderSide::Bid => token_id.clone(),
-                    };
+            }
+            PromiseResult::Failed => amount,
+        };
 
-                    println!("Выполнен ордер №{} от {} {} {} ${}",
-                             id,
-                             order_creator,
-                             match side {
-                                 OrderSide::Bid => "на покупку",
-                                 OrderSide::Ask => "на продажу",
-                             },
-                             *qty,
-                             token_id,
-                    );
-                    match *order_type {
-                        OrderType::Limit => {
-                            match side {
-                                OrderSide::Bid => {
-                                    self.transfer_from_contract(
-                                        order_creator.to_string(),
-                                        token,
-                                        U128::from(*qty),
-                                    );
-                                }
-                                OrderSide::Ask => {
-                                    let amount = U128::from((price * (*qty).to_f64().unwrap()).to_u128().unwrap());
-                                    self.transfer_from_contract(
-                                        order_creator.to_string(),
-                                        token,
-                                        amount,
-                                    );
-                                }
-                            };
-                        }
-                        OrderType::Market => {
-                            match side {
-                                OrderSide::Bid => {
-                                    let amount = U128::from((price * (*qty).to_f64().unwrap()).to_u128().unwrap());
-                                    // переводим токены на контракт, откуда их возьмет продавец
-                                    self.transfer(
-                                        env::current_account_id(),
-                                        self.get_standard_token(),
-                                        amount,
-                                    );
-                                    self.transfer_from_contract(
-                                        order_creator.to_string(),
-                                        token,
-                                        U128::from(*qty),
-                                    );
-                                }
-                                OrderSide::Ask => {
-                                    self.transfer_from_contract(
-                                        order_creator.to_string(),
-                                        self.get_standard_token(),
-                                        U128::from((price * (*qty).to_f64().unwrap()).to_u128().unwrap()),
-                                    );
-                                }
-                            };
-                        }
+        if unused_amount > 0 {
+            // заблокированные вестингом токены получателя вернуть нельзя
+            let receiver_balance = self.get_account(&receiver_id, token_id.clone()).available_balance();
+            let refund_amount = std::cmp::min(unused_amount, receiver_balance);
+            if refund_amount > 0 {
+                self.internal_transfer(&receiver_id, &sender_id, &token_id, refund_amount, None);
+                return U128(amount - refund_amount);
+            }
+        }
+        U128(amount)
+    }
+
+    /// Returns NEP-148 metadata of `token_id`.
+    pub fn ft_metadata(&self, token_id: TokenId) -> TokenMetadata {
+        self.get_token(&token_id).meta
+    }
+
+    /// Returns total supply of `token_id`.
+    pub fn ft_total_supply(&self, token_id: TokenId) -> U128 {
+        match self.tokens.get(&token_id) {
+            Some(token) => U128(token.total_supply),
+            None => env::panic(b"Token not found"),
+        }
+    }
+
+    /// Returns balance of the `account_id` account for `token_id`.
+    pub fn ft_balance_of(&self, account_id: AccountId, token_id: TokenId) -> U128 {
+        self.get_balance(account_id, token_id)
+    }
+
+    fn internal_ft_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        token_id: &TokenId,
+        amount: U128,
+        memo: Option<String>,
+    ) {
+        if sender_id == receiver_id {
+            env::panic(b"Sender and receiver should be different");
+        }
+        if self.tokens.get(token_id).is_none() {
+            env::panic(b"Token not found");
+        }
+        if amount.0 == 0 {
+            env::panic(b"Can't transfer 0 tokens");
+        }
+        self.internal_transfer(sender_id, receiver_id, token_id, amount.0, memo);
+    }
+}
+
+/// Секция внешних NEP-141 токенов: пополнение и вывод
+#[near_bindgen]
+impl Contract {
+    /// Добавляет на биржу внешний NEP-141 токен из контракта `contract_id`.
+    pub fn add_bridged_token(&mut self, token_id: TokenId, contract_id: AccountId, meta: TokenMetadata) {
+        self.access.assert_role(Role::ListingManager);
+        meta.assert_valid();
+        if self.tokens.get(&token_id).is_some() {
+            env::panic(b"A token with this ID already exists");
+        }
+        if self.bridged_tokens.get(&contract_id).is_some() {
+            env::panic(b"This contract is already bridged");
+        }
+        self.register_token(&Token {
+            token_id: token_id.clone(),
+            owner_id: contract_id.clone(),
+            total_supply: 0,
+            max_supply: None,
+            meta,
+            origin: TokenOrigin::Bridged { contract_id: contract_id.clone() },
+        });
+        self.bridged_tokens.insert(&contract_id, &token_id);
+    }
+
+    /// Вызывается внешним токеном при `ft_transfer_call` на биржу.
+    /// Зачисляет `amount` на кошелек `sender_id`, ничего не возвращает.
+    pub fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> PromiseOrValue<U128> {
+        self.paused.assert_not_paused(Subsystem::Transfers);
+        let contract_id = env::predecessor_account_id();
+        let token_id = match self.bridged_tokens.get(&contract_id) {
+            Some(t) => t,
+            None => env::panic(b"This token is not bridged to the exchange"),
+        };
+        if amount.0 == 0 {
+            env::panic(b"Can't deposit 0 tokens");
+        }
+        if !msg.is_empty() {
+            env::log(format!("Deposit message: {}", msg).as_bytes());
+        }
+
+        let initial_storage = env::storage_usage();
+        self.internal_deposit(&sender_id, &token_id, amount.0);
+        self.charge_storage(&sender_id, initial_storage);
+        PromiseOrValue::Value(U128(0))
+    }
+
+    /// Выводит `amount` внешнего токена `token_id` на аккаунт вызывающего.
+    /// Если перевод во внешнем контракте не прошел, баланс восстанавливается в `ft_resolve_withdraw`.
+    #[payable]
+    pub fn withdraw(&mut self, token_id: TokenId, amount: U128) -> Promise {
+        assert_one_yocto();
+        let contract_id = match self.get_token(&token_id).origin {
+            TokenOrigin::Bridged { contract_id } => contract_id,
+            _ => env::panic(b"Only bridged tokens can be withdrawn"),
+        };
+        if amount.0 == 0 {
+            env::panic(b"Can't withdraw 0 tokens");
+        }
+        let account_id = env::predecessor_account_id();
+        self.internal_withdraw(&account_id, &token_id, amount.0);
+
+        ext_fungible_token::ft_transfer(
+            account_id.clone(),
+            amount,
+            None,
+            &contract_id,
+            1,
+            GAS_FOR_FT_TRANSFER,
+        )
+            .then(ext_self::ft_resolve_withdraw(
+                account_id,
+                token_id,
+                amount,
+                &env::current_account_id(),
+                NO_DEPOSIT,
+                GAS_FOR_RESOLVE_TRANSFER,
+            ))
+    }
+
+    /// Возвращает токены на кошелек, если вывод не удался.
+    #[private]
+    pub fn ft_resolve_withdraw(&mut self, account_id: AccountId, token_id: TokenId, amount: U128) {
+        match env::promise_result(0) {
+            PromiseResult::Successful(_) => {}
+            _ => self.internal_deposit(&account_id, &token_id, amount.0),
+        }
+    }
+
+    fn get_token(&self, token_id: &TokenId) -> Token {
+        match self.tokens.get(token_id) {
+            Some(token) => token,
+            None => env::panic(b"Token not found"),
+        }
+    }
+
+    fn internal_deposit(&mut self, account_id: &AccountId, token_id: &TokenId, amount: Balance) {
+        let mut token = self.get_token(token_id);
+        token.total_supply += amount;
+        self.tokens.insert(token_id, &token);
+
+        let mut account = self.get_account(account_id, token_id.clone());
+        account.balance += amount;
+        self.set_account(account_id, &account, token_id);
+        emit_mt_mint(account_id, token_id, amount);
+    }
+
+    fn internal_withdraw(&mut self, account_id: &AccountId, token_id: &TokenId, amount: Balance) {
+        let mut account = self.get_account(account_id, token_id.clone());
+        if account.available_balance() < amount {
+            env::panic(b"Not enough balance");
+        }
+        account.balance -= amount;
+        self.set_account(account_id, &account, token_id);
+
+        let mut token = self.get_token(token_id);
+        token.total_supply -= amount;
+        self.tokens.insert(token_id, &token);
+
+        Event::MtBurn(vec![MtBurn {
+            owner_id: account_id.clone(),
+            token_ids: vec![token_id.clone()],
+            amounts: vec![U128(amount)],
+            memo: None,
+        }]).emit();
+    }
+}
+
+/// Секция NEP-145: депозит за хранение кошельков, ордеров и заявок
+#[near_bindgen]
+impl Contract {
+    /// Вносит прикрепленный депозит за хранилище `account_id` (по умолчанию вызывающего).
+    /// При `registration_only` берется только минимальный депозит, остальное возвращается.
+    #[payable]
+    pub fn storage_deposit(&mut self, account_id: Option<AccountId>, registration_only: Option<bool>) -> StorageBalance {
+        let amount = env::attached_deposit();
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let account_hash = env::sha256(account_id.as_bytes());
+        let min_balance = self.storage_balance_bounds().min.0;
+
+        let storage = match self.storage_accounts.get(&account_hash) {
+            Some(mut storage) => {
+                if registration_only.unwrap_or(false) {
+                    if amount > 0 {
+                        Promise::new(env::predecessor_account_id()).transfer(amount);
                     }
+                    return (&storage).into();
                 }
-                Success::PartiallyFilled {
-                    order_id: id,
-                    side,
-                    order_type,
-                    price,
-                    qty,
-                    order_creator,
-                    ts: _,
-                } => {
-                    let token = match *side {
-                        OrderSide::Ask => self.get_standard_token(),
-                        OrderSide::Bid => token_id.clone(),
-                    };
+                storage.deposit += amount;
+                storage
+            }
+            None => {
+                if amount < min_balance {
+                    env::panic(b"The attached deposit is less than the minimum storage balance");
+                }
+                if registration_only.unwrap_or(false) {
+                    let refund = amount - min_balance;
+                    if refund > 0 {
+                        Promise::new(env::predecessor_account_id()).transfer(refund);
+                    }
+                    StorageAccount::new(min_balance)
+                } else {
+                    StorageAccount::new(amount)
+                }
+            }
+        };
+        self.storage_accounts.insert(&account_hash, &storage);
+        (&storage).into()
+    }
+
+    /// Выводит `amount` (по умолчанию всю свободную часть) депозита за хранилище.
+    #[payable]
+    pub fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut storage = self.get_storage_account(&account_id);
+        let available = storage.available();
+        let amount = amount.map(|a| a.0).unwrap_or(available);
+        if amount > available {
+            env::panic(b"The amount is greater than the available storage balance");
+        }
+        if amount > 0 {
+            storage.deposit -= amount;
+            self.storage_accounts.insert(&env::sha256(account_id.as_bytes()), &storage);
+            Promise::new(account_id).transfer(amount);
+        }
+        (&storage).into()
+    }
+
+    pub fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.storage_accounts
+            .get(&env::sha256(account_id.as_bytes()))
+            .map(|storage| (&storage).into())
+    }
+
+    pub fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageBalanceBounds {
+            min: U128(MIN_STORAGE_BYTES as Balance * env::storage_byte_cost()),
+            max: None,
+        }
+    }
+
+    fn get_storage_account(&self, account_id: &AccountId) -> StorageAccount {
+        match self.storage_accounts.get(&env::sha256(account_id.as_bytes())) {
+            Some(storage) => storage,
+            None => env::panic(b"The account is not registered, call storage_deposit first"),
+        }
+    }
+
+    /// Списывает с депозита `account_id` байты, занятые с момента `initial_storage`,
+    /// или возвращает освобожденные. Незарегистрированный аккаунт может только освобождать место.
+    fn charge_storage(&mut self, account_id: &AccountId, initial_storage: StorageUsage) {
+        let current_storage = env::storage_usage();
+        if current_storage > initial_storage {
+            let mut storage = self.get_storage_account(account_id);
+            storage.used_bytes += current_storage - initial_storage;
+            if storage.deposit < storage.locked() {
+                env::panic(b"Not enough storage deposit, call storage_deposit");
+            }
+            self.storage_accounts.insert(&env::sha256(account_id.as_bytes()), &storage);
+        } else if current_storage < initial_storage {
+            let account_hash = env::sha256(account_id.as_bytes());
+            if let Some(mut storage) = self.storage_accounts.get(&account_hash) {
+                storage.used_bytes = storage.used_bytes.saturating_sub(initial_storage - current_storage);
+                self.storage_accounts.insert(&account_hash, &storage);
+            }
+        }
+    }
+}
+
+pub fn order_escrow_key(token_id: &TokenId, order_id: u64) -> Vec<u8> {
+    env::sha256(format!("{}:{}", token_id, order_id).as_bytes())
+}
+
+fn parse_side(side: &str) -> Option<OrderSide> {
+    match side {
+        "Ask" => Some(OrderSide::Ask),
+        "Bid" => Some(OrderSide::Bid),
+        _ => env::panic(b"Side not parsed!"),
+    }
+}
+
+fn get_current_time() -> u64 {
+    return env::block_timestamp();
+}
+
+#[near_bindgen]
+impl Contract {
+    pub fn new_ask_limit_order(&mut self, token_id: TokenId, price: f64, quantity: u128) -> Vec<Result<Success, Failed>> {
+        self.new_limit_order(token_id, price, quantity, "Ask".to_string())
+    }
+
+    pub fn new_bid_limit_order(&mut self, token_id: TokenId, price: f64, quantity: u128) -> Vec<Result<Success, Failed>> {
+        self.new_limit_order(token_id, price, quantity, "Bid".to_string())
+    }
+
+    /// Создает новый лимитный ордер:
+    /// * 'side':
+    /// Ask - заявка на продажу
+    /// Bid - заявка на покупку
+    pub fn new_limit_order(&mut self, token_id: TokenId, price: f64, quantity: u128, side: String) -> Vec<Result<Success, Failed>> {
+        self.paused.assert_not_paused(Subsystem::Trading);
+        self.assert_market_open(&token_id);
+        let initial_storage = env::storage_usage();
+        let side = parse_side(side.as_str()).unwrap();
+        let token = match side {
+            OrderSide::Bid => self.get_standard_token(),
+            OrderSide::Ask => token_id.clone(),
+        };
+        let amount = match side {
+            OrderSide::Bid => (price * quantity.to_f64().unwrap()).to_u128().unwrap(),
+            OrderSide::Ask => quantity,
+        };
+        /**
+        Цена бид – это цена спроса или максимальная цена, по которой покупатель согласен купить товар.
+        Покупатель не хочет покупать дорого. Это логика закона спроса и предложения
+
+        Цена аск – это цена предложения или наименьшая цена, по которой продавец согласен продать товар.
+        Продавец не хочет продавать дешево
+         */
+        self.internal_transfer_from(
+            env::signer_account_id(),
+            env::current_account_id(),
+            token,
+            U128(amount),
+        );
+        let res = self.post_transfer(token_id.clone(), price, quantity, side);
+        if let OrderSide::Bid = side {
+            self.settle_bid_escrow(&token_id, &res, amount);
+        }
+        self.charge_storage(&env::predecessor_account_id(), initial_storage);
+        res
+    }
+
+    /// Из эскроу нового бида вычитается оплата сделок, прошедших сразу. Остаток ждет в стакане
+    /// в `order_escrow`, а если бид исполнен целиком - возвращается создателю: продавцы могли просить меньше.
+    fn settle_bid_escrow(&mut self, token_id: &TokenId, res: &[Result<Success, Failed>], escrow: Balance) {
+        let mut incoming = None;
+        let mut filled = false;
+        let mut spent: Balance = 0;
+        for result in res {
+            match result {
+                Ok(Success::Accepted { id, order_creator, .. }) => incoming = Some((*id, order_creator.clone())),
+                // все аски в ответе - встречные ордера, им платит новый бид
+                Ok(Success::Filled { side: OrderSide::Ask, price, qty, .. })
+                | Ok(Success::PartiallyFilled { side: OrderSide::Ask, price, qty, .. }) => {
+                    spent += (price * (*qty).to_f64().unwrap()).to_u128().unwrap();
+                }
+                Ok(Success::Filled { order_id, side: OrderSide::Bid, .. })
+                    if incoming.as_ref().map(|(id, _)| id) == Some(order_id) => filled = true,
+                _ => {}
+            }
+        }
+        let (order_id, creator) = match incoming {
+            Some(incoming) => incoming,
+            None => return,
+        };
+        let left = escrow.saturating_sub(spent);
+        if !filled {
+            self.order_escrow.insert(&order_escrow_key(token_id, order_id), &left);
+        } else if left > 0 {
+            self.transfer_from_contract(creator, self.get_standard_token(), U128(left));
+        }
+    }
+
+    /// Бид из стакана заплатил `paid` по сделке. Исполненный целиком бид возвращает создателю остаток эскроу.
+    /// У бидов, выставленных до учета эскроу, записи нет: они платят по цене, как раньше.
+    fn spend_bid_escrow(&mut self, token_id: &TokenId, order_id: u64, creator: &AccountId, paid: Balance, filled: bool) {
+        let key = order_escrow_key(token_id, order_id);
+        let escrow = match self.order_escrow.get(&key) {
+            Some(escrow) => escrow,
+            None => return,
+        };
+        let left = escrow.saturating_sub(paid);
+        if !filled {
+            self.order_escrow.insert(&key, &left);
+            return;
+        }
+        self.order_escrow.remove(&key);
+        if left > 0 {
+            self.transfer_from_contract(creator.clone(), self.get_standard_token(), U128(left));
+        }
+    }
+
+    #[private]
+    fn post_transfer(&mut self, token_id: TokenId, price: f64, quantity: u128, side: OrderSide) -> Vec<Result<Success, Failed>> {
+        let order = orders::new_limit_order_request(
+            token_id.clone(),
+            self.get_standard_token(),
+            side,
+            price,
+            quantity,
+            env::signer_account_id(),
+            get_current_time(),
+        );
+
+        let mut order_book = self.order_books.get(&token_id).unwrap();
+        let res = order_book.process_order(order);
+        self.order_books.insert(&token_id, &order_book);
+
+        self.process_orderbook_result(token_id, res)
+    }
+
+    /// Создает новый рыночный ордер:
+    /// * 'side':
+    /// Ask - заявка на продажу
+    /// Bid - заявка на покупку
+    pub fn new_market_order(&mut self, token_id: TokenId, quantity: u128, side: String) -> Vec<Result<Success, Failed>> {
+        self.paused.assert_not_paused(Subsystem::Trading);
+        let initial_storage = env::storage_usage();
+        let res = self.internal_market_order(token_id, quantity, side);
+        self.charge_storage(&env::predecessor_account_id(), initial_storage);
+        res
+    }
+
+    fn internal_market_order(&mut self, token_id: TokenId, quantity: u128, side: String) -> Vec<Result<Success, Failed>> {
+        self.assert_market_open(&token_id);
+        let standard_token = self.get_standard_token();
+        let trader = env::predecessor_account_id();
+        let standard_before = self.get_account(&trader, standard_token.clone()).balance;
+        let side = parse_side(side.as_str()).unwrap();
+        // для продажи сразу переводим, для покупки будем переводить потом, когда будем знать цену
+        match side {
+            OrderSide::Ask => {
+                self.internal_transfer_from(
+                    trader.clone(),
+                    env::current_account_id(),
+                    token_id.clone(),
+                    U128::from(quantity),
+                );
+            }
+            _ => {}
+        };
+
+        let order = orders::new_market_order_request(
+            token_id.clone(),
+            self.get_standard_token(),
+            side,
+            quantity,
+            trader.clone(),
+            get_current_time(),
+        );
+
+        let mut order_book = self.order_books.get(&token_id.clone()).unwrap();
+        let res = order_book.process_order(order);
+        self.order_books.insert(&token_id.clone(), &order_book);
+
+        let res = self.process_orderbook_result(token_id, res);
+        // комиссия тейкера с объема сделки в стандартном токене
+        let standard_after = self.get_account(&trader, standard_token.clone()).balance;
+        let volume = standard_after.abs_diff(standard_before);
+        let fee = self.fees.trading_fee(volume);
+        if fee > 0 {
+            self.internal_to_treasury(&trader, &standard_token, fee);
+        }
+        res
+    }
+
+    /// Обменивает `amount_in` первого токена из `path` на последний, последовательно
+    /// исполняя рыночные ордера в стаканах маршрута (например TOKEN_A -> XDHO -> TOKEN_B).
+    /// Каждый шаг маршрута должен проходить через стандартный токен.
+    /// Если какой-то шаг не исполнился или итог меньше `min_out`, вызов паникует
+    /// и все промежуточные переводы в `wallets` откатываются вместе с ним.
+    pub fn swap_route(&mut self, path: Vec<TokenId>, amount_in: U128, min_out: U128) -> U128 {
+        self.paused.assert_not_paused(Subsystem::Trading);
+        if path.len() < 2 {
+            env::panic(b"Swap path must contain at least two tokens");
+        }
+        let initial_storage = env::storage_usage();
+        let trader = env::predecessor_account_id();
+        let mut amount = amount_in.0;
+        for hop in path.windows(2) {
+            amount = self.swap_hop(&trader, &hop[0], &hop[1], amount);
+        }
+        if amount < min_out.0 {
+            env::panic(b"Swap output is less than min_out");
+        }
+        self.charge_storage(&trader, initial_storage);
+        U128(amount)
+    }
+
+    /// Исполняет один шаг маршрута и возвращает, сколько `token_out` получил `trader`
+    fn swap_hop(&mut self, trader: &AccountId, token_in: &TokenId, token_out: &TokenId, amount_in: Balance) -> Balance {
+        let standard_token = self.get_standard_token();
+        let balance_before = self.get_account(trader, token_out.clone()).balance;
+
+        if *token_in != standard_token && *token_out == standard_token {
+            self.internal_market_order(token_in.clone(), amount_in, "Ask".to_string());
+        } else if *token_in == standard_token && *token_out != standard_token {
+            let order_book = match self.order_books.get(token_out) {
+                Some(t) => t,
+                None => env::panic(b"OrderBook not init!"),
+            };
+            // комиссия тейкера берется сверх объема, поэтому резервируем ее заранее
+            let quantity = order_book.market_bid_qty(amount_in - self.fees.trading_fee(amount_in));
+            if quantity == 0 {
+                env::panic(b"Not enough liquidity for the swap");
+            }
+            let standard_before = self.get_account(trader, standard_token.clone()).balance;
+            self.internal_market_order(token_out.clone(), quantity, "Bid".to_string());
+            let spent = standard_before - self.get_account(trader, standard_token).balance;
+            if spent > amount_in {
+                env::panic(b"Swap hop spent more than its input");
+            }
+        } else {
+            env::panic(b"Each swap hop must trade against the standard token");
+        }
+
+        self.get_account(trader, token_out.clone()).balance - balance_before
+    }
+
+    pub fn cancel_limit_order(
+        &mut self,
+        token_id: TokenId,
+        id: u64,
+        side: String,
+    ) -> Vec<Result<Success, Failed>> {
+        let side = parse_side(&side).unwrap();
+        let mut order_book = self.order_books.get(&token_id).unwrap();
+        let queue = match side {
+            OrderSide::Bid => &order_book.bid_queue,
+            OrderSide::Ask => &order_book.ask_queue,
+        };
+        let cancelled = queue.orders.get(&id).cloned();
+        if let Some(order) = &cancelled {
+            if order.order_creator != env::predecessor_account_id() {
+                env::panic(b"Only the order creator can cancel it");
+            }
+        }
+        let res = order_book.process_order(orders::limit_order_cancel_request(id, side));
+        self.order_books.insert(&token_id, &order_book);
+        for result in &res {
+            if let Ok(Success::Cancelled { id, ts: _ }) = result {
+                // возвращаем остаток эскроу и место в стакане создателю ордера
+                if let Some(order) = &cancelled {
+                    self.release_order_storage(&token_id, &order.order_creator, side);
+                    let (token, amount) = match side {
+                        OrderSide::Bid => {
+                            let escrow = self.order_escrow.remove(&order_escrow_key(&token_id, *id));
+                            let amount = escrow.unwrap_or_else(|| (order.price * order.qty.to_f64().unwrap()).to_u128().unwrap());
+                            (self.get_standard_token(), amount)
+                        }
+                        OrderSide::Ask => (token_id.clone(), order.qty),
+                    };
+                    if amount > 0 {
+                        self.transfer_from_contract(order.order_creator.clone(), token, U128(amount));
+                    }
+                }
+                Event::OrderCancelled(vec![OrderCancelled {
+                    order_id: *id,
+                    token_id: token_id.clone(),
+                }]).emit();
+            }
+        }
+        res
+    }
+
+    pub fn get_ask_orders(&self, token_id: TokenId) -> Vec<Order> {
+        let order_book = self.order_books.get(&token_id).unwrap();
+        Vec::from_iter(order_book.ask_queue.clone().orders.into_values())
+    }
+
+    pub fn get_orders(&self, account_id: AccountId, token_id: TokenId, side: String) -> Vec<Order> {
+        let side = parse_side(side.as_str()).unwrap();
+        let order_book = self.order_books.get(&token_id);
+        match order_book {
+            Some(t) => (t.get_orders(account_id, side)),
+            None => env::panic(b"OrderBook not init!"),
+        }
+    }
+
+    pub fn get_bid_orders(&self, token_id: TokenId) -> Vec<Order> {
+        let order_book = self.order_books.get(&token_id).unwrap();
+        Vec::from_iter(order_book.bid_queue.clone().orders.into_values())
+    }
+
+    pub fn get_current_spread(&self, token_id: TokenId) -> Vec<f64> {
+        let order_book = self.order_books.get(&token_id).unwrap();
+        if let Some((bid, ask)) = order_book.clone().current_spread() {
+            vec![ask, bid]
+        } else {
+            vec![0.0, 0.0]
+        }
+    }
+
+    /// Выплата из эскроу контракта, allowance не нужен.
+    fn transfer_from_contract(&mut self, new_owner_id: AccountId, token_id: TokenId, amount: U128) {
+        if amount.0 == 0 {
+            env::panic(b"Can't transfer 0 tokens");
+        }
+        self.internal_transfer(&env::current_account_id(), &new_owner_id, &token_id, amount.0, None);
+    }
+
+    /// Сколько байт ордер `owner_id` занимает в стакане `token_id`: ордер с ключом и его запись в очереди цен,
+    /// а у бида еще остаток эскроу
+    fn order_storage(&self, token_id: &TokenId, owner_id: &AccountId, side: OrderSide) -> StorageUsage {
+        let order = Order {
+            order_asset: token_id.clone(),
+            price_asset: self.get_standard_token(),
+            order_creator: owner_id.clone(),
+            ..Order::default()
+        };
+        let index = OrderIndex { id: 0, price: 0.0, quantity: 0, timestamp: 0, order_side: OrderSide::Bid };
+        let mut bytes = 0u64.try_to_vec().unwrap().len() + order.try_to_vec().unwrap().len() + index.try_to_vec().unwrap().len();
+        if let OrderSide::Bid = side {
+            bytes += order_escrow_key(token_id, 0).try_to_vec().unwrap().len() + 0u128.try_to_vec().unwrap().len();
+        }
+        bytes as StorageUsage
+    }
+
+    /// Место ордера, который ушел из стакана, возвращается его владельцу. Если ордер закрыла
+    /// чужая сделка, освободившиеся байты не достаются ее автору даром: они переходят на него.
+    /// Ордера без депозита владельца (например, выставленные до учета места) ничего не переносят.
+    fn release_order_storage(&mut self, token_id: &TokenId, owner_id: &AccountId, side: OrderSide) {
+        let bytes = self.order_storage(token_id, owner_id, side);
+        let mut owner = match self.storage_accounts.get(&env::sha256(owner_id.as_bytes())) {
+            Some(owner) => owner,
+            None => return,
+        };
+        owner.used_bytes = owner.used_bytes.saturating_sub(bytes);
+        self.storage_accounts.insert(&env::sha256(owner_id.as_bytes()), &owner);
+
+        let taker_id = env::predecessor_account_id();
+        if taker_id != *owner_id {
+            let mut taker = self.get_storage_account(&taker_id);
+            taker.used_bytes += bytes;
+            if taker.deposit < taker.locked() {
+                env::panic(b"Not enough storage deposit, call storage_deposit");
+            }
+            self.storage_accounts.insert(&env::sha256(taker_id.as_bytes()), &taker);
+        }
+    }
+
+    fn process_orderbook_result(
+        &mut self,
+        token_id: TokenId,
+        order: Vec<Result<Success, Failed>>,
+    ) -> Vec<Result<Success, Failed>> {
+        let mut incoming_id = None;
+        for temp_variable in &order {
+            let success = temp_variable.as_ref().unwrap();
+
+            match success {
+                Success::Accepted {
+                    id,
+                    order_type,
+                    order_creator,
+                    ts: _,
+                } => {
+                    incoming_id = Some(*id);
+                    Event::OrderAccepted(vec![OrderAccepted {
+                        order_id: *id,
+                        token_id: token_id.clone(),
+                        order_type: *order_type,
+                        order_creator: order_creator.clone(),
+                    }]).emit();
+                }
+                Success::Filled {
+                    order_id: id,
+                    side,
+                    order_type,
+                    price,
+                    qty,
+                    order_creator,
+                    ts: _,
+                } => {
+                    let token = match *side {
+                        OrderSide::Ask => self.get_standard_token(),
+                        OrderSide::Bid => token_id.clone(),
+                    };
+
+                    Event::OrderFilled(vec![OrderFilled {
+                        order_id: *id,
+                        token_id: token_id.clone(),
+                        side: *side,
+                        order_type: *order_type,
+                        price: *price,
+                        qty: U128(*qty),
+                        order_creator: order_creator.clone(),
+                        partial: false,
+                    }]).emit();
+                    match *order_type {
+                        OrderType::Limit => {
+                            // исполненный до конца ордер из стакана освобождает место своего владельца
+                            if incoming_id != Some(*id) {
+                                self.release_order_storage(&token_id, order_creator, *side);
+                            }
+                            match side {
+                                OrderSide::Bid => {
+                                    if incoming_id != Some(*id) {
+                                        let paid = (price * (*qty).to_f64().unwrap()).to_u128().unwrap();
+                                        self.spend_bid_escrow(&token_id, *id, order_creator, paid, true);
+                                    }
+                                    self.transfer_from_contract(
+                                        order_creator.to_string(),
+                                        token,
+                                        U128::from(*qty),
+                                    );
+                                }
+                                OrderSide::Ask => {
+                                    let amount = U128::from((price * (*qty).to_f64().unwrap()).to_u128().unwrap());
+                                    self.transfer_from_contract(
+                                        order_creator.to_string(),
+                                        token,
+                                        amount,
+                                    );
+                                }
+                            };
+                        }
+                        OrderType::Market => {
+                            match side {
+                                OrderSide::Bid => {
+                                    let amount = U128::from((price * (*qty).to_f64().unwrap()).to_u128().unwrap());
+                                    // переводим токены на контракт, откуда их возьмет продавец
+                                    self.internal_transfer_from(
+                                        env::predecessor_account_id(),
+                                        env::current_account_id(),
+                                        self.get_standard_token(),
+                                        amount,
+                                    );
+                                    self.transfer_from_contract(
+                                        order_creator.to_string(),
+                                        token,
+                                        U128::from(*qty),
+                                    );
+                                }
+                                OrderSide::Ask => {
+                                    self.transfer_from_contract(
+                                        order_creator.to_string(),
+                                        self.get_standard_token(),
+                                        U128::from((price * (*qty).to_f64().unwrap()).to_u128().unwrap()),
+                                    );
+                                }
+                            };
+                        }
+                    }
+                }
+                Success::PartiallyFilled {
+                    order_id: id,
+                    side,
+                    order_type,
+                    price,
+                    qty,
+                    order_creator,
+                    ts: _,
+                } => {
+                    let token = match *side {
+                        OrderSide::Ask => self.get_standard_token(),
+                        OrderSide::Bid => token_id.clone(),
+                    };
+
+                    Event::OrderFilled(vec![OrderFilled {
+                        order_id: *id,
+                        token_id: token_id.clone(),
+                        side: *side,
+                        order_type: *order_type,
+                        price: *price,
+                        qty: U128(*qty),
+                        order_creator: order_creator.clone(),
+                        partial: true,
+                    }]).emit();
+                    match *order_type {
+                        OrderType::Limit => {
+                            let amount = match *side {
+                                OrderSide::Ask => (price * (*qty).to_f64().unwrap()).to_u128().unwrap(),
+                                OrderSide::Bid => *qty,
+                            };
+                            if let OrderSide::Bid = side {
+                                if incoming_id != Some(*id) {
+                                    let paid = (price * (*qty).to_f64().unwrap()).to_u128().unwrap();
+                                    self.spend_bid_escrow(&token_id, *id, order_creator, paid, false);
+                                }
+                            }
+
+                            self.transfer_from_contract(
+                                order_creator.to_string(),
+                                token,
+                                U128::from(amount),
+                            );
+                        }
+                        OrderType::Market => {
+                            match side {
+                                OrderSide::Bid => {
+                                    let amount = U128::from((price * (*qty).to_f64().unwrap()).to_u128().unwrap());
+                                    // переводим токены на контракт, откуда их возьмет продавец
+                                    self.internal_transfer_from(
+                                        env::predecessor_account_id(),
+                                        env::current_account_id(),
+                                        self.get_standard_token(),
+                                        amount,
+                                    );
+                                    self.transfer_from_contract(
+                                        order_creator.to_string(),
+                                        token,
+                                        U128::from(*qty),
+                                    );
+                                }
+                                OrderSide::Ask => {
+                                    self.transfer_from_contract(
+                                        order_creator.to_string(),
+                                        self.get_standard_token(),
+                                        U128::from((price * (*qty).to_f64().unwrap()).to_u128().unwrap()),
+                                    );
+                                }
+                            };
+                        }
+                    }
+                }
+                Success::Amended {
+                    id: _,
+                    price: _,
+                    qty: _,
+                    ts: _,
+                } => {}
+                Success::Cancelled { id: _, ts: _ } => {}
+            };
+        }
+        order
+    }
+
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(test)]
+mod tests {
+    use near_sdk::{AccountId, env, Gas, MockedBlockchain, PromiseResult, serde_json};
+    use near_sdk::test_utils::get_logs;
+    use near_sdk::{testing_env, VMContext};
+    use near_sdk::borsh::{BorshDeserialize, BorshSerialize};
+    use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet};
+    use near_sdk::json_types::{Base64VecU8, U128};
+    use num_traits::ToPrimitive;
+
+    use crate::{APPROVED, Contract, NANOSEC_IN_DAY, PERCENT_STAKING_PER_YEAR, Token, TokenMetadata, TokenMetadataUpdate, TokenOrigin, UserRequest};
+    use crate::request::RequestStatus::REJECTED;
+    use crate::access_control::Role;
+    use crate::governance::{GovernanceConfig, VotingTerms};
+    use crate::migration::{ContractV1, LaunchPadV1, read_state_version, RequestV1, STATE_VERSION, TokenMetadataV1, TokenV1, VoteV1};
+    use crate::ballot::{BallotHandler, StakeInfo};
+    use crate::account::TokenAccountV1;
+    use crate::fees::FeeRates;
+    use crate::pause::{MarketStatus, Subsystem};
+    use crate::request::{ProposalType, RequestId, RequestKind, RequestStatus, VoteOption};
+    use crate::secret_ballot::vote_hash;
+    use crate::account::AllowanceView;
+    use crate::token::TokenId;
+    use orderbook::Orderbook;
+    use crate::vesting::{VestingSchedule, VestingTerms};
+    use crate::storage::StorageAccount;
+
+    const YOKTO_NEAR: u128 = 1_000_000_000_000_000_000_000_000;
+
+    fn standart_token() -> Token {
+        Token {
+            token_id: "XDHO".to_string(),
+            owner_id: carol().to_string(),
+            total_supply: 100_000_000_000,
+            max_supply: None,
+            meta: TokenMetadata::new("XDHO", "XDHO", 2),
+            origin: TokenOrigin::Native,
+        }
+    }
+
+    fn test_token() -> Token {
+        Token {
+            token_id: "TEST".to_string(),
+            owner_id: bob(),
+            total_supply: 10000,
+            max_supply: None,
+            meta: TokenMetadata::new("TEST", "TEST", 2),
+            origin: TokenOrigin::Native,
+        }
+    }
+
+    fn alice() -> AccountId {
+        "alice.near".to_string()
+    }
+
+    fn bob() -> AccountId {
+        "bob.near".to_string()
+    }
+
+    fn ivan() -> AccountId {
+        "ivan.near".to_string()
+    }
+
+    fn vasy() -> AccountId {
+        "vasy.near".to_string()
+    }
+
+    fn carol() -> AccountId {
+        "carol.near".to_string()
+    }
+
+    /// Создает контракт и вносит депозит за хранилище всем тестовым аккаунтам
+    fn new_contract(owner_id: AccountId) -> Contract {
+        let mut contract = Contract::new(owner_id);
+        for account_id in [alice(), bob(), carol(), ivan(), vasy()] {
+            contract.storage_accounts.insert(
+                &env::sha256(account_id.as_bytes()),
+                &StorageAccount::new(YOKTO_NEAR),
+            );
+        }
+        contract
+    }
+
+    fn catch_unwind_silent<F: FnOnce() -> R + std::panic::UnwindSafe, R>(
+        f: F,
+    ) -> std::thread::Result<R> {
+        let prev_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(|_| {}));
+        let result = std::panic::catch_unwind(f);
+        std::panic::set_hook(prev_hook);
+        result
+    }
+
+    fn get_context(predecessor_account_id: AccountId) -> VMContext {
+        get_extend_context(predecessor_account_id, bob())
+    }
+
+    fn get_extend_context(predecessor_account_id: AccountId, signer: AccountId) -> VMContext {
+        VMContext {
+            current_account_id: alice(),
+            signer_account_id: signer,
+            signer_account_pk: vec![0, 1, 2],
+            predecessor_account_id,
+            input: vec![],
+            block_index: 0,
+            block_timestamp: 0,
+            account_balance: 100,
+            account_locked_balance: 0,
+            storage_usage: 10u64.pow(6),
+            attached_deposit: 0,
+            prepaid_gas: Gas::from(10u64.pow(18)),
+            random_seed: vec![0, 1, 2],
+            is_view: false,
+            output_data_receivers: vec![],
+            epoch_height: 0,
+        }
+    }
+
+    fn init_contract_with_tokens() -> Contract {
+        let context = get_context(bob());
+        testing_env!(context);
+
+        let mut contract = new_contract(bob());
+        assert_eq!(
+            contract.get_balance(bob(), standart_token().token_id).0,
+            standart_token().total_supply
+        );
+
+        contract.add_token(test_token());
+        let balance = contract
+            .get_balance(bob(), test_token().token_id)
+            .0;
+        assert_eq!(balance, 10000u128);
+
+        contract.transfer(carol(), standart_token().token_id, U128::from(1000u128));
+        contract.transfer(ivan(), standart_token().token_id, U128::from(1000u128));
+        contract.transfer(vasy(), standart_token().token_id, U128::from(1000u128));
+        contract.transfer(carol(), test_token().token_id, U128::from(1000u128));
+        contract.transfer(ivan(), test_token().token_id, U128::from(1000u128));
+
+        contract
+    }
+
+    fn get_test_request() -> UserRequest{
+        UserRequest {
+            token_id: "SOSI".to_string(),
+            title: "Тестовое название".to_string(),
+            description: "ПРИВЕТИКИ".to_string(),
+            price: 23,
+            supply: 100000,
+            max_supply: None,
+            meta: TokenMetadata::new("Сосисочка", "SOSI", 2),
+            hash: "сосисочка".to_string(),
+        }
+    }
+
+    fn get_contract_with_request() -> Contract {
+        let mut contract = init_contract_with_tokens();
+        testing_env!(get_extend_context(carol(), carol()));
+        contract.add_new_request(get_test_request());
+        contract
+    }
+
+    fn get_contract_with_request_and_staking() -> Contract {
+        let mut contract = get_contract_with_request();
+        testing_env!(get_extend_context(carol(), carol()));
+        contract.stake(100);
+        testing_env!(get_extend_context(ivan(), ivan()));
+        contract.stake(100);
+        testing_env!(get_extend_context(vasy(), vasy()));
+        contract.stake(100);
+
+        contract
+    }
+
+    fn get_contract_with_approve_request() -> Contract{
+        let mut contract = get_contract_with_request_and_staking();
+        testing_env!(get_extend_context(vasy(), vasy()));
+        contract.vote(0, VoteOption::Yes);
+        testing_env!(get_extend_context(ivan(), ivan()));
+        contract.vote(0, VoteOption::Yes);
+        testing_env!(get_extend_context(carol(), carol()));
+        contract.vote(0, VoteOption::No);
+
+        let mut context = get_extend_context(carol(), carol());
+        context.block_timestamp = NANOSEC_IN_DAY;
+        testing_env!(context);
+
+        contract.finalize_request(0);
+        contract
+    }
+
+    #[test]
+    fn test_new() {
+        let context = get_context(carol());
+        testing_env!(context);
+
+        let contract = new_contract(bob());
+        assert_eq!(contract.get_balance(bob(), standart_token().token_id).0, standart_token().total_supply);
+    }
+
+    #[test]
+    fn test_transfer() {
+        let context = get_context(carol());
+        testing_env!(context);
+        let total_supply = standart_token().total_supply;
+        let mut contract = new_contract(carol());
+        let transfer_amount = total_supply / 3;
+        contract.transfer(bob(), standart_token().token_id, transfer_amount.into());
+        assert_eq!(
+            contract.get_balance(carol(), standart_token().token_id).0,
+            (total_supply - transfer_amount)
+        );
+        assert_eq!(contract.get_balance(bob(), standart_token().token_id).0, transfer_amount);
+    }
+
+    #[test]
+    fn test_failed_unstaking() {
+        let mut contract = init_contract_with_tokens_and_limit_bids();
+        let context = get_extend_context(carol(), carol());
+        testing_env!(context);
+
+        catch_unwind_silent(move || {
+            contract.unstake();
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn test_failed_staking() {
+        let mut contract = init_contract_with_tokens_and_limit_bids();
+        let context = get_extend_context(carol(), carol());
+        testing_env!(context);
+
+        contract.stake(123);
+        catch_unwind_silent(move || {
+            contract.stake(123);
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn test_staking() {
+        let mut contract = init_contract_with_tokens_and_limit_bids();
+        let context = get_extend_context(carol(), carol());
+        testing_env!(context);
+        let amount = 100;
+        contract.deposit_staking_rewards(U128(50));
+        let old_balance = contract.get_balance(carol(), standart_token().token_id);
+        let escrow_balance = contract.get_balance(alice(), standart_token().token_id);
+        contract.stake(amount);
+        assert_eq!(contract.get_balance(carol(), standart_token().token_id).0, old_balance.0 - amount);
+        assert_eq!(contract.get_balance(alice(), standart_token().token_id).0, escrow_balance.0 + amount);
+        assert_eq!(contract.get_staking(carol()).staked, amount);
+
+        let mut context = get_extend_context(carol(), carol());
+        context.block_timestamp = NANOSEC_IN_DAY * 365;
+        testing_env!(context);
+        contract.unstake();
+        let reward = (amount.to_f64().unwrap() * PERCENT_STAKING_PER_YEAR).to_u128().unwrap();
+        assert_eq!(contract.get_balance(carol(), standart_token().token_id).0, old_balance.0 + reward);
+        assert_eq!(contract.get_balance(alice(), standart_token().token_id).0, escrow_balance.0 - reward);
+        assert_eq!(contract.get_staking_rewards().0, 50 - reward);
+        assert_eq!(contract.get_staking(carol()).staked, 0);
+    }
+
+    #[test]
+    fn test_staking_reward_over_reserve() {
+        let mut contract = init_contract_with_tokens_and_limit_bids();
+        testing_env!(get_extend_context(carol(), carol()));
+        contract.deposit_staking_rewards(U128(5));
+        let old_balance = contract.get_balance(carol(), standart_token().token_id);
+        contract.stake(100);
+
+        set_time(carol(), NANOSEC_IN_DAY * 365);
+        contract.unstake();
+        // награда 20, но в резерве только 5
+        assert_eq!(contract.get_balance(carol(), standart_token().token_id).0, old_balance.0 + 5);
+        assert_eq!(contract.get_staking_rewards().0, 0);
+    }
+
+    #[test]
+    fn test_unstake_with_pending_vote() {
+        let mut contract = get_contract_with_request_and_staking();
+        testing_env!(get_extend_context(carol(), carol()));
+        contract.vote(0, VoteOption::Yes);
+        contract.vote(0, VoteOption::No);
+        assert_eq!(contract.pending_votes.get(&carol()), Some(1));
+
+        set_time(carol(), NANOSEC_IN_DAY + 1);
+        contract.finalize_request(0);
+        contract.unstake();
+        assert_eq!(contract.get_staking(carol()).staked, 0);
+    }
+
+    #[test]
+    fn test_unstake_with_pending_vote_failed() {
+        let mut contract = get_contract_with_request_and_staking();
+        testing_env!(get_extend_context(carol(), carol()));
+        contract.vote(0, VoteOption::Yes);
+        catch_unwind_silent(move || {
+            contract.unstake();
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn test_voting_without_staking() {
+        let mut contract = get_contract_with_request();
+        let context = get_extend_context(carol(), carol());
+        testing_env!(context);
+        catch_unwind_silent(move || {
+            contract.vote(0, VoteOption::Yes);
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn test_voting_after_staking() {
+        let mut contract = get_contract_with_request();
+        let context = get_extend_context(carol(), carol());
+        testing_env!(context);
+        let amount = 100;
+        contract.stake(amount);
+        contract.vote(0, VoteOption::Yes);
+        assert!(contract.is_voting(carol(), 0));
+    }
+
+    #[test]
+    fn test_request_finalize_failed() {
+        let mut contract = get_contract_with_request_and_staking();
+        testing_env!(get_extend_context(vasy(), vasy()));
+        contract.vote(0, VoteOption::Yes);
+        testing_env!(get_extend_context(ivan(), ivan()));
+        contract.vote(0, VoteOption::Yes);
+        testing_env!(get_extend_context(carol(), carol()));
+        contract.vote(0, VoteOption::No);
+
+        catch_unwind_silent(move || {
+            contract.finalize_request(0);
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn test_request_approve() {
+        let contract = get_contract_with_approve_request();
+        assert_eq!(contract.get_request(0).status, APPROVED);
+    }
+
+    #[test]
+    fn test_request_reject_by_stake_weight() {
+        let mut contract = get_contract_with_request();
+        testing_env!(get_extend_context(carol(), carol()));
+        contract.stake(100);
+        contract.vote(0, VoteOption::Yes);
+        testing_env!(get_extend_context(ivan(), ivan()));
+        contract.stake(100);
+        contract.vote(0, VoteOption::Yes);
+        testing_env!(get_extend_context(vasy(), vasy()));
+        contract.stake(500);
+        contract.vote(0, VoteOption::No);
+        assert_eq!(contract.get_all_votes(0).iter().map(|vote| vote.weight).sum::<u128>(), 700);
+
+        let mut context = get_extend_context(carol(), carol());
+        context.block_timestamp = NANOSEC_IN_DAY;
+        testing_env!(context);
+        contract.finalize_request(0);
+        assert_eq!(contract.get_request(0).status, REJECTED);
+    }
+
+    #[test]
+    fn test_change_vote() {
+        let mut contract = get_contract_with_request_and_staking();
+        testing_env!(get_extend_context(vasy(), vasy()));
+        contract.vote(0, VoteOption::Yes);
+        testing_env!(get_extend_context(ivan(), ivan()));
+        contract.vote(0, VoteOption::No);
+        testing_env!(get_extend_context(vasy(), vasy()));
+        contract.vote(0, VoteOption::No);
+
+        let votes = contract.get_all_votes(0);
+        assert_eq!(votes.len(), 2);
+        let vasy_vote = votes.iter().find(|vote| vote.owner_id == vasy()).unwrap();
+        assert_eq!(vasy_vote.result, VoteOption::No);
+        assert_eq!(vasy_vote.weight, 100);
+
+        let mut context = get_extend_context(carol(), carol());
+        context.block_timestamp = NANOSEC_IN_DAY;
+        testing_env!(context);
+        contract.finalize_request(0);
+        assert_eq!(contract.get_request(0).status, REJECTED);
+    }
+
+    #[test]
+    fn test_change_vote_after_voting_end_failed() {
+        let mut contract = get_contract_with_request_and_staking();
+        testing_env!(get_extend_context(vasy(), vasy()));
+        contract.vote(0, VoteOption::Yes);
+        let mut context = get_extend_context(vasy(), vasy());
+        context.block_timestamp = NANOSEC_IN_DAY + 1;
+        testing_env!(context);
+        catch_unwind_silent(move || {
+            contract.vote(0, VoteOption::No);
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn test_vote_storage_is_refunded_at_finalize() {
+        let mut contract = get_contract_with_request_and_staking();
+        let available = contract.storage_balance_of(vasy()).unwrap().available.0;
+        testing_env!(get_extend_context(vasy(), vasy()));
+        contract.vote(0, VoteOption::Yes);
+        assert!(contract.storage_balance_of(vasy()).unwrap().available.0 < available);
+
+        set_time(carol(), NANOSEC_IN_DAY);
+        contract.finalize_request(0);
+        assert_eq!(contract.storage_balance_of(vasy()).unwrap().available.0, available);
+    }
+
+    #[test]
+    fn stake_storage_is_refunded_at_unstake() {
+        let mut contract = init_contract_with_tokens();
+        // первый стейк заводит счет контракта
+        testing_env!(get_extend_context(ivan(), ivan()));
+        contract.stake(100);
+        testing_env!(get_extend_context(carol(), carol()));
+        let available = contract.storage_balance_of(carol()).unwrap().available.0;
+        contract.stake(100);
+        assert!(contract.storage_balance_of(carol()).unwrap().available.0 < available);
+
+        contract.unstake();
+        assert_eq!(contract.storage_balance_of(carol()).unwrap().available.0, available);
+    }
+
+    #[test]
+    fn test_abstain_counts_for_quorum_only() {
+        let mut contract = get_contract_with_request_and_staking();
+        update_terms(&mut contract, 0, |terms| terms.quorum_percent = 50);
+        testing_env!(get_extend_context(vasy(), vasy()));
+        contract.vote(0, VoteOption::Yes);
+        // без воздержавшегося кворума в 150 из 300 нет, а с ним "за" все определившиеся
+        testing_env!(get_extend_context(ivan(), ivan()));
+        contract.vote(0, VoteOption::Abstain);
+
+        let mut context = get_extend_context(carol(), carol());
+        context.block_timestamp = NANOSEC_IN_DAY;
+        testing_env!(context);
+        contract.finalize_request(0);
+        assert_eq!(contract.get_request(0).status, APPROVED);
+    }
+
+    #[test]
+    fn test_delegated_vote() {
+        let mut contract = get_contract_with_request_and_staking();
+        update_terms(&mut contract, 0, |terms| terms.quorum_percent = 50);
+        testing_env!(get_extend_context(ivan(), ivan()));
+        contract.delegate_votes(vasy());
+        assert_eq!(contract.get_delegate(ivan()), Some(vasy()));
+        assert_eq!(contract.get_delegators(vasy()), vec![ivan()]);
+        assert_eq!(contract.get_delegated_stake(vasy()).0, 100);
+
+        // сам по себе голос vasy не набирает кворум в 150 из 300
+        testing_env!(get_extend_context(vasy(), vasy()));
+        contract.vote(0, VoteOption::Yes);
+        assert_eq!(get_events("vote")[0]["weight"], "200");
+        assert_eq!(contract.get_all_votes(0)[0].weight, 200);
+
+        let mut context = get_extend_context(carol(), carol());
+        context.block_timestamp = NANOSEC_IN_DAY;
+        testing_env!(context);
+        contract.finalize_request(0);
+        assert_eq!(contract.get_request(0).status, APPROVED);
+    }
+
+    #[test]
+    fn delegated_weight_is_fixed_at_vote() {
+        let mut contract = get_contract_with_request_and_staking();
+        testing_env!(get_extend_context(ivan(), ivan()));
+        contract.delegate_votes(vasy());
+        testing_env!(get_extend_context(vasy(), vasy()));
+        contract.vote(0, VoteOption::Yes);
+        testing_env!(get_extend_context(carol(), carol()));
+        contract.vote(0, VoteOption::No);
+        // ivan сам не голосовал и может снять стейк, голос vasy его уже учел
+        testing_env!(get_extend_context(ivan(), ivan()));
+        contract.unstake();
+        assert_eq!(contract.get_delegated_stake(vasy()).0, 0);
+
+        let mut context = get_extend_context(carol(), carol());
+        context.block_timestamp = NANOSEC_IN_DAY;
+        testing_env!(context);
+        contract.finalize_request(0);
+        assert_eq!(contract.get_request(0).status, APPROVED);
+    }
+
+    #[test]
+    fn test_delegator_overrides_delegate_vote() {
+        let mut contract = get_contract_with_request_and_staking();
+        testing_env!(get_extend_context(ivan(), ivan()));
+        contract.delegate_votes(vasy());
+        testing_env!(get_extend_context(vasy(), vasy()));
+        contract.vote(0, VoteOption::Yes);
+        // ivan голосует сам, его стейк уходит из голоса vasy
+        testing_env!(get_extend_context(ivan(), ivan()));
+        contract.vote(0, VoteOption::No);
+        testing_env!(get_extend_context(carol(), carol()));
+        contract.vote(0, VoteOption::No);
+
+        let mut context = get_extend_context(carol(), carol());
+        context.block_timestamp = NANOSEC_IN_DAY;
+        testing_env!(context);
+        contract.finalize_request(0);
+        assert_eq!(contract.get_request(0).status, REJECTED);
+    }
+
+    fn get_contract_with_delegation_chain() -> Contract {
+        let mut contract = get_contract_with_request_and_staking();
+        testing_env!(get_extend_context(carol(), carol()));
+        contract.delegate_votes(ivan());
+        testing_env!(get_extend_context(ivan(), ivan()));
+        contract.delegate_votes(vasy());
+        contract
+    }
+
+    #[test]
+    fn test_delegation_chain() {
+        let mut contract = get_contract_with_delegation_chain();
+        assert_eq!(contract.get_delegation_chain(carol()), vec![ivan(), vasy()]);
+        assert_eq!(contract.get_delegated_stake(vasy()).0, 200);
+
+        // голос vasy несет стейк всей цепочки
+        testing_env!(get_extend_context(vasy(), vasy()));
+        contract.vote(0, VoteOption::Yes);
+        assert_eq!(get_events("vote")[0]["weight"], "300");
+
+        testing_env!(get_extend_context(carol(), carol()));
+        contract.undelegate();
+        assert_eq!(get_events("undelegate")[0]["delegate_id"], ivan());
+        assert_eq!(contract.get_delegate(carol()), None);
+        assert_eq!(contract.get_delegated_stake(vasy()).0, 100);
+        catch_unwind_silent(move || {
+            contract.undelegate();
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn test_delegated_stake_follows_staking() {
+        let mut contract = get_contract_with_delegation_chain();
+        testing_env!(get_extend_context(vasy(), vasy()));
+        contract.vote(0, VoteOption::Yes);
+        // carol не голосовала и может снять стейк, он уходит из голоса vasy
+        testing_env!(get_extend_context(carol(), carol()));
+        contract.unstake();
+        assert_eq!(contract.get_delegated_stake(ivan()).0, 0);
+        assert_eq!(contract.get_delegated_stake(vasy()).0, 100);
+        contract.stake(50);
+        assert_eq!(contract.get_delegated_stake(vasy()).0, 150);
+    }
+
+    #[test]
+    fn test_delegator_in_chain_overrides_delegate_vote() {
+        let mut contract = get_contract_with_delegation_chain();
+        update_terms(&mut contract, 0, |terms| terms.approval_threshold = 70);
+        testing_env!(get_extend_context(vasy(), vasy()));
+        contract.vote(0, VoteOption::Yes);
+        // carol голосует сама: у vasy остается 200 из 300, это меньше 70%
+        testing_env!(get_extend_context(carol(), carol()));
+        contract.vote(0, VoteOption::No);
+
+        set_time(carol(), NANOSEC_IN_DAY + 1);
+        contract.finalize_request(0);
+        assert_eq!(contract.get_request(0).status, REJECTED);
+    }
+
+    #[test]
+    fn test_delegation_cycle_failed() {
+        let mut contract = get_contract_with_delegation_chain();
+        testing_env!(get_extend_context(vasy(), vasy()));
+        catch_unwind_silent(move || {
+            contract.delegate_votes(carol());
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn test_delegation_chain_too_long_failed() {
+        let mut contract = get_contract_with_delegation_chain();
+        testing_env!(get_extend_context(bob(), bob()));
+        contract.delegate_votes(carol());
+        assert_eq!(contract.get_delegation_chain(bob()).len(), 3);
+        testing_env!(get_extend_context(alice(), alice()));
+        catch_unwind_silent(move || {
+            contract.delegate_votes(bob());
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn test_request_reject_without_stake_quorum() {
+        let mut contract = get_contract_with_request();
+        testing_env!(get_extend_context(vasy(), vasy()));
+        contract.stake(500);
+        testing_env!(get_extend_context(carol(), carol()));
+        contract.stake(100);
+        contract.vote(0, VoteOption::Yes);
+
+        let mut context = get_extend_context(carol(), carol());
+        context.block_timestamp = NANOSEC_IN_DAY;
+        testing_env!(context);
+        contract.finalize_request(0);
+        assert_eq!(contract.get_request(0).status, REJECTED);
+    }
+
+    #[test]
+    fn test_start_launchpad() {
+        let mut contract = get_contract_with_approve_request();
+        contract.start_launchpad(0, NANOSEC_IN_DAY + 100);
+        let pads = contract.get_launchpad_tokens();
+        assert_eq!(pads[0].price, 23);
+        assert_eq!(pads[0].token.token_id, get_test_request().token_id);
+        let mut context = get_extend_context(bob(), bob());
+        context.block_timestamp = NANOSEC_IN_DAY + 100;
+        testing_env!(context);
+        contract.buy_tokens_on_launchpad(get_test_request().token_id, 50);
+        assert_eq!(contract.get_launchpad(get_test_request().token_id).sell_supply, get_test_request().supply - 50);
+        assert_eq!(contract.get_balance(bob(), get_test_request().token_id).0, 50);
+    }
+
+    #[test]
+    fn buy_on_launchpad_overflow_failed() {
+        let mut contract = get_contract_with_approve_request();
+        contract.start_launchpad(0, NANOSEC_IN_DAY);
+        let mut launchpad = contract.get_launchpad(get_test_request().token_id);
+        launchpad.price = u128::MAX / 2;
+        contract.insert_launchpad_tokens(&launchpad);
+        let mut context = get_extend_context(bob(), bob());
+        context.block_timestamp = NANOSEC_IN_DAY;
+        testing_env!(context);
+        let err = catch_unwind_silent(move || {
+            contract.buy_tokens_on_launchpad(get_test_request().token_id, 3);
+        }).unwrap_err();
+        assert_eq!(err.downcast_ref::<String>().unwrap(), "Overflow");
+    }
+
+    #[test]
+    fn test_finalize_launchpad() {
+        let mut contract = get_contract_with_approve_request();
+        contract.start_launchpad(0, NANOSEC_IN_DAY + 100);
+        let mut context = get_extend_context(bob(), bob());
+        context.block_timestamp = NANOSEC_IN_DAY + 100;
+        testing_env!(context);
+        contract.buy_tokens_on_launchpad(get_test_request().token_id, 50);
+        let context = get_extend_context(carol(), carol());
+        testing_env!(context);
+        assert_eq!(contract.get_balance(carol(), get_test_request().token_id).0, 0);
+        contract.finalize_my_launchpad(get_test_request().token_id);
+        assert_eq!(contract.get_balance(carol(), get_test_request().token_id).0, get_test_request().supply - 50);
+        assert_eq!(contract.ft_total_supply(get_test_request().token_id).0, get_test_request().supply);
+    }
+
+    #[test]
+    fn launched_token_metadata() {
+        let mut contract = get_contract_with_approve_request();
+        contract.start_launchpad(0, NANOSEC_IN_DAY + 100);
+        contract.finalize_my_launchpad(get_test_request().token_id);
+        assert_eq!(contract.ft_metadata(get_test_request().token_id), get_test_request().meta);
+        assert_eq!(contract.ft_metadata(contract.get_near_token()).decimals, 24);
+    }
+
+    #[test]
+    fn invalid_metadata_failed() {
+        testing_env!(get_context(carol()));
+        let mut meta = get_test_request().meta;
+        meta.icon = Some("https://example.com/icon.png".to_string());
+        catch_unwind_silent(move || meta.assert_valid()).unwrap_err();
+
+        let mut meta = get_test_request().meta;
+        meta.reference = Some("https://example.com/token.json".to_string());
+        catch_unwind_silent(move || meta.assert_valid()).unwrap_err();
+
+        let mut meta = get_test_request().meta;
+        meta.icon = Some("data:image/svg+xml,<svg></svg>".to_string());
+        meta.assert_valid();
+    }
+
+    #[test]
+    fn test_not_started_launchpad() {
+        let mut contract = get_contract_with_approve_request();
+        contract.start_launchpad(0, NANOSEC_IN_DAY + 100);
+        let pads = contract.get_launchpad_tokens();
+        assert_eq!(pads[0].price, 23);
+        assert_eq!(pads[0].token.token_id, get_test_request().token_id);
+        let context = get_extend_context(bob(), bob());
+        testing_env!(context);
+        catch_unwind_silent(move || {
+            contract.buy_tokens_on_launchpad(get_test_request().token_id, 50);
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn test_request_reject() {
+        let mut contract = get_contract_with_request_and_staking();
+        testing_env!(get_extend_context(vasy(), vasy()));
+        contract.vote(0, VoteOption::Yes);
+        testing_env!(get_extend_context(ivan(), ivan()));
+        contract.vote(0, VoteOption::No);
+        testing_env!(get_extend_context(carol(), carol()));
+        contract.vote(0, VoteOption::No);
+
+        let mut context = get_extend_context(carol(), carol());
+        context.block_timestamp = NANOSEC_IN_DAY;
+        testing_env!(context);
+
+        contract.finalize_request(0);
+        assert_eq!(contract.get_request(0).status, REJECTED);
+    }
+
+    #[test]
+    fn test_wallets() {
+        let mut context = get_context(carol());
+        context.attached_deposit = 3 * YOKTO_NEAR;
+        testing_env!(context);
+        let mut contract = new_contract(carol());
+
+        contract.deposit_near();
+        let balance = contract
+            .get_balance(carol(), contract.get_near_token())
+            .0;
+        let account = contract
+            .get_wallet(&carol())
+            .get_account(&carol(), &contract.get_near_token());
+
+        assert_eq!(account.unwrap().balance, 3 * YOKTO_NEAR);
+        assert_eq!(balance, 3 * YOKTO_NEAR);
+        assert_eq!(contract.ft_total_supply(contract.get_near_token()).0, 3 * YOKTO_NEAR);
+    }
+
+    #[test]
+    fn test_pay() {
+        let mut context = get_context(carol());
+        context.account_balance *= YOKTO_NEAR;
+        context.attached_deposit = 3 * YOKTO_NEAR;
+        testing_env!(context);
+        let mut contract = new_contract(carol());
+        contract.deposit_near();
+
+        let mut context = get_context(carol());
+        context.account_balance *= YOKTO_NEAR;
+        context.attached_deposit = 1;
+        testing_env!(context);
+        let old_balance = env::account_balance();
+        contract.withdraw_near(U128(YOKTO_NEAR));
+        assert_eq!(env::account_balance(), old_balance - YOKTO_NEAR);
+        assert_eq!(contract.get_balance(carol(), contract.get_near_token()).0, 2 * YOKTO_NEAR);
+
+        // перевод NEAR не прошел
+        testing_env!(
+            get_context(alice()),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        contract.near_resolve_withdraw(carol(), U128(YOKTO_NEAR));
+        assert_eq!(contract.get_balance(carol(), contract.get_near_token()).0, 3 * YOKTO_NEAR);
+        assert_eq!(contract.ft_total_supply(contract.get_near_token()).0, 3 * YOKTO_NEAR);
+    }
+
+    #[test]
+    fn test_near_market() {
+        let mut context = get_context(carol());
+        context.attached_deposit = 3 * YOKTO_NEAR;
+        testing_env!(context);
+        let mut contract = new_contract(carol());
+        contract.deposit_near();
+
+        // продаем 1 NEAR за 97 XDHO
+        testing_env!(get_extend_context(carol(), carol()));
+        contract.new_limit_order(contract.get_near_token(), 97.0 / YOKTO_NEAR as f64, YOKTO_NEAR, "Ask".to_string());
+        let asks = contract.get_ask_orders(contract.get_near_token());
+        assert_eq!(asks[0].price, 97.0 / YOKTO_NEAR as f64);
+        assert_eq!(contract.get_balance(carol(), contract.get_near_token()).0, 2 * YOKTO_NEAR);
+    }
+
+    #[test]
+    fn near_market_fill() {
+        let mut context = get_context(carol());
+        context.attached_deposit = 3 * YOKTO_NEAR;
+        testing_env!(context);
+        let mut contract = new_contract(carol());
+        contract.deposit_near();
+        testing_env!(get_context(carol()));
+        contract.transfer(bob(), standart_token().token_id, U128::from(1000u128));
+
+        // цена задается в XDHO за один yocto NEAR
+        let price = 97.0 / YOKTO_NEAR as f64;
+        testing_env!(get_extend_context(carol(), carol()));
+        contract.new_limit_order(contract.get_near_token(), price, YOKTO_NEAR, "Ask".to_string());
+        testing_env!(get_extend_context(bob(), bob()));
+        contract.new_limit_order(contract.get_near_token(), price, YOKTO_NEAR, "Bid".to_string());
+
+        let filled = get_events("order_filled");
+        assert_eq!(filled.len(), 2);
+        assert!(contract.get_ask_orders(contract.get_near_token()).is_empty());
+        assert!(contract.get_bid_orders(contract.get_near_token()).is_empty());
+        let paid = 97;
+        assert_eq!(contract.get_balance(bob(), contract.get_near_token()).0, YOKTO_NEAR);
+        assert_eq!(contract.get_balance(bob(), standart_token().token_id).0, 1000 - paid);
+        assert_eq!(contract.get_balance(carol(), contract.get_near_token()).0, 2 * YOKTO_NEAR);
+        assert_eq!(
+            contract.get_balance(carol(), standart_token().token_id).0,
+            standart_token().total_supply - 1000 + paid
+        );
+        assert_eq!(contract.get_balance(alice(), standart_token().token_id).0, 0);
+        assert_eq!(contract.get_balance(alice(), contract.get_near_token()).0, 0);
+    }
+
+    #[test]
+    fn test_self_allowance_fail() {
+        let context = get_context(carol());
+        testing_env!(context);
+        let total_supply = standart_token().total_supply;
+        let mut contract = new_contract(carol());
+        catch_unwind_silent(move || {
+            contract.set_allowance(carol(), standart_token().token_id, (total_supply / 2).into(), None);
+        })
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_carol_add_new_token() {
+        // Acting as carol
+        testing_env!(get_context(carol()));
+        let total_supply = 10000;
+        let mut contract = new_contract(carol());
+
+        contract.add_token(Token {
+            token_id: test_token().token_id,
+            total_supply: test_token().total_supply,
+            max_supply: None,
+            owner_id: carol(),
+            meta: TokenMetadata::new("TEST", "TEST", 2),
+            origin: TokenOrigin::Native,
+        });
+        let balance = contract
+            .get_balance(carol(), test_token().token_id)
+            .0;
+        assert_eq!(balance, 10000u128);
+
+        let allowance = total_supply / 3;
+        let transfer_amount = allowance / 3;
+        contract.set_allowance(bob(), test_token().token_id, allowance.into(), None);
+        assert_eq!(
+            contract
+                .get_allowance(carol(), bob(), test_token().token_id)
+                .0,
+            allowance
+        );
+        // Acting as bob now
+        testing_env!(get_context(bob()));
+        contract.transfer_from(
+            carol(),
+            alice(),
+            test_token().token_id,
+            transfer_amount.into(),
+        );
+        assert_eq!(
+            contract.get_balance(carol(), test_token().token_id).0,
+            total_supply - transfer_amount
+        );
+        assert_eq!(
+            contract.get_balance(alice(), test_token().token_id).0,
+            transfer_amount
+        );
+        assert_eq!(
+            contract.get_allowance(carol(), bob(), test_token().token_id).0,
+            allowance - transfer_amount
+        );
+    }
+
+    #[test]
+    fn test_carol_escrows_to_bob_transfers_to_alice() {
+        // Acting as carol
+        testing_env!(get_context(carol()));
+        let total_supply = standart_token().total_supply;
+        let mut contract = new_contract(carol());
+        assert_eq!(contract.get_total_supply(standart_token().token_id).0, total_supply);
+        let allowance = total_supply / 3;
+        let transfer_amount = allowance / 3;
+        contract.set_allowance(bob(), standart_token().token_id, allowance.into(), None);
+        assert_eq!(contract.get_allowance(carol(), bob(), standart_token().token_id).0, allowance);
+        // Acting as bob now
+        testing_env!(get_context(bob()));
+        contract.transfer_from(carol(), alice(), standart_token().token_id, transfer_amount.into());
+        assert_eq!(
+            contract.get_balance(carol(), standart_token().token_id).0,
+            total_supply - transfer_amount
+        );
+        assert_eq!(contract.get_balance(alice(), standart_token().token_id).0, transfer_amount);
+        assert_eq!(
+            contract.get_allowance(carol(), bob(), standart_token().token_id).0,
+            allowance - transfer_amount
+        );
+    }
+
+    fn print_all_balances(contract: &Contract, account: AccountId) {
+        for (token, balance) in contract.get_balances(
+            account.clone(),
+            [standart_token().token_id, test_token().token_id].to_vec(),
+        ) {
+            println!("Acc:{} token: {} balance: {}", account, token, balance);
+        }
+    }
+
+    fn init_contract_with_tokens_and_limit_bids() -> Contract {
+        let mut contract = init_contract_with_tokens();
+        contract.new_limit_order(test_token().token_id, 5.0, 100, "Ask".to_string());
+        contract.new_limit_order(test_token().token_id, 6.0, 200, "Ask".to_string());
+        contract.new_limit_order(test_token().token_id, 10.0, 300, "Ask".to_string());
+        contract.new_limit_order(test_token().token_id, 11.0, 500, "Ask".to_string());
+        contract.new_limit_order(test_token().token_id, 16.0, 1000, "Ask".to_string());
+        contract.new_limit_order(test_token().token_id, 3.0, 20, "Bid".to_string());
+        contract.new_limit_order(test_token().token_id, 2.0, 40, "Bid".to_string());
+        contract.new_limit_order(test_token().token_id, 1.0, 100, "Bid".to_string());
+
+        let context = get_extend_context(carol(), carol());
+        testing_env!(context);
+        contract.new_limit_order(test_token().token_id, 5.0, 50, "Ask".to_string());
+        contract.new_limit_order(test_token().token_id, 4.0, 30, "Ask".to_string());
+        contract.new_limit_order(test_token().token_id, 1.0, 100, "Bid".to_string());
+
+        let spread = contract.get_current_spread(test_token().token_id);
+        println!("Spread => Ask: {}, Bid: {}", spread[0], spread[1]);
+        assert_eq!(spread[0], 4.0);
+        assert_eq!(spread[1], 3.0);
+        contract
+    }
+
+    #[test]
+    fn new_limit_order() {
+        testing_env!(get_context(bob()));
+        let mut contract = init_contract_with_tokens();
+
+        // Currrent Spread
+        let spread = contract.get_current_spread(test_token().token_id);
+        println!("Spread => Ask: {}, Bid: {}", spread[0], spread[1]);
+        assert_eq!(spread[0], 0.0);
+        assert_eq!(spread[1], 0.0);
+
+        let std_balance = contract.get_balance(bob(), standart_token().token_id).0;
+        let test_balance = contract.get_balance(bob(), test_token().token_id).0;
+        // Ask Order
+        contract.new_limit_order(test_token().token_id, 1.25, 2, "Ask".to_string());
+        // Bid Order
+        contract.new_limit_order(test_token().token_id, 1.22, 50, "Bid".to_string());
+        contract.new_limit_order(test_token().token_id, 1.20, 50, "Bid".to_string());
+
+        assert_eq!(
+            contract.get_balance(alice(), standart_token().token_id).0 +
+                contract.get_balance(bob(), standart_token().token_id).0,
+            std_balance
+        );
+        assert_eq!(
+            contract.get_balance(alice(), test_token().token_id).0 +
+                contract.get_balance(bob(), test_token().token_id).0,
+            test_balance
+        );
+        let spread = contract.get_current_spread(test_token().token_id);
+        println!("Spread => Ask: {}, Bid: {}", spread[0], spread[1]);
+        assert_eq!(spread[0], 1.25);
+        assert_eq!(spread[1], 1.22);
+    }
+
+    #[test]
+    fn matching_limit_order() {
+        let mut contract = init_contract_with_tokens_and_limit_bids();
+        let context = get_extend_context(ivan(), ivan());
+        testing_env!(context);
+
+        assert_eq!(contract.get_balance(ivan(), standart_token().token_id).0, 1000u128);
+        let balance_bob = contract.get_balance(bob(), test_token().token_id).0;
+        contract.new_limit_order(test_token().token_id, 1.0, 50, "Ask".to_string());
+        assert_eq!(contract.get_balance(ivan(), standart_token().token_id).0, 1120u128);
+        assert_eq!(contract.get_balance(bob(), test_token().token_id).0, balance_bob + 50);
+
+        let spread = contract.get_current_spread(test_token().token_id);
+        println!("Spread => Ask: {}, Bid: {}", spread[0], spread[1]);
+        assert_eq!(spread[0], 4.0);
+        assert_eq!(spread[1], 2.0);
+
+        print_all_balances(&contract, ivan());
+        contract.new_limit_order(test_token().token_id, 5.0, 50, "Bid".to_string());
+        print_all_balances(&contract, bob());
+        print_all_balances(&contract, ivan());
+        assert_eq!(contract.get_balance(bob(), standart_token().token_id).0, 99999996860u128);
+        assert_eq!(contract.get_balance(ivan(), test_token().token_id).0, 1000u128);
+        let spread = contract.get_current_spread(test_token().token_id);
+        println!("Spread => Ask: {}, Bid: {}", spread[0], spread[1]);
+        assert_eq!(spread[0], 5.0);
+        assert_eq!(spread[1], 2.0);
+    }
+
+    #[test]
+    fn matching_market_order() {
+        let mut contract = init_contract_with_tokens_and_limit_bids();
+        let context = get_extend_context(ivan(), ivan());
+        testing_env!(context);
+
+        assert_eq!(contract.get_balance(ivan(), standart_token().token_id).0, 1000u128);
+        assert_eq!(contract.get_balance(bob(), test_token().token_id).0, 5900u128);
+        contract.new_market_order(test_token().token_id, 50, "Ask".to_string());
+        assert_eq!(contract.get_balance(ivan(), standart_token().token_id).0, 1120u128);
+        assert_eq!(contract.get_balance(bob(), test_token().token_id).0, 5950u128);
+        print_all_balances(&contract, bob());
+        print_all_balances(&contract, ivan());
+
+        let spread = contract.get_current_spread(test_token().token_id);
+        println!("Spread => Ask: {}, Bid: {}", spread[0], spread[1]);
+        assert_eq!(spread[0], 4.0);
+        assert_eq!(spread[1], 2.0);
+
+        contract.new_market_order(test_token().token_id, 50, "Bid".to_string());
+
+        print_all_balances(&contract, ivan());
+        let spread = contract.get_current_spread(test_token().token_id);
+        println!("Spread => Ask: {}, Bid: {}", spread[0], spread[1]);
+        assert_eq!(spread[0], 5.0);
+        assert_eq!(spread[1], 2.0);
+    }
+
+    fn get_payable_context(predecessor_account_id: AccountId, attached_deposit: u128) -> VMContext {
+        let mut context = get_extend_context(predecessor_account_id.clone(), predecessor_account_id);
+        context.attached_deposit = attached_deposit;
+        context
+    }
+
+    #[test]
+    fn ft_transfer() {
+        testing_env!(get_payable_context(bob(), 1));
+        let mut contract = new_contract(bob());
+        assert_eq!(contract.ft_total_supply(standart_token().token_id).0, standart_token().total_supply);
+
+        contract.ft_transfer(carol(), standart_token().token_id, U128(1000), Some("hi".to_string()));
+        assert_eq!(contract.ft_balance_of(carol(), standart_token().token_id).0, 1000);
+        assert_eq!(
+            contract.ft_balance_of(bob(), standart_token().token_id).0,
+            standart_token().total_supply - 1000
+        );
+    }
+
+    #[test]
+    fn ft_transfer_without_yocto_failed() {
+        testing_env!(get_payable_context(bob(), 0));
+        let mut contract = new_contract(bob());
+        catch_unwind_silent(move || {
+            contract.ft_transfer(carol(), standart_token().token_id, U128(1000), None);
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn ft_resolve_transfer_refund() {
+        testing_env!(get_payable_context(bob(), 1));
+        let mut contract = new_contract(bob());
+        contract.ft_transfer(carol(), standart_token().token_id, U128(1000), None);
+
+        // получатель вернул 400 токенов
+        testing_env!(
+            get_context(alice()),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Successful(b"\"400\"".to_vec())]
+        );
+        let used = contract.ft_resolve_transfer(bob(), carol(), standart_token().token_id, U128(1000));
+        assert_eq!(used.0, 600);
+        assert_eq!(contract.ft_balance_of(carol(), standart_token().token_id).0, 600);
+    }
+
+    #[test]
+    fn ft_resolve_transfer_failed_call() {
+        testing_env!(get_payable_context(bob(), 1));
+        let mut contract = new_contract(bob());
+        contract.ft_transfer(carol(), standart_token().token_id, U128(1000), None);
+
+        testing_env!(
+            get_context(alice()),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        let used = contract.ft_resolve_transfer(bob(), carol(), standart_token().token_id, U128(1000));
+        assert_eq!(used.0, 0);
+        assert_eq!(contract.ft_balance_of(carol(), standart_token().token_id).0, 0);
+        assert_eq!(contract.ft_balance_of(bob(), standart_token().token_id).0, standart_token().total_supply);
+    }
+
+    #[test]
+    fn ft_resolve_transfer_to_vested_receiver() {
+        let mut contract = init_contract_with_tokens();
+        contract.create_vesting(vasy(), test_token().token_id, U128(400), vesting_terms(false), None);
+        contract.transfer(vasy(), test_token().token_id, U128(100));
+        testing_env!(get_context(vasy()));
+        contract.transfer(ivan(), test_token().token_id, U128(50));
+
+        // из 100 возвращаемых токенов у получателя свободно только 50
+        testing_env!(
+            get_context(alice()),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Successful(b"\"100\"".to_vec())]
+        );
+        let used = contract.ft_resolve_transfer(bob(), vasy(), test_token().token_id, U128(100));
+        assert_eq!(used.0, 50);
+        assert_eq!(contract.get_balance(vasy(), test_token().token_id).0, 400);
+    }
+
+    /// Возвращает `data` всех событий `event` из логов
+    fn get_events(event: &str) -> Vec<serde_json::Value> {
+        let mut result = Vec::new();
+        for log in get_logs() {
+            let json: serde_json::Value = serde_json::from_str(log.strip_prefix("EVENT_JSON:").unwrap()).unwrap();
+            assert!(json["version"].is_string());
+            if json["event"] == event {
+                result.extend(json["data"].as_array().unwrap().clone());
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn transfer_events() {
+        testing_env!(get_context(carol()));
+        let mut contract = new_contract(carol());
+        testing_env!(get_context(carol()));
+        contract.transfer(bob(), standart_token().token_id, U128(100));
+        contract.set_allowance(bob(), standart_token().token_id, U128(50), None);
+
+        let transfers = get_events("mt_transfer");
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0]["old_owner_id"], carol());
+        assert_eq!(transfers[0]["new_owner_id"], bob());
+        assert_eq!(transfers[0]["token_ids"][0], standart_token().token_id);
+        assert_eq!(transfers[0]["amounts"][0], "100");
+        let allowances = get_events("allowance_set");
+        assert_eq!(allowances[0]["escrow_account_id"], bob());
+        assert_eq!(allowances[0]["allowance"], "50");
+    }
+
+    #[test]
+    fn order_events() {
+        let mut contract = init_contract_with_tokens_and_limit_bids();
+        testing_env!(get_extend_context(ivan(), ivan()));
+        contract.new_limit_order(test_token().token_id, 1.0, 50, "Ask".to_string());
+
+        let accepted = get_events("order_accepted");
+        assert_eq!(accepted.len(), 1);
+        assert_eq!(accepted[0]["order_creator"], ivan());
+        let filled = get_events("order_filled");
+        assert_eq!(filled[0]["order_creator"], ivan());
+        assert_eq!(filled[0]["partial"], true);
+        assert_eq!(filled[0]["qty"], "20");
+        assert_eq!(filled[1]["order_creator"], bob());
+        assert_eq!(filled[1]["partial"], false);
+
+        testing_env!(get_extend_context(bob(), bob()));
+        contract.cancel_limit_order(test_token().token_id, 7, "Bid".to_string());
+        assert_eq!(get_events("order_cancelled")[0]["order_id"], 7);
+    }
+
+    #[test]
+    fn governance_events() {
+        let mut contract = get_contract_with_request();
+        assert_eq!(get_events("request_status")[0]["status"], "PENDING");
+
+        testing_env!(get_extend_context(carol(), carol()));
+        contract.stake(100);
+        contract.vote(0, VoteOption::Yes);
+        assert_eq!(get_events("stake")[0]["amount"], "100");
+        assert_eq!(get_events("vote")[0]["voter_id"], carol());
+        assert_eq!(get_events("vote")[0]["vote"], "Yes");
+        assert_eq!(get_events("vote")[0]["weight"], "100");
+
+        let mut context = get_extend_context(carol(), carol());
+        context.block_timestamp = NANOSEC_IN_DAY;
+        testing_env!(context);
+        contract.finalize_request(0);
+        assert_eq!(get_events("request_status")[0]["status"], "APPROVED");
+    }
+
+    #[test]
+    fn launchpad_events() {
+        let mut contract = get_contract_with_approve_request();
+        contract.start_launchpad(0, NANOSEC_IN_DAY + 100);
+        assert_eq!(get_events("request_status").last().unwrap()["status"], "LAUNCHED");
+
+        let mut context = get_extend_context(bob(), bob());
+        context.block_timestamp = NANOSEC_IN_DAY + 100;
+        testing_env!(context);
+        contract.buy_tokens_on_launchpad(get_test_request().token_id, 50);
+        let purchases = get_events("launchpad_purchase");
+        assert_eq!(purchases[0]["buyer_id"], bob());
+        assert_eq!(purchases[0]["amount"], "50");
+        assert_eq!(purchases[0]["price"], "23");
+    }
+
+    #[test]
+    fn storage_deposit_and_withdraw() {
+        testing_env!(get_context(bob()));
+        let mut contract = Contract::new(bob());
+        let min = contract.storage_balance_bounds().min.0;
+        assert!(contract.storage_balance_of(carol()).is_none());
+
+        testing_env!(get_payable_context(carol(), min + 1000));
+        let balance = contract.storage_deposit(None, None);
+        assert_eq!(balance.total.0, min + 1000);
+        assert_eq!(balance.available.0, 1000);
+
+        let mut context = get_payable_context(carol(), 1);
+        context.account_balance = YOKTO_NEAR;
+        testing_env!(context);
+        let balance = contract.storage_withdraw(None);
+        assert_eq!(balance.total.0, min);
+        assert_eq!(balance.available.0, 0);
+    }
+
+    #[test]
+    fn storage_deposit_registration_only() {
+        testing_env!(get_context(bob()));
+        let mut contract = Contract::new(bob());
+        let min = contract.storage_balance_bounds().min.0;
+
+        testing_env!(get_payable_context(carol(), min * 2));
+        let balance = contract.storage_deposit(Some(ivan()), Some(true));
+        assert_eq!(balance.total.0, min);
+        assert_eq!(contract.storage_balance_of(ivan()).unwrap().total.0, min);
+    }
+
+    #[test]
+    fn storage_is_charged_for_orders() {
+        let mut contract = init_contract_with_tokens();
+        let available = contract.storage_balance_of(bob()).unwrap().available.0;
+        contract.new_limit_order(test_token().token_id, 5.0, 100, "Ask".to_string());
+        assert!(contract.storage_balance_of(bob()).unwrap().available.0 < available);
+    }
+
+    fn cancel_ask(contract: &mut Contract, price: f64) {
+        let orders = contract.get_orders(bob(), test_token().token_id, "Ask".to_string());
+        let order = orders.iter().find(|order| order.price == price).unwrap();
+        contract.cancel_limit_order(test_token().token_id, order.order_id, "Ask".to_string());
+    }
+
+    #[test]
+    fn order_storage_is_refunded_to_owner() {
+        let mut contract = init_contract_with_tokens();
+        // первый ордер заводит счет контракта, дальше платится только место ордера в стакане
+        contract.new_limit_order(test_token().token_id, 50.0, 10, "Ask".to_string());
+        let available = contract.storage_balance_of(bob()).unwrap().available.0;
+
+        contract.new_limit_order(test_token().token_id, 5.0, 100, "Ask".to_string());
+        assert!(contract.storage_balance_of(bob()).unwrap().available.0 < available);
+        cancel_ask(&mut contract, 5.0);
+        assert_eq!(contract.storage_balance_of(bob()).unwrap().available.0, available);
+
+        // место исполненного ордера возвращается владельцу, а не покупателю
+        contract.new_limit_order(test_token().token_id, 5.0, 100, "Ask".to_string());
+        testing_env!(get_extend_context(ivan(), ivan()));
+        let ivan_available = contract.storage_balance_of(ivan()).unwrap().available.0;
+        contract.new_market_order(test_token().token_id, 100, "Bid".to_string());
+        assert_eq!(contract.get_balance(ivan(), test_token().token_id).0, 1100);
+        assert_eq!(contract.storage_balance_of(bob()).unwrap().available.0, available);
+        assert!(contract.storage_balance_of(ivan()).unwrap().available.0 <= ivan_available);
+    }
+
+    #[test]
+    fn cancel_bid_refunds_escrow_left() {
+        let mut contract = init_contract_with_tokens();
+        let balance = contract.get_balance(bob(), standart_token().token_id).0;
+        // в эскроу уходит 3 XDHO, а сделка на 5 токенов стоит 1.5 и платит продавцу 1
+        contract.new_limit_order(test_token().token_id, 0.3, 10, "Bid".to_string());
+        testing_env!(get_extend_context(carol(), carol()));
+        contract.new_limit_order(test_token().token_id, 0.3, 5, "Ask".to_string());
+
+        testing_env!(get_extend_context(bob(), bob()));
+        let bid = contract.get_orders(bob(), test_token().token_id, "Bid".to_string())[0].order_id;
+        contract.cancel_limit_order(test_token().token_id, bid, "Bid".to_string());
+        assert_eq!(contract.get_balance(bob(), standart_token().token_id).0, balance - 1);
+        assert_eq!(contract.get_balance(carol(), standart_token().token_id).0, 1001);
+        assert_eq!(contract.get_balance(alice(), standart_token().token_id).0, 0);
+    }
+
+    #[test]
+    fn filled_bid_refunds_price_improvement() {
+        let mut contract = init_contract_with_tokens();
+        testing_env!(get_extend_context(carol(), carol()));
+        contract.new_limit_order(test_token().token_id, 5.0, 10, "Ask".to_string());
+
+        testing_env!(get_extend_context(bob(), bob()));
+        let balance = contract.get_balance(bob(), standart_token().token_id).0;
+        contract.new_limit_order(test_token().token_id, 6.0, 10, "Bid".to_string());
+        assert_eq!(contract.get_balance(bob(), standart_token().token_id).0, balance - 50);
+        assert_eq!(contract.get_balance(alice(), standart_token().token_id).0, 0);
+    }
+
+    #[test]
+    fn orders_of_unregistered_maker() {
+        let mut contract = init_contract_with_tokens();
+        contract.new_limit_order(test_token().token_id, 5.0, 100, "Ask".to_string());
+        contract.new_limit_order(test_token().token_id, 6.0, 100, "Ask".to_string());
+        // как у ордеров, выставленных до учета места
+        contract.storage_accounts.remove(&env::sha256(bob().as_bytes()));
+
+        testing_env!(get_extend_context(ivan(), ivan()));
+        contract.new_limit_order(test_token().token_id, 5.0, 100, "Bid".to_string());
+        assert_eq!(contract.get_balance(ivan(), test_token().token_id).0, 1100);
+
+        testing_env!(get_extend_context(bob(), bob()));
+        let balance = contract.get_balance(bob(), test_token().token_id).0;
+        cancel_ask(&mut contract, 6.0);
+        assert_eq!(contract.get_balance(bob(), test_token().token_id).0, balance + 100);
+        assert!(contract.storage_balance_of(bob()).is_none());
+    }
+
+    #[test]
+    fn transfer_whole_balance_without_storage_deposit() {
+        let mut contract = init_contract_with_tokens();
+        let dave = "dave.near".to_string();
+        contract.transfer(dave.clone(), standart_token().token_id, U128(10));
+
+        testing_env!(get_context(dave.clone()));
+        contract.transfer(carol(), standart_token().token_id, U128(5));
+        contract.transfer(carol(), standart_token().token_id, U128(5));
+        assert_eq!(contract.get_balance(dave, standart_token().token_id).0, 0);
+    }
+
+    #[test]
+    fn order_without_storage_deposit_failed() {
+        testing_env!(get_context(bob()));
+        let mut contract = Contract::new(bob());
+        contract.add_token(test_token());
+        catch_unwind_silent(move || {
+            contract.new_limit_order(test_token().token_id, 5.0, 100, "Ask".to_string());
+        }).unwrap_err();
+    }
+
+    fn init_contract_with_bridged_token() -> Contract {
+        testing_env!(get_context(bob()));
+        let mut contract = new_contract(bob());
+        contract.add_bridged_token(
+            "USDT".to_string(),
+            "usdt.near".to_string(),
+            TokenMetadata::new("Tether USD", "USDT", 6),
+        );
+
+        testing_env!(get_extend_context("usdt.near".to_string(), carol()));
+        contract.ft_on_transfer(carol(), U128(500), "".to_string());
+        contract
+    }
+
+    #[test]
+    fn bridged_deposit() {
+        let contract = init_contract_with_bridged_token();
+        assert_eq!(contract.get_balance(carol(), "USDT".to_string()).0, 500);
+        assert_eq!(contract.ft_total_supply("USDT".to_string()).0, 500);
+        assert_eq!(
+            contract.get_token(&"USDT".to_string()).origin,
+            TokenOrigin::Bridged { contract_id: "usdt.near".to_string() }
+        );
+    }
+
+    #[test]
+    fn deposit_from_unknown_contract_failed() {
+        let mut contract = init_contract_with_bridged_token();
+        testing_env!(get_extend_context("fake.near".to_string(), carol()));
+        catch_unwind_silent(move || {
+            contract.ft_on_transfer(carol(), U128(500), "".to_string());
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn bridged_withdraw() {
+        let mut contract = init_contract_with_bridged_token();
+        testing_env!(get_payable_context(carol(), 1));
+        contract.withdraw("USDT".to_string(), U128(200));
+        assert_eq!(contract.get_balance(carol(), "USDT".to_string()).0, 300);
+        assert_eq!(contract.ft_total_supply("USDT".to_string()).0, 300);
+        let burns = get_events("mt_burn");
+        assert_eq!(burns[0]["owner_id"], carol());
+        assert_eq!(burns[0]["token_ids"][0], "USDT");
+        assert_eq!(burns[0]["amounts"][0], "200");
+
+        // перевод во внешнем контракте не прошел
+        testing_env!(
+            get_context(alice()),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        contract.ft_resolve_withdraw(carol(), "USDT".to_string(), U128(200));
+        assert_eq!(contract.get_balance(carol(), "USDT".to_string()).0, 500);
+        assert_eq!(contract.ft_total_supply("USDT".to_string()).0, 500);
+    }
+
+    #[test]
+    fn native_withdraw_failed() {
+        let mut contract = init_contract_with_bridged_token();
+        testing_env!(get_payable_context(bob(), 1));
+        catch_unwind_silent(move || {
+            contract.withdraw(standart_token().token_id, U128(200));
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn mint_by_issuer() {
+        let mut contract = init_contract_with_tokens();
+        contract.mint(test_token().token_id, U128(500), Some(carol()));
+        assert_eq!(contract.get_balance(carol(), test_token().token_id).0, 1500);
+        assert_eq!(contract.ft_total_supply(test_token().token_id).0, 10500);
+    }
+
+    #[test]
+    fn mint_by_non_issuer_failed() {
+        let mut contract = init_contract_with_tokens();
+        testing_env!(get_context(carol()));
+        catch_unwind_silent(move || {
+            contract.mint(test_token().token_id, U128(500), None);
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn mint_over_max_supply_failed() {
+        let mut contract = init_contract_with_tokens();
+        contract.add_token(Token {
+            token_id: "CAPPED".to_string(),
+            owner_id: bob(),
+            total_supply: 1000,
+            max_supply: Some(1200),
+            meta: TokenMetadata::new("CAPPED", "CAPPED", 2),
+            origin: TokenOrigin::Native,
+        });
+        contract.mint("CAPPED".to_string(), U128(200), None);
+        assert_eq!(contract.ft_total_supply("CAPPED".to_string()).0, 1200);
+        catch_unwind_silent(move || {
+            contract.mint("CAPPED".to_string(), U128(1), None);
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn burn() {
+        let mut contract = init_contract_with_tokens();
+        testing_env!(get_context(carol()));
+        contract.burn(test_token().token_id, U128(400));
+        assert_eq!(contract.get_balance(carol(), test_token().token_id).0, 600);
+        assert_eq!(contract.ft_total_supply(test_token().token_id).0, 9600);
+        catch_unwind_silent(move || {
+            contract.burn(test_token().token_id, U128(601));
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn increase_and_decrease_allowance() {
+        let mut contract = init_contract_with_tokens();
+        testing_env!(get_context(carol()));
+        contract.increase_allowance(bob(), test_token().token_id, U128(100), None);
+        contract.increase_allowance(bob(), test_token().token_id, U128(50), None);
+        contract.decrease_allowance(bob(), test_token().token_id, U128(30));
+        assert_eq!(contract.get_allowance(carol(), bob(), test_token().token_id).0, 120);
+        assert_eq!(contract.get_allowance(carol(), bob(), standart_token().token_id).0, 0);
+        catch_unwind_silent(move || {
+            contract.decrease_allowance(bob(), test_token().token_id, U128(121));
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn expired_allowance_failed() {
+        let mut contract = init_contract_with_tokens();
+        testing_env!(get_context(carol()));
+        contract.increase_allowance(bob(), test_token().token_id, U128(100), Some(NANOSEC_IN_DAY));
+
+        let mut context = get_context(bob());
+        context.block_timestamp = NANOSEC_IN_DAY - 1;
+        testing_env!(context);
+        contract.transfer_from(carol(), ivan(), test_token().token_id, U128(40));
+        assert_eq!(contract.get_allowance(carol(), bob(), test_token().token_id).0, 60);
+
+        let mut context = get_context(bob());
+        context.block_timestamp = NANOSEC_IN_DAY;
+        testing_env!(context);
+        assert_eq!(contract.get_allowance(carol(), bob(), test_token().token_id).0, 0);
+        catch_unwind_silent(move || {
+            contract.transfer_from(carol(), ivan(), test_token().token_id, U128(10));
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn get_allowances() {
+        let mut contract = init_contract_with_tokens();
+        testing_env!(get_context(carol()));
+        contract.increase_allowance(bob(), test_token().token_id, U128(100), None);
+        contract.increase_allowance(ivan(), standart_token().token_id, U128(10), Some(NANOSEC_IN_DAY));
+        let allowances = contract.get_allowances(carol(), None);
+        assert_eq!(allowances.len(), 2);
+        assert!(allowances.contains(&AllowanceView {
+            token_id: standart_token().token_id,
+            escrow_account_id: ivan(),
+            allowance: U128(10),
+            expires_at: Some(NANOSEC_IN_DAY),
+        }));
+        assert_eq!(contract.get_allowances(carol(), Some(test_token().token_id))[0].escrow_account_id, bob());
+    }
+
+    #[test]
+    fn batch_transfer() {
+        let mut contract = init_contract_with_tokens();
+        testing_env!(get_context(bob()));
+        contract.batch_transfer(test_token().token_id, vec![
+            (carol(), U128(100)),
+            (vasy(), U128(200)),
+        ]);
+        assert_eq!(contract.get_balance(bob(), test_token().token_id).0, 7700);
+        assert_eq!(contract.get_balance(carol(), test_token().token_id).0, 1100);
+        assert_eq!(contract.get_balance(vasy(), test_token().token_id).0, 200);
+        assert_eq!(get_events("mt_transfer").len(), 2);
+    }
+
+    #[test]
+    fn batch_transfer_not_enough_balance_failed() {
+        let mut contract = init_contract_with_tokens();
+        testing_env!(get_context(carol()));
+        catch_unwind_silent(move || {
+            contract.batch_transfer(test_token().token_id, vec![
+                (ivan(), U128(600)),
+                (vasy(), U128(600)),
+            ]);
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn airdrop() {
+        let mut contract = init_contract_with_tokens();
+        let snapshot_id = contract.snapshot(test_token().token_id);
+        // после снимка carol продает все, ivan - половину: делится по балансам на момент снимка
+        testing_env!(get_context(carol()));
+        contract.transfer(vasy(), test_token().token_id, U128(1000));
+        testing_env!(get_context(ivan()));
+        contract.transfer(vasy(), test_token().token_id, U128(500));
+
+        testing_env!(get_context(bob()));
+        let airdrop_id = contract.create_airdrop(test_token().token_id, U128(1000), snapshot_id);
+        assert_eq!(contract.get_balance(bob(), test_token().token_id).0, 7000);
+        // bob, ivan и vasy
+        assert_eq!(contract.get_airdrop(airdrop_id).remaining, 3);
+
+        // у vasy на момент снимка ничего не было
+        assert!(!contract.process_airdrop(airdrop_id, Some(2)));
+        assert_eq!(contract.get_balance(ivan(), test_token().token_id).0, 600);
+        assert_eq!(contract.get_balance(vasy(), test_token().token_id).0, 1500);
+        assert_eq!(contract.get_airdrop(airdrop_id).remaining, 1);
+
+        testing_env!(get_context(carol()));
+        assert!(contract.process_airdrop(airdrop_id, None));
+        // carol продала все после снимка, но получает долю по балансу на момент снимка
+        assert_eq!(contract.get_balance(carol(), test_token().token_id).0, 100);
+        assert_eq!(contract.get_balance(bob(), test_token().token_id).0, 7800);
+        assert_eq!(contract.get_balance(alice(), test_token().token_id).0, 0);
+        catch_unwind_silent(move || {
+            contract.get_airdrop(airdrop_id);
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn airdrop_pays_each_holder_once() {
+        let mut contract = init_contract_with_tokens();
+        let snapshot_id = contract.snapshot(test_token().token_id);
+        // XDHO раздается держателям TEST: bob, carol, ivan
+        let airdrop_id = contract.create_airdrop(standart_token().token_id, U128(1000), snapshot_id);
+        assert!(!contract.process_airdrop(airdrop_id, Some(1)));
+        assert_eq!(contract.get_balance(ivan(), standart_token().token_id).0, 1100);
+        // carol выходит, и ivan переезжает на ее место в еще не пройденной части индекса
+        testing_env!(get_context(carol()));
+        contract.transfer(vasy(), test_token().token_id, U128(1000));
+        assert!(contract.process_airdrop(airdrop_id, None));
+        assert_eq!(contract.get_balance(ivan(), standart_token().token_id).0, 1100);
+        assert_eq!(contract.get_balance(carol(), standart_token().token_id).0, 1100);
+    }
+
+    #[test]
+    fn airdrop_by_non_issuer_failed() {
+        let mut contract = init_contract_with_tokens();
+        let snapshot_id = contract.snapshot(test_token().token_id);
+        testing_env!(get_context(carol()));
+        catch_unwind_silent(move || {
+            contract.create_airdrop(test_token().token_id, U128(100), snapshot_id);
+        }).unwrap_err();
+    }
 
-                    println!("Частично выполнен ордер №{} от {} {} {} ${}",
-                             id,
-                             order_creator,
-                             match side {
-                                 OrderSide::Bid => "на покупку",
-                                 OrderSide::Ask => "на продажу",
-                             },
-                             *qty,
-                             token_id,
-                    );
-                    match *order_type {
-                        OrderType::Limit => {
-                            let amount = match *side {
-                                OrderSide::Ask => (price * (*qty).to_f64().unwrap()).to_u128().unwrap(),
-                                OrderSide::Bid => *qty,
-                            };
+    #[test]
+    fn get_holders() {
+        let mut contract = init_contract_with_tokens();
+        assert_eq!(contract.get_holder_count(test_token().token_id), 3);
+        testing_env!(get_context(carol()));
+        contract.transfer(bob(), test_token().token_id, U128(1000));
+        assert_eq!(contract.get_holder_count(test_token().token_id), 2);
+        let holders = contract.get_holders(test_token().token_id, 0, 10);
+        assert!(holders.contains(&(bob(), U128(9000))));
+        assert!(holders.contains(&(ivan(), U128(1000))));
+        assert_eq!(contract.get_holders(test_token().token_id, 1, 10).len(), 1);
+    }
 
-                            self.transfer_from_contract(
-                                order_creator.to_string(),
-                                token,
-                                U128::from(amount),
-                            );
-                        }
-                        OrderType::Market => {
-                            match side {
-                                OrderSide::Bid => {
-                                    let amount = U128::from((price * (*qty).to_f64().unwrap()).to_u128().unwrap());
-                                    // переводим токены на контракт, откуда их возьмет продавец
-                                    self.transfer(
-                                        env::current_account_id(),
-                                        self.get_standard_token(),
-                                        amount,
-                                    );
-                                    self.transfer_from_contract(
-                                        order_creator.to_string(),
-                                        token,
-                                        U128::from(*qty),
-                                    );
-                                }
-                                OrderSide::Ask => {
-                                    self.transfer_from_contract(
-                                        order_creator.to_string(),
-                                        self.get_standard_token(),
-                                        U128::from((price * (*qty).to_f64().unwrap()).to_u128().unwrap()),
-                                    );
-                                }
-                            };
-                        }
-                    }
-                }
-                Success::Amended {
-                    id: _,
-                    price: _,
-                    qty: _,
-                    ts: _,
-                } => {}
-                Success::Cancelled { id: _, ts: _ } => {}
-            };
+    #[test]
+    fn snapshot_balances() {
+        let mut contract = init_contract_with_tokens();
+        let first = contract.snapshot(test_token().token_id);
+        contract.transfer(carol(), test_token().token_id, U128(500));
+        let second = contract.snapshot(test_token().token_id);
+        let third = contract.snapshot(test_token().token_id);
+        contract.transfer(carol(), test_token().token_id, U128(500));
+        contract.mint(test_token().token_id, U128(100), None);
+
+        assert_eq!(contract.get_balance_at(first, carol()).0, 1000);
+        assert_eq!(contract.get_balance_at(second, carol()).0, 1500);
+        assert_eq!(contract.get_balance_at(third, carol()).0, 1500);
+        assert_eq!(contract.get_balance_at(third, bob()).0, 7500);
+        assert_eq!(contract.get_balance_at(third, ivan()).0, 1000);
+        assert_eq!(contract.get_balance(carol(), test_token().token_id).0, 2000);
+        assert_eq!(contract.get_snapshot(third).total_supply.0, 10000);
+
+        testing_env!(get_context(carol()));
+        catch_unwind_silent(move || {
+            contract.snapshot(test_token().token_id);
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn dividends() {
+        // bob: 8000 TEST, carol: 1000, ivan: 1000
+        let mut contract = init_contract_with_tokens();
+        contract.deposit_dividends(test_token().token_id, U128(1000));
+        assert_eq!(contract.get_unclaimed_dividends(carol(), test_token().token_id).0, 100);
+
+        // новый держатель не получает дивиденды, внесенные до покупки
+        testing_env!(get_context(carol()));
+        contract.transfer(vasy(), test_token().token_id, U128(500));
+        assert_eq!(contract.get_unclaimed_dividends(vasy(), test_token().token_id).0, 0);
+
+        testing_env!(get_context(bob()));
+        contract.deposit_dividends(test_token().token_id, U128(1000));
+        assert_eq!(contract.get_unclaimed_dividends(vasy(), test_token().token_id).0, 50);
+
+        testing_env!(get_context(carol()));
+        let balance = contract.get_balance(carol(), standart_token().token_id).0;
+        assert_eq!(contract.claim_dividends(test_token().token_id).0, 150);
+        assert_eq!(contract.get_balance(carol(), standart_token().token_id).0, balance + 150);
+        assert_eq!(contract.get_unclaimed_dividends(carol(), test_token().token_id).0, 0);
+        catch_unwind_silent(move || {
+            contract.claim_dividends(test_token().token_id);
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn deposit_dividends_by_non_issuer_failed() {
+        let mut contract = init_contract_with_tokens();
+        testing_env!(get_context(carol()));
+        catch_unwind_silent(move || {
+            contract.deposit_dividends(test_token().token_id, U128(100));
+        }).unwrap_err();
+    }
+
+    fn vesting_terms(revocable: bool) -> VestingTerms {
+        VestingTerms {
+            cliff_duration: NANOSEC_IN_DAY,
+            duration: NANOSEC_IN_DAY * 4,
+            revocable,
         }
-        order
     }
 
-    fn _only_owner_predecessor(&mut self) {
-        assert_eq!(
-            env::predecessor_account_id(),
-            env::current_account_id(),
-            "Only contract owner can sign transactions for this method."
-        );
+    #[test]
+    fn vesting() {
+        let mut contract = init_contract_with_tokens();
+        contract.create_vesting(vasy(), test_token().token_id, U128(400), vesting_terms(false), None);
+        assert_eq!(contract.get_vesting(vasy(), test_token().token_id).unwrap().locked.0, 400);
+
+        let mut context = get_context(vasy());
+        context.block_timestamp = NANOSEC_IN_DAY - 1;
+        testing_env!(context.clone());
+        assert_eq!(contract.get_vesting(vasy(), test_token().token_id).unwrap().locked.0, 400);
+
+        context.block_timestamp = NANOSEC_IN_DAY * 2;
+        testing_env!(context);
+        assert_eq!(contract.get_vesting(vasy(), test_token().token_id).unwrap().locked.0, 200);
+        contract.transfer(ivan(), test_token().token_id, U128(200));
+        assert_eq!(contract.get_balance(vasy(), test_token().token_id).0, 200);
+        catch_unwind_silent(move || {
+            contract.transfer(ivan(), test_token().token_id, U128(1));
+        }).unwrap_err();
     }
-}
 
-#[cfg(not(target_arch = "wasm32"))]
-#[cfg(test)]
-mod tests {
-    use near_sdk::{AccountId, env, Gas, MockedBlockchain};
-    use near_sdk::{testing_env, VMContext};
-    use near_sdk::json_types::U128;
-    use num_traits::ToPrimitive;
+    #[test]
+    fn vesting_of_large_supply() {
+        let schedule = VestingSchedule { total: u128::MAX, start: 0, terms: vesting_terms(false) };
+        assert_eq!(schedule.locked(NANOSEC_IN_DAY * 2), u128::MAX - u128::MAX / 2);
+        assert_eq!(schedule.locked(NANOSEC_IN_DAY * 4 - 1), u128::MAX / (NANOSEC_IN_DAY * 4) as u128 + 1);
+    }
 
-    use crate::{APPROVED, Contract, NANOSEC_IN_DAY, PERCENT_STAKING_PER_YEAR, Token, UserRequest, YOKTO_NEAR};
-    use crate::request::RequestStatus::REJECTED;
+    #[test]
+    fn vested_tokens_excluded_from_orders() {
+        let mut contract = init_contract_with_tokens();
+        contract.create_vesting(vasy(), test_token().token_id, U128(400), vesting_terms(false), None);
+        testing_env!(get_extend_context(vasy(), vasy()));
+        catch_unwind_silent(move || {
+            contract.new_limit_order(test_token().token_id, 5.0, 100, "Ask".to_string());
+        }).unwrap_err();
+    }
 
-    fn standart_token() -> Token {
-        Token {
+    #[test]
+    fn revoke_vesting() {
+        let mut contract = init_contract_with_tokens();
+        contract.create_vesting(vasy(), test_token().token_id, U128(400), vesting_terms(true), None);
+        let mut context = get_context(bob());
+        context.block_timestamp = NANOSEC_IN_DAY * 3;
+        testing_env!(context);
+        assert_eq!(contract.revoke_vesting(vasy(), test_token().token_id).0, 100);
+        assert_eq!(contract.get_balance(vasy(), test_token().token_id).0, 300);
+        assert_eq!(contract.get_balance(bob(), test_token().token_id).0, 7700);
+        assert_eq!(contract.get_vesting(vasy(), test_token().token_id), None);
+
+        contract.create_vesting(vasy(), test_token().token_id, U128(400), vesting_terms(false), None);
+        catch_unwind_silent(move || {
+            contract.revoke_vesting(vasy(), test_token().token_id);
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn launchpad_vesting() {
+        let mut contract = get_contract_with_request_and_staking();
+        let kind = RequestKind::SetLaunchpadVesting { terms: Some(vesting_terms(false)) };
+        let vesting_request = propose_and_vote(&mut contract, kind, 0);
+        contract.finalize_request(vesting_request);
+        assert_eq!(contract.get_launchpad_vesting(), Some(vesting_terms(false)));
+        testing_env!(get_extend_context(vasy(), vasy()));
+        contract.vote(0, VoteOption::Yes);
+        let mut context = get_extend_context(carol(), carol());
+        context.block_timestamp = 2 * NANOSEC_IN_DAY;
+        testing_env!(context);
+        contract.finalize_request(0);
+        contract.start_launchpad(0, 2 * NANOSEC_IN_DAY + 100);
+        contract.finalize_my_launchpad(get_test_request().token_id);
+
+        let vesting = contract.get_vesting(carol(), get_test_request().token_id).unwrap();
+        assert_eq!(vesting.total.0, get_test_request().supply);
+        assert_eq!(vesting.locked.0, get_test_request().supply);
+        catch_unwind_silent(move || {
+            contract.transfer(ivan(), get_test_request().token_id, U128(1));
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn grant_and_revoke_role() {
+        let mut contract = init_contract_with_tokens();
+        assert!(contract.has_role(Role::Admin, bob()));
+        assert!(!contract.has_role(Role::ListingManager, carol()));
+        contract.grant_role(Role::ListingManager, carol());
+        assert_eq!(contract.get_role_members(Role::ListingManager), vec![carol()]);
+
+        testing_env!(get_context(carol()));
+        contract.add_token(Token {
+            token_id: "CAROL".to_string(),
+            owner_id: carol(),
+            total_supply: 100,
+            max_supply: None,
+            meta: TokenMetadata::new("CAROL", "CAROL", 2),
+            origin: TokenOrigin::Native,
+        });
+
+        testing_env!(get_context(bob()));
+        contract.revoke_role(Role::ListingManager, carol());
+        assert!(!contract.has_role(Role::ListingManager, carol()));
+        assert_eq!(get_events("role_revoked").len(), 1);
+    }
+
+    #[test]
+    fn grant_role_by_non_admin_failed() {
+        let mut contract = init_contract_with_tokens();
+        testing_env!(get_context(carol()));
+        catch_unwind_silent(move || {
+            contract.grant_role(Role::Pauser, carol());
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn add_token_without_role_failed() {
+        let mut contract = init_contract_with_tokens();
+        testing_env!(get_context(carol()));
+        catch_unwind_silent(move || {
+            contract.add_token(Token {
+                token_id: "CAROL".to_string(),
+                owner_id: carol(),
+                total_supply: 100,
+                max_supply: None,
+                meta: TokenMetadata::new("CAROL", "CAROL", 2),
+                origin: TokenOrigin::Native,
+            });
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn two_step_ownership_transfer() {
+        let mut contract = init_contract_with_tokens();
+        contract.transfer_ownership(carol());
+        assert_eq!(contract.get_owner(), bob());
+        assert_eq!(contract.get_pending_owner(), Some(carol()));
+
+        testing_env!(get_context(carol()));
+        contract.accept_ownership();
+        assert_eq!(contract.get_owner(), carol());
+        assert_eq!(contract.get_pending_owner(), None);
+        assert!(contract.has_role(Role::Admin, carol()));
+        assert!(!contract.has_role(Role::Admin, bob()));
+
+        testing_env!(get_context(ivan()));
+        catch_unwind_silent(move || {
+            contract.accept_ownership();
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn paused_trading() {
+        let mut contract = init_contract_with_tokens_and_limit_bids();
+        testing_env!(get_extend_context(bob(), bob()));
+        contract.set_paused(Subsystem::Trading, true);
+        assert!(contract.get_paused().trading);
+
+        // отмена и переводы работают во время паузы торгов
+        let balance = contract.get_balance(bob(), standart_token().token_id).0;
+        contract.cancel_limit_order(test_token().token_id, 8, "Bid".to_string());
+        assert_eq!(contract.get_balance(bob(), standart_token().token_id).0, balance + 100);
+        contract.transfer(carol(), standart_token().token_id, U128(10));
+        catch_unwind_silent(move || {
+            contract.new_limit_order(test_token().token_id, 5.0, 10, "Ask".to_string());
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn paused_transfers() {
+        let mut contract = init_contract_with_tokens();
+        contract.grant_role(Role::Pauser, ivan());
+        testing_env!(get_context(ivan()));
+        contract.set_paused(Subsystem::Transfers, true);
+        testing_env!(get_context(bob()));
+        catch_unwind_silent(move || {
+            contract.transfer(carol(), standart_token().token_id, U128(10));
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn paused_burn() {
+        let mut contract = init_contract_with_tokens();
+        contract.set_paused(Subsystem::Transfers, true);
+        catch_unwind_silent(move || {
+            contract.burn(test_token().token_id, U128(10));
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn set_paused_without_role_failed() {
+        let mut contract = init_contract_with_tokens();
+        testing_env!(get_context(carol()));
+        catch_unwind_silent(move || {
+            contract.set_paused(Subsystem::Staking, true);
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn cancel_foreign_order_failed() {
+        let mut contract = init_contract_with_tokens_and_limit_bids();
+        testing_env!(get_extend_context(carol(), carol()));
+        catch_unwind_silent(move || {
+            contract.cancel_limit_order(test_token().token_id, 8, "Bid".to_string());
+        }).unwrap_err();
+    }
+
+    /// Пишет состояние, которое оставляет `Contract::new(ivan)` исходной версии, плюс листинг,
+    /// заявки carol и ivan на голосовании с голосами, стейк carol и разрешение ivan для alice
+    fn write_v1_state() {
+        let mut tokens: UnorderedMap<TokenId, TokenV1> = UnorderedMap::new(b"t".to_vec());
+        tokens.insert(&"XDHO".to_string(), &TokenV1 {
             token_id: "XDHO".to_string(),
-            owner_id: carol().to_string(),
+            owner_id: ivan(),
             supply: 100_000_000_000,
             meta: None,
+        });
+        let mut order_books: LookupMap<TokenId, Orderbook> = LookupMap::new(b"o".to_vec());
+        order_books.insert(&"XDHO".to_string(), &Orderbook::new("XDHO".to_string(), "XDHO".to_string()));
+        for (owner_id, balance) in [(ivan(), 100_000_000_000 - 1000), (carol(), 900)] {
+            let account_hash = env::sha256(owner_id.as_bytes());
+            let mut accounts: LookupMap<TokenId, TokenAccountV1> = LookupMap::new(account_hash.clone());
+            let mut allowances = LookupMap::new(account_hash);
+            if owner_id == ivan() {
+                allowances.insert(&env::sha256(alice().as_bytes()), &50u128);
+            }
+            accounts.insert(&"XDHO".to_string(), &TokenAccountV1 { balance, allowances });
+        }
+
+        let mut staking: UnorderedMap<Vec<u8>, StakeInfo> = UnorderedMap::new(b"s".to_vec());
+        staking.insert(&env::sha256(carol().as_bytes()), &StakeInfo { staked: 100, created_time: 0 });
+        let mut launchpad: UnorderedMap<TokenId, LaunchPadV1> = UnorderedMap::new(b"launch".to_vec());
+        launchpad.insert(&"OLD".to_string(), &LaunchPadV1 {
+            price: 10,
+            sell_supply: 5,
+            launched_time: 0,
+            token: TokenV1 {
+                token_id: "OLD".to_string(),
+                owner_id: vasy(),
+                supply: 1000,
+                meta: Some(TokenMetadataV1 {
+                    title: Some("Old token".to_string()),
+                    description: Some("Listed before NEP-148".to_string()),
+                    icon: Some("https://example.com/old.png".to_string()),
+                }),
+            },
+        });
+
+        let mut ballot_handler = BallotHandler::new();
+        let mut requests: UnorderedMap<RequestId, RequestV1> = UnorderedMap::new(b"req".to_vec());
+        requests.insert(&0, &RequestV1 {
+            id: 0,
+            token_id: get_test_request().token_id,
+            title: "title".to_string(),
+            description: "description".to_string(),
+            price: get_test_request().price,
+            supply: get_test_request().supply,
+            status: RequestStatus::PENDING,
+            created_time: 0,
+            owner_id: carol(),
+            hash: "hash".to_string(),
+        });
+        requests.insert(&1, &RequestV1 {
+            id: 1,
+            token_id: "NEW".to_string(),
+            title: "title".to_string(),
+            description: "description".to_string(),
+            price: 1,
+            supply: 1,
+            status: RequestStatus::PENDING,
+            created_time: 0,
+            owner_id: ivan(),
+            hash: "hash".to_string(),
+        });
+        ballot_handler.requests = UnorderedMap::try_from_slice(&requests.try_to_vec().unwrap()).unwrap();
+        // голоса обеих заявок в общем множестве `vot`, у заявок записана его длина на момент голосования
+        let mut votes: LookupMap<RequestId, UnorderedSet<VoteV1>> = LookupMap::new(b"v".to_vec());
+        let mut shared_votes = UnorderedSet::new(b"vot".to_vec());
+        for (request_id, voter_id) in [(0, carol()), (1, vasy())] {
+            shared_votes.insert(&VoteV1 { owner_id: voter_id, result: true });
+            votes.insert(&request_id, &shared_votes);
+        }
+
+        env::state_write(&ContractV1 {
+            wallets: LookupMap::new(b"w".to_vec()),
+            order_books,
+            tokens,
+            ballot_handler,
+            staking,
+            launchpad,
+            owner_id: ivan(),
+        });
+    }
+
+    #[test]
+    fn migrate_from_v1() {
+        testing_env!(get_context(alice()));
+        write_v1_state();
+        assert_eq!(read_state_version(), 1);
+
+        let mut contract = Contract::migrate();
+        assert_eq!(contract.get_state_version(), STATE_VERSION);
+        assert_eq!(contract.get_owner(), ivan());
+        assert!(contract.has_role(Role::Admin, ivan()));
+        assert!(!contract.get_paused().trading);
+        assert_eq!(contract.get_governance_config(), GovernanceConfig::default());
+        assert_eq!(contract.get_fee_rates(), FeeRates::default());
+
+        // счета версии 1 читаются, разрешения не переносятся, стейк carol переходит от ivan на счет контракта
+        assert_eq!(contract.get_balance(ivan(), "XDHO".to_string()).0, 100_000_000_000 - 1100);
+        assert_eq!(contract.get_balance(alice(), "XDHO".to_string()).0, 100);
+        assert_eq!(contract.get_staking(carol()).staked, 100);
+        assert_eq!(contract.get_balance(carol(), "XDHO".to_string()).0, 900);
+        assert_eq!(contract.get_allowance(ivan(), alice(), "XDHO".to_string()).0, 0);
+        assert_eq!(contract.ft_total_supply("XDHO".to_string()).0, 100_000_000_000);
+        assert_eq!(contract.ft_metadata("XDHO".to_string()), TokenMetadata::new("XDHO", "XDHO", 2));
+        // ivan как эмитент, carol как автор заявки и контракт со стейком carol
+        assert_eq!(contract.get_holder_count("XDHO".to_string()), 3);
+        assert_eq!(contract.get_holders("XDHO".to_string(), 1, 5).len(), 2);
+        assert_eq!(contract.ft_metadata(contract.get_near_token()).decimals, 24);
+        // при записи счет переходит в текущую раскладку
+        testing_env!(get_context(carol()));
+        contract.internal_transfer_from(carol(), ivan(), "XDHO".to_string(), U128(400));
+        assert_eq!(contract.get_balance(carol(), "XDHO".to_string()).0, 500);
+        assert_eq!(contract.get_balance(ivan(), "XDHO".to_string()).0, 100_000_000_000 - 700);
+        let carol_v1_key = [env::sha256(carol().as_bytes()), "XDHO".to_string().try_to_vec().unwrap()].concat();
+        assert!(!env::storage_has_key(&carol_v1_key));
+
+        let pad = contract.get_launchpad("OLD".to_string());
+        assert_eq!(pad.sell_supply, 5);
+        assert_eq!(pad.token.total_supply, 1000);
+        assert_eq!(pad.token.meta.name, "Old token");
+        assert_eq!(pad.token.meta.symbol, "OLD");
+        assert_eq!(pad.token.meta.description, Some("Listed before NEP-148".to_string()));
+        assert_eq!(pad.token.meta.icon, None);
+
+        match contract.get_request(0).kind {
+            RequestKind::Listing(listing) => {
+                assert_eq!(listing.token_id, get_test_request().token_id);
+                assert_eq!(listing.price, get_test_request().price);
+                assert_eq!(listing.max_supply, None);
+            }
+            kind => panic!("Unexpected request kind {:?}", kind),
         }
+        assert_eq!(contract.get_open_requests_count(carol()), 1);
+        // голоса версии 1 не отнести к заявкам, они удалены вместе с общим множеством
+        assert!(contract.get_all_votes(0).is_empty());
+        assert!(contract.get_all_votes(1).is_empty());
+        assert!(!contract.ballot_handler.is_vote(0, carol()));
+        assert!(!env::storage_has_key(&[b"vote".as_ref(), &0u64.to_le_bytes()].concat()));
+        assert!(!env::storage_has_key(&[b"vote".as_ref(), &1u64.to_le_bytes()].concat()));
     }
 
-    fn test_token() -> Token {
-        Token {
-            token_id: "TEST".to_string(),
-            owner_id: bob(),
-            supply: 10000,
-            meta: None,
+    #[test]
+    fn migrate_with_uncovered_stakes_failed() {
+        testing_env!(get_context(alice()));
+        write_v1_state();
+        let mut state: ContractV1 = env::state_read().unwrap();
+        state.staking.insert(&env::sha256(vasy().as_bytes()), &StakeInfo { staked: 100_000_000_000, created_time: 0 });
+        env::state_write(&state);
+
+        let err = catch_unwind_silent(|| {
+            Contract::migrate();
+        }).unwrap_err();
+        assert!(err.downcast_ref::<String>().unwrap().contains("Owner balance does not cover the staked tokens"));
+        assert_eq!(read_state_version(), 1);
+    }
+
+    #[test]
+    fn migrate_by_other_account_failed() {
+        init_contract_with_tokens();
+        testing_env!(get_context(carol()));
+        catch_unwind_silent(|| {
+            Contract::migrate();
+        }).unwrap_err();
+    }
+
+    fn get_contract_with_approved_upgrade(code: &[u8]) -> Contract {
+        let mut contract = get_contract_with_request_and_staking();
+        testing_env!(get_extend_context(carol(), carol()));
+        let request_id = contract.add_proposal(
+            "Новая версия".to_string(),
+            "Исправления".to_string(),
+            RequestKind::Upgrade { code_hash: Base64VecU8(env::sha256(code)) },
+        );
+        assert_eq!(request_id, 1);
+        testing_env!(get_extend_context(vasy(), vasy()));
+        contract.vote(1, VoteOption::Yes);
+        testing_env!(get_extend_context(ivan(), ivan()));
+        contract.vote(1, VoteOption::Yes);
+
+        let mut context = get_extend_context(carol(), carol());
+        context.block_timestamp = NANOSEC_IN_DAY;
+        testing_env!(context);
+        contract.finalize_request(1);
+        contract
+    }
+
+    fn test_governance_config() -> GovernanceConfig {
+        GovernanceConfig {
+            voting_period: 2 * NANOSEC_IN_DAY,
+            quorum_percent: 50,
+            approval_threshold: 60,
+            proposal_deposit: U128(0),
+            min_stake: U128(200),
+            secret_ballot: vec![],
+            reveal_period: NANOSEC_IN_DAY,
         }
     }
 
-    fn alice() -> AccountId {
-        "alice.near".to_string()
-    }
+    #[test]
+    fn test_change_governance_config() {
+        let mut contract = get_contract_with_request_and_staking();
+        assert_eq!(contract.get_governance_config(), GovernanceConfig::default());
+        testing_env!(get_extend_context(carol(), carol()));
+        let request_id = contract.add_proposal(
+            "Строже".to_string(),
+            "Кворум в половину стейка".to_string(),
+            RequestKind::ChangeGovernanceConfig { config: test_governance_config() },
+        );
+        testing_env!(get_extend_context(vasy(), vasy()));
+        contract.vote(request_id, VoteOption::Yes);
+        contract.vote(0, VoteOption::Yes);
+        testing_env!(get_extend_context(ivan(), ivan()));
+        contract.vote(request_id, VoteOption::Yes);
+
+        set_time(carol(), NANOSEC_IN_DAY);
+        contract.finalize_request(request_id);
+        assert_eq!(contract.get_request(request_id).status, APPROVED);
+        assert_eq!(contract.get_governance_config(), test_governance_config());
+        assert_eq!(get_events("governance_config_change")[0]["config"]["quorum_percent"], 50);
+
+        // новые параметры действуют только для заявок, поданных после смены
+        let request_id = contract.add_proposal("".to_string(), "".to_string(), fees_proposal());
+        assert_eq!(contract.get_request(request_id).terms, VotingTerms {
+            voting_end: NANOSEC_IN_DAY * 3,
+            quorum_percent: 50,
+            approval_threshold: 60,
+        });
+        // первая заявка закончилась через сутки, и 100 из 300 хватает для ее прежнего кворума
+        assert_eq!(contract.get_request(0).terms.voting_end, NANOSEC_IN_DAY);
+        set_time(carol(), NANOSEC_IN_DAY + 1);
+        contract.finalize_request(0);
+        assert_eq!(contract.get_request(0).status, APPROVED);
+    }
+
+    #[test]
+    fn test_propose_invalid_governance_config_failed() {
+        let mut contract = get_contract_with_request_and_staking();
+        testing_env!(get_extend_context(carol(), carol()));
+        let mut config = test_governance_config();
+        config.approval_threshold = 100;
+        catch_unwind_silent(move || {
+            contract.add_proposal("Ошибка".to_string(), "".to_string(), RequestKind::ChangeGovernanceConfig { config });
+        }).unwrap_err();
+    }
+
+    #[test]
+    fn test_upgrade() {
+        let code = b"new code".to_vec();
+        let mut contract = get_contract_with_approved_upgrade(&code);
+        assert_eq!(contract.get_request(1).status, APPROVED);
 
-    fn bob() -> AccountId {
-        "bob.near".to_string()
+        let mut context = get_extend_context(bob(), bob());
+        context.block_timestamp = NANOSEC_IN_DAY * 3;
+        testing_env!(context);
+        contract.upgrade(code.clone());
+        assert_eq!(contract.get_request(1).status, APPROVED);
+
+        testing_env!(
+            get_context(alice()),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Successful(vec![])]
+        );
+        contract.resolve_upgrade(1);
+        assert_eq!(contract.get_request(1).status, RequestStatus::LAUNCHED);
+
+        catch_unwind_silent(move || {
+            contract.upgrade(code);
+        }).unwrap_err();
     }
 
-    fn ivan() -> AccountId {
-        "ivan.near".to_string()
+    #[test]
+    fn test_failed_upgrade_can_be_retried() {
+        let code = b"new code".to_vec();
+        let mut contract = get_contract_with_approved_upgrade(&code);
+        set_time(bob(), NANOSEC_IN_DAY * 3);
+        contract.upgrade(code.clone());
+
+        // развертывание или migrate не прошли
+        testing_env!(
+            get_context(alice()),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        contract.resolve_upgrade(1);
+        assert_eq!(contract.get_request(1).status, APPROVED);
+
+        set_time(bob(), NANOSEC_IN_DAY * 3);
+        contract.upgrade(code);
     }
 
-    fn vasy() -> AccountId {
-        "vasy.near".to_string()
+    #[test]
+    fn test_upgrade_before_timelock_failed() {
+        let mut contract = get_contract_with_approved_upgrade(b"new code");
+        let mut context = get_extend_context(bob(), bob());
+        context.block_timestamp = NANOSEC_IN_DAY * 3 - 1;
+        testing_env!(context);
+        catch_unwind_silent(move || {
+            contract.upgrade(b"new code".to_vec());
+        }).unwrap_err();
     }
 
-    fn carol() -> AccountId {
-        "carol.near".to_string()
+    #[test]
+    fn test_upgrade_before_timelock_from_approval_failed() {
+        let code = b"new code".to_vec();
+        let mut contract = get_contract_with_request_and_staking();
+        let request_id = propose_and_vote(&mut contract, RequestKind::Upgrade { code_hash: Base64VecU8(env::sha256(&code)) }, 0);
+        // заявку завершили на сутки позже окончания голосования
+        set_time(carol(), NANOSEC_IN_DAY * 2);
+        contract.finalize_request(request_id);
+        assert_eq!(contract.get_request(request_id).approved_time, Some(NANOSEC_IN_DAY * 2));
+
+        set_time(bob(), NANOSEC_IN_DAY * 4 - 1);
+        catch_unwind_silent(move || {
+            contract.upgrade(code);
+        }).unwrap_err();
     }
 
-    fn catch_unwind_silent<F: FnOnce() -> R + std::panic::UnwindSafe, R>(
-        f: F,
-    ) -> std::thread::Result<R> {
-        let prev_hook = std::panic::take_hook();
-        std::panic::set_hook(Box::new(|_| {}));
-        let result = std::panic::catch_unwind(f);
-        std::panic::set_hook(prev_hook);
-        result
+    #[test]
+    fn test_upgrade_with_other_code_failed() {
+        let mut contract = get_contract_with_approved_upgrade(b"new code");
+        let mut context = get_extend_context(bob(), bob());
+        context.block_timestamp = NANOSEC_IN_DAY * 3;
+        testing_env!(context);
+        catch_unwind_silent(move || {
+            contract.upgrade(b"other code".to_vec());
+        }).unwrap_err();
     }
 
-    fn get_context(predecessor_account_id: AccountId) -> VMContext {
-        get_extend_context(predecessor_account_id, bob())
+    #[test]
+    fn test_start_launchpad_with_upgrade_request_failed() {
+        let mut contract = get_contract_with_approved_upgrade(b"new code");
+        catch_unwind_silent(move || {
+            contract.start_launchpad(1, NANOSEC_IN_DAY);
+        }).unwrap_err();
     }
 
-    fn get_extend_context(predecessor_account_id: AccountId, signer: AccountId) -> VMContext {
-        VMContext {
-            current_account_id: alice(),
-            signer_account_id: signer,
-            signer_account_pk: vec![0, 1, 2],
-            predecessor_account_id,
-            input: vec![],
-            block_index: 0,
-            block_timestamp: 0,
-            account_balance: 100,
-            account_locked_balance: 0,
-            storage_usage: 10u64.pow(6),
-            attached_deposit: 0,
-            prepaid_gas: Gas::from(10u64.pow(18)),
-            random_seed: vec![0, 1, 2],
-            is_view: false,
-            output_data_receivers: vec![],
-            epoch_height: 0,
+    fn stake_voters(contract: &mut Contract) {
+        for voter in [carol(), ivan(), vasy()] {
+            testing_env!(get_extend_context(voter.clone(), voter));
+            contract.stake(100);
         }
     }
 
-    fn init_contract_with_tokens() -> Contract {
-        let context = get_context(bob());
+    /// carol подает заявку в момент `start`, vasy и ivan голосуют за
+    fn propose_and_vote(contract: &mut Contract, kind: RequestKind, start: u64) -> RequestId {
+        let mut context = get_extend_context(carol(), carol());
+        context.block_timestamp = start;
         testing_env!(context);
+        let request_id = contract.add_proposal("Заявка".to_string(), "Описание".to_string(), kind);
+        for voter in [vasy(), ivan()] {
+            let mut context = get_extend_context(voter.clone(), voter);
+            context.block_timestamp = start;
+            testing_env!(context);
+            contract.vote(request_id, VoteOption::Yes);
+        }
+        let mut context = get_extend_context(carol(), carol());
+        context.block_timestamp = start + NANOSEC_IN_DAY;
+        testing_env!(context);
+        request_id
+    }
 
-        let mut contract = Contract::new(bob());
-        assert_eq!(
-            contract.get_balance(bob(), standart_token().token_id).0,
-            standart_token().supply
-        );
-
-        contract.add_token(test_token());
-        let balance = contract
-            .get_balance(bob(), test_token().token_id)
-            .0;
-        assert_eq!(balance, 10000u128);
-
-        contract.transfer(carol(), standart_token().token_id, U128::from(1000u128));
-        contract.transfer(ivan(), standart_token().token_id, U128::from(1000u128));
-        contract.transfer(vasy(), standart_token().token_id, U128::from(1000u128));
-        contract.transfer(carol(), test_token().token_id, U128::from(1000u128));
-        contract.transfer(ivan(), test_token().token_id, U128::from(1000u128));
+    fn pass_proposal(contract: &mut Contract, kind: RequestKind, start: u64) -> RequestId {
+        let request_id = propose_and_vote(contract, kind, start);
+        contract.finalize_request(request_id);
+        assert_eq!(contract.get_request(request_id).status, APPROVED);
+        request_id
+    }
 
-        contract
+    #[test]
+    fn test_add_listing_proposal_failed() {
+        let mut contract = get_contract_with_request();
+        let request = contract.get_request(0);
+        catch_unwind_silent(move || {
+            contract.add_proposal("Листинг".to_string(), "".to_string(), request.kind);
+        }).unwrap_err();
     }
 
-    fn get_test_request() -> UserRequest{
-        UserRequest {
-            token_id: "SOSI".to_string(),
-            title: "Тестовое название".to_string(),
-            description: "ПРИВЕТИКИ".to_string(),
-            price: 23,
-            supply: 100000,
-            hash: "сосисочка".to_string(),
-        }
+    #[test]
+    fn test_change_fees_proposal() {
+        let mut contract = get_contract_with_request_and_staking();
+        let fees = FeeRates { trading_fee_bps: 30, launchpad_fee_bps: 200 };
+        pass_proposal(&mut contract, RequestKind::ChangeFees { fees: fees.clone() }, 0);
+        assert_eq!(contract.get_fee_rates(), fees);
+        assert_eq!(get_events("fee_rates_change")[0]["fees"]["trading_fee_bps"], 30);
     }
 
-    fn get_contract_with_request() -> Contract {
+    #[test]
+    fn test_set_fee_rates_without_role_failed() {
         let mut contract = init_contract_with_tokens();
         testing_env!(get_extend_context(carol(), carol()));
-        contract.add_new_request(get_test_request());
-        contract
+        catch_unwind_silent(move || {
+            contract.set_fee_rates(FeeRates { trading_fee_bps: 30, launchpad_fee_bps: 0 });
+        }).unwrap_err();
     }
 
-    fn get_contract_with_request_and_staking() -> Contract {
-        let mut contract = get_contract_with_request();
-        testing_env!(get_extend_context(carol(), carol()));
-        contract.stake(100);
+    #[test]
+    fn test_trading_fee_and_treasury_spend() {
+        let mut contract = init_contract_with_tokens_and_limit_bids();
+        testing_env!(get_extend_context(bob(), bob()));
+        contract.set_fee_rates(FeeRates { trading_fee_bps: 1000, launchpad_fee_bps: 0 });
+
+        // ivan продает 50 TEST за 120 XDHO, 10% уходит в казну
         testing_env!(get_extend_context(ivan(), ivan()));
-        contract.stake(100);
-        testing_env!(get_extend_context(vasy(), vasy()));
-        contract.stake(100);
+        contract.new_market_order(test_token().token_id, 50, "Ask".to_string());
+        assert_eq!(contract.get_balance(ivan(), standart_token().token_id).0, 1108);
+        assert_eq!(contract.get_treasury_balance(standart_token().token_id).0, 12);
+        assert_eq!(get_events("treasury_deposit")[0]["amount"], "12");
+
+        stake_voters(&mut contract);
+        pass_proposal(&mut contract, RequestKind::TreasurySpend {
+            receiver_id: vasy(),
+            token_id: standart_token().token_id,
+            amount: U128(10),
+        }, 0);
+        assert_eq!(contract.get_balance(vasy(), standart_token().token_id).0, 910);
+        assert_eq!(contract.get_treasury(), vec![(standart_token().token_id, U128(2))]);
+    }
 
-        contract
+    #[test]
+    fn test_treasury_spend_without_funds_execution_failed() {
+        let mut contract = get_contract_with_request_and_staking();
+        let carol_balance = contract.get_balance(carol(), standart_token().token_id).0;
+        let request_id = propose_and_vote(&mut contract, RequestKind::TreasurySpend {
+            receiver_id: vasy(),
+            token_id: standart_token().token_id,
+            amount: U128(10),
+        }, 0);
+        contract.finalize_request(request_id);
+        assert_eq!(contract.get_request(request_id).status, RequestStatus::EXECUTION_FAILED);
+        assert_eq!(get_events("request_status")[0]["status"], "EXECUTION_FAILED");
+        // залог вернулся автору, казна не тронута
+        assert_eq!(contract.get_balance(carol(), standart_token().token_id).0, carol_balance);
+        assert_eq!(contract.get_proposal_deposit(request_id).0, 0);
+        assert_eq!(contract.get_open_requests_count(carol()), 1);
+        assert_eq!(contract.get_treasury_balance(standart_token().token_id).0, 0);
     }
 
-    fn get_contract_with_approve_request() -> Contract{
+    #[test]
+    fn test_delist_already_delisted_execution_failed() {
         let mut contract = get_contract_with_request_and_staking();
-        testing_env!(get_extend_context(vasy(), vasy()));
-        contract.vote(0, true);
-        testing_env!(get_extend_context(ivan(), ivan()));
-        contract.vote(0, true);
+        let first = propose_and_vote(&mut contract, RequestKind::Delist { token_id: test_token().token_id }, 0);
+        let second = propose_and_vote(&mut contract, RequestKind::Delist { token_id: test_token().token_id }, 0);
+        contract.finalize_request(first);
+        contract.finalize_request(second);
+        assert_eq!(contract.get_request(first).status, APPROVED);
+        assert_eq!(contract.get_request(second).status, RequestStatus::EXECUTION_FAILED);
+        // повторную заявку уже не подать
         testing_env!(get_extend_context(carol(), carol()));
-        contract.vote(0, false);
+        catch_unwind_silent(move || {
+            contract.add_proposal("".to_string(), "".to_string(), RequestKind::Delist { token_id: test_token().token_id });
+        }).unwrap_err();
+    }
 
+    #[test]
+    fn test_launchpad_fee() {
+        let mut contract = get_contract_with_approve_request();
+        testing_env!(get_extend_context(bob(), bob()));
+        contract.set_fee_rates(FeeRates { trading_fee_bps: 0, launchpad_fee_bps: 1000 });
         let mut context = get_extend_context(carol(), carol());
         context.block_timestamp = NANOSEC_IN_DAY;
         testing_env!(context);
+        contract.start_launchpad(0, NANOSEC_IN_DAY);
+        let carol_balance = contract.get_balance(carol(), standart_token().token_id).0;
 
-        contract.finalize_request(0);
-        contract
+        let mut context = get_extend_context(bob(), bob());
+        context.block_timestamp = NANOSEC_IN_DAY;
+        testing_env!(context);
+        contract.buy_tokens_on_launchpad(get_test_request().token_id, 50);
+        assert_eq!(contract.get_balance(carol(), standart_token().token_id).0, carol_balance + 1035);
+        assert_eq!(contract.get_treasury_balance(standart_token().token_id).0, 115);
     }
 
     #[test]
-    fn test_new() {
-        let context = get_context(carol());
-        testing_env!(context);
+    fn test_halt_and_resume_market() {
+        let mut contract = get_contract_with_request_and_staking();
+        pass_proposal(&mut contract, RequestKind::HaltMarket { token_id: test_token().token_id, halted: true }, 0);
+        assert_eq!(contract.get_market_status(test_token().token_id), Some(MarketStatus::Halted));
+        assert_eq!(get_events("market_status_change")[0]["status"], "Halted");
 
-        let contract = Contract::new(bob());
-        assert_eq!(contract.get_balance(bob(), standart_token().token_id).0, standart_token().supply);
+        pass_proposal(&mut contract, RequestKind::HaltMarket { token_id: test_token().token_id, halted: false }, NANOSEC_IN_DAY);
+        assert_eq!(contract.get_market_status(test_token().token_id), None);
+        contract.new_limit_order(test_token().token_id, 5.0, 10, "Ask".to_string());
     }
 
     #[test]
-    fn test_transfer() {
-        let context = get_context(carol());
-        testing_env!(context);
-        let total_supply = standart_token().supply;
-        let mut contract = Contract::new(carol());
-        let transfer_amount = total_supply / 3;
-        contract.transfer(bob(), standart_token().token_id, transfer_amount.into());
-        assert_eq!(
-            contract.get_balance(carol(), standart_token().token_id).0,
-            (total_supply - transfer_amount)
-        );
-        assert_eq!(contract.get_balance(bob(), standart_token().token_id).0, transfer_amount);
+    fn test_halted_market_rejects_orders() {
+        let mut contract = get_contract_with_request_and_staking();
+        pass_proposal(&mut contract, RequestKind::HaltMarket { token_id: test_token().token_id, halted: true }, 0);
+        catch_unwind_silent(move || {
+            contract.new_limit_order(test_token().token_id, 5.0, 10, "Ask".to_string());
+        }).unwrap_err();
     }
 
     #[test]
-    fn test_failed_unstaking() {
-        let mut contract = init_contract_with_tokens_and_limit_bids();
-        let context = get_extend_context(carol(), carol());
-        testing_env!(context);
-
+    fn test_delist_proposal() {
+        let mut contract = get_contract_with_request_and_staking();
+        pass_proposal(&mut contract, RequestKind::Delist { token_id: test_token().token_id }, 0);
+        assert_eq!(contract.get_market_status(test_token().token_id), Some(MarketStatus::Delisted));
+        assert!(contract.get_tokens().iter().all(|token| token.token_id != test_token().token_id));
+        // баланс остается, переводить можно
+        contract.transfer(ivan(), test_token().token_id, U128(10));
         catch_unwind_silent(move || {
-            contract.unstake();
+            contract.new_market_order(test_token().token_id, 10, "Ask".to_string());
         }).unwrap_err();
     }
 
     #[test]
-    fn test_failed_staking() {
-        let mut contract = init_contract_with_tokens_and_limit_bids();
-        let context = get_extend_context(carol(), carol());
-        testing_env!(context);
-
-        contract.stake(123);
+    fn test_delist_standard_token_failed() {
+        let mut contract = get_contract_with_request_and_staking();
+        testing_env!(get_extend_context(carol(), carol()));
         catch_unwind_silent(move || {
-            contract.stake(123);
+            contract.add_proposal("".to_string(), "".to_string(), RequestKind::Delist { token_id: standart_token().token_id });
         }).unwrap_err();
     }
 
-    #[test]
-    fn test_staking() {
-        let mut contract = init_contract_with_tokens_and_limit_bids();
-        let context = get_extend_context(carol(), carol());
-        testing_env!(context);
-        let amount = 100;
-        let old_balance = contract.get_balance(carol(), standart_token().token_id);
-        contract.stake(amount);
-        assert_eq!(contract.get_balance(carol(), standart_token().token_id).0, old_balance.0 - amount);
-        assert_eq!(contract.get_staking(carol()).staked, amount);
+    /// Заявка 1 на смену комиссий голосуется тайно
+    fn get_contract_with_secret_ballot() -> Contract {
+        let mut contract = get_contract_with_request_and_staking();
+        contract.governance.secret_ballot = vec![ProposalType::ChangeFees];
+        testing_env!(get_extend_context(carol(), carol()));
+        contract.add_proposal("".to_string(), "".to_string(), fees_proposal());
+        contract
+    }
 
-        let mut context = get_extend_context(carol(), carol());
-        context.block_timestamp = NANOSEC_IN_DAY * 365;
-        testing_env!(context);
-        contract.unstake();
-        assert_eq!(
-            contract.get_balance(carol(), standart_token().token_id).0,
-            old_balance.0 + (amount.to_f64().unwrap() * PERCENT_STAKING_PER_YEAR).to_u128().unwrap()
-        );
-        assert_eq!(contract.get_staking(carol()).staked, 0);
+    fn commit_vote(contract: &mut Contract, voter: AccountId, vote: VoteOption) {
+        testing_env!(get_extend_context(voter.clone(), voter.clone()));
+        contract.commit_vote(1, Base64VecU8(vote_hash(1, &voter, vote, &voter)));
     }
 
-    #[test]
-    fn test_voting_without_staking() {
-        let mut contract = get_contract_with_request();
-        let context = get_extend_context(carol(), carol());
+    /// Меняет условия голосования, зафиксированные в уже поданной заявке
+    fn update_terms<F: FnOnce(&mut VotingTerms)>(contract: &mut Contract, request_id: RequestId, update: F) {
+        let mut request = contract.get_request(request_id);
+        update(&mut request.terms);
+        contract.ballot_handler.requests.insert(&request_id, &request);
+    }
+
+    fn set_time(account: AccountId, block_timestamp: u64) {
+        let mut context = get_extend_context(account.clone(), account);
+        context.block_timestamp = block_timestamp;
         testing_env!(context);
-        catch_unwind_silent(move || {
-            contract.vote(0, true);
-        }).unwrap_err();
     }
 
     #[test]
-    fn test_voting_after_staking() {
-        let mut contract = get_contract_with_request();
-        let context = get_extend_context(carol(), carol());
-        testing_env!(context);
-        let amount = 100;
-        contract.stake(amount);
-        contract.vote(0, true);
-        assert!(contract.is_voting(carol(), 0));
+    fn test_secret_ballot() {
+        let mut contract = get_contract_with_secret_ballot();
+        assert!(contract.is_secret_ballot(1));
+        assert!(!contract.is_secret_ballot(0));
+
+        commit_vote(&mut contract, vasy(), VoteOption::Yes);
+        assert_eq!(get_events("vote_commit")[0]["voter_id"], vasy());
+        assert!(get_events("vote_commit")[0]["vote"].is_null());
+        commit_vote(&mut contract, ivan(), VoteOption::No);
+        commit_vote(&mut contract, carol(), VoteOption::No);
+        assert!(contract.is_vote_committed(ivan(), 1));
+        assert!(contract.get_all_votes(1).is_empty());
+
+        set_time(vasy(), NANOSEC_IN_DAY + 1);
+        contract.reveal_vote(1, VoteOption::Yes, vasy());
+        assert_eq!(get_events("vote")[0]["weight"], "100");
+        assert_eq!(contract.get_all_votes(1).len(), 1);
+        assert!(!contract.is_vote_committed(vasy(), 1));
+
+        // голоса ivan и carol не раскрыты и идут в кворум как воздержавшиеся
+        set_time(carol(), 2 * NANOSEC_IN_DAY);
+        contract.finalize_request(1);
+        assert_eq!(contract.get_request(1).status, APPROVED);
+        assert_eq!(contract.get_fee_rates().trading_fee_bps, 30);
+        assert!(!contract.is_secret_ballot(1));
     }
 
     #[test]
-    fn test_request_finalize_failed() {
-        let mut contract = get_contract_with_request_and_staking();
+    fn test_open_vote_on_secret_ballot_failed() {
+        let mut contract = get_contract_with_secret_ballot();
         testing_env!(get_extend_context(vasy(), vasy()));
-        contract.vote(0, true);
-        testing_env!(get_extend_context(ivan(), ivan()));
-        contract.vote(0, true);
-        testing_env!(get_extend_context(carol(), carol()));
-        contract.vote(0, false);
-
         catch_unwind_silent(move || {
-            contract.finalize_request(0);
+            contract.vote(1, VoteOption::Yes);
         }).unwrap_err();
     }
 
     #[test]
-    fn test_request_approve() {
-        let contract = get_contract_with_approve_request();
-        assert_eq!(contract.get_request(0).status, APPROVED);
+    fn test_reveal_other_vote_failed() {
+        let mut contract = get_contract_with_secret_ballot();
+        commit_vote(&mut contract, vasy(), VoteOption::Yes);
+        set_time(vasy(), NANOSEC_IN_DAY + 1);
+        catch_unwind_silent(move || {
+            contract.reveal_vote(1, VoteOption::No, vasy());
+        }).unwrap_err();
     }
 
     #[test]
-    fn test_start_launchpad() {
-        let mut contract = get_contract_with_approve_request();
-        contract.start_launchpad(0, NANOSEC_IN_DAY + 100);
-        let pads = contract.get_launchpad_tokens();
-        assert_eq!(pads[0].price, 23);
-        assert_eq!(pads[0].token.token_id, get_test_request().token_id);
-        let mut context = get_extend_context(bob(), bob());
-        context.block_timestamp = NANOSEC_IN_DAY + 100;
-        testing_env!(context);
-        contract.buy_tokens_on_launchpad(get_test_request().token_id, 50);
-        assert_eq!(contract.get_launchpad(get_test_request().token_id).sell_supply, get_test_request().supply - 50);
-        assert_eq!(contract.get_balance(bob(), get_test_request().token_id).0, 50);
+    fn test_reveal_copied_commit_failed() {
+        let mut contract = get_contract_with_secret_ballot();
+        commit_vote(&mut contract, vasy(), VoteOption::Yes);
+        // ivan повторяет хеш vasy, но в хеше записан голосующий
+        testing_env!(get_extend_context(ivan(), ivan()));
+        contract.commit_vote(1, Base64VecU8(vote_hash(1, &vasy(), VoteOption::Yes, &vasy())));
+        set_time(ivan(), NANOSEC_IN_DAY + 1);
+        catch_unwind_silent(move || {
+            contract.reveal_vote(1, VoteOption::Yes, vasy());
+        }).unwrap_err();
     }
 
     #[test]
-    fn test_finalize_launchpad() {
-        let mut contract = get_contract_with_approve_request();
-        contract.start_launchpad(0, NANOSEC_IN_DAY + 100);
-        let pads = contract.get_launchpad_tokens();
-        let mut context = get_extend_context(bob(), bob());
-        context.block_timestamp = NANOSEC_IN_DAY + 100;
-        testing_env!(context);
-        contract.buy_tokens_on_launchpad(get_test_request().token_id, 50);
-        let context = get_extend_context(carol(), carol());
-        testing_env!(context);
-        assert_eq!(contract.get_balance(carol(), get_test_request().token_id).0, 0);
-        contract.finalize_my_launchpad(get_test_request().token_id);
-        assert_eq!(contract.get_balance(carol(), get_test_request().token_id).0, get_test_request().supply - 50);
+    fn test_reveal_during_voting_failed() {
+        let mut contract = get_contract_with_secret_ballot();
+        commit_vote(&mut contract, vasy(), VoteOption::Yes);
+        catch_unwind_silent(move || {
+            contract.reveal_vote(1, VoteOption::Yes, vasy());
+        }).unwrap_err();
     }
 
     #[test]
-    fn test_not_started_launchpad() {
-        let mut contract = get_contract_with_approve_request();
-        contract.start_launchpad(0, NANOSEC_IN_DAY + 100);
-        let pads = contract.get_launchpad_tokens();
-        assert_eq!(pads[0].price, 23);
-        assert_eq!(pads[0].token.token_id, get_test_request().token_id);
-        let context = get_extend_context(bob(), bob());
-        testing_env!(context);
+    fn test_finalize_secret_ballot_before_reveal_end_failed() {
+        let mut contract = get_contract_with_secret_ballot();
+        commit_vote(&mut contract, vasy(), VoteOption::Yes);
+        set_time(carol(), NANOSEC_IN_DAY + 1);
         catch_unwind_silent(move || {
-            contract.buy_tokens_on_launchpad(get_test_request().token_id, 50);
+            contract.finalize_request(1);
         }).unwrap_err();
     }
 
+    fn fees_proposal() -> RequestKind {
+        RequestKind::ChangeFees { fees: FeeRates { trading_fee_bps: 30, launchpad_fee_bps: 0 } }
+    }
+
     #[test]
-    fn test_request_reject() {
+    fn test_proposal_deposit_refund_on_approve() {
         let mut contract = get_contract_with_request_and_staking();
-        testing_env!(get_extend_context(vasy(), vasy()));
-        contract.vote(0, true);
-        testing_env!(get_extend_context(ivan(), ivan()));
-        contract.vote(0, false);
-        testing_env!(get_extend_context(carol(), carol()));
-        contract.vote(0, false);
+        contract.governance.proposal_deposit = U128(50);
+        let request_id = propose_and_vote(&mut contract, fees_proposal(), 0);
+        assert_eq!(contract.get_balance(carol(), standart_token().token_id).0, 850);
+        assert_eq!(contract.get_proposal_deposit(request_id).0, 50);
+
+        contract.finalize_request(request_id);
+        assert_eq!(contract.get_request(request_id).status, APPROVED);
+        assert_eq!(contract.get_balance(carol(), standart_token().token_id).0, 900);
+        assert_eq!(contract.get_proposal_deposit(request_id).0, 0);
+        assert_eq!(contract.get_treasury_balance(standart_token().token_id).0, 0);
+    }
 
+    #[test]
+    fn test_proposal_deposit_refund_on_reject_with_quorum() {
+        let mut contract = get_contract_with_request_and_staking();
+        contract.governance.proposal_deposit = U128(50);
+        testing_env!(get_extend_context(carol(), carol()));
+        let request_id = contract.add_proposal("".to_string(), "".to_string(), fees_proposal());
+        for voter in [vasy(), ivan()] {
+            testing_env!(get_extend_context(voter.clone(), voter));
+            contract.vote(request_id, VoteOption::No);
+        }
         let mut context = get_extend_context(carol(), carol());
         context.block_timestamp = NANOSEC_IN_DAY;
         testing_env!(context);
-
-        contract.finalize_request(0);
-        assert_eq!(contract.get_request(0).status, REJECTED);
+        contract.finalize_request(request_id);
+        assert_eq!(contract.get_request(request_id).status, REJECTED);
+        assert_eq!(contract.get_balance(carol(), standart_token().token_id).0, 900);
     }
 
     #[test]
-    fn test_wallets() {
-        let context = get_context(carol());
-        testing_env!(context);
-        let mut contract = Contract::new(carol());
+    fn test_proposal_deposit_slashed_without_quorum() {
+        let mut contract = get_contract_with_request_and_staking();
+        contract.governance.proposal_deposit = U128(50);
+        testing_env!(get_extend_context(carol(), carol()));
+        let request_id = contract.add_proposal("".to_string(), "".to_string(), fees_proposal());
+        assert_eq!(contract.get_open_requests_count(carol()), 2);
 
-        contract.pay_standard_token(U128(3), bob());
-        assert_eq!(env::account_balance(), 97);
-        let balance = contract
-            .get_balance(bob(), standart_token().token_id)
-            .0;
-        let account = contract
-            .get_wallet(&bob())
-            .get_account(&standart_token().token_id);
+        let mut context = get_extend_context(carol(), carol());
+        context.block_timestamp = NANOSEC_IN_DAY;
+        testing_env!(context);
+        contract.finalize_request(request_id);
+        assert_eq!(contract.get_request(request_id).status, REJECTED);
+        assert_eq!(contract.get_balance(carol(), standart_token().token_id).0, 850);
+        assert_eq!(contract.get_treasury_balance(standart_token().token_id).0, 50);
+        assert_eq!(contract.get_open_requests_count(carol()), 1);
+    }
 
-        assert_eq!(account.unwrap().balance, 156u128);
-        assert_eq!(balance, 156u128);
+    #[test]
+    fn test_open_requests_limit() {
+        let mut contract = get_contract_with_request();
+        testing_env!(get_extend_context(carol(), carol()));
+        contract.add_proposal("".to_string(), "".to_string(), fees_proposal());
+        contract.add_proposal("".to_string(), "".to_string(), fees_proposal());
+        assert_eq!(contract.get_open_requests_count(carol()), 3);
+        catch_unwind_silent(move || {
+            contract.add_proposal("".to_string(), "".to_string(), fees_proposal());
+        }).unwrap_err();
     }
 
     #[test]
-    fn test_pay() {
-        let mut context = get_context(carol());
-        context.account_balance *= YOKTO_NEAR;
-        testing_env!(context);
-        let mut contract = Contract::new(carol());
+    fn test_finalize_twice_failed() {
+        let mut contract = get_contract_with_request_and_staking();
+        let request_id = pass_proposal(&mut contract, fees_proposal(), 0);
+        catch_unwind_silent(move || {
+            contract.finalize_request(request_id);
+        }).unwrap_err();
+    }
 
-        let oldBalance = env::account_balance();
-        let amount = 3;
-        contract.pay_standard_token(U128(amount), bob());
-        assert_eq!(env::account_balance(), oldBalance - amount * YOKTO_NEAR);
-        let balance = contract
-            .get_balance(bob(), standart_token().token_id)
-            .0;
-        assert_eq!(balance, contract.get_standard_price() * amount);
+    fn description_update(description: &str) -> TokenMetadataUpdate {
+        TokenMetadataUpdate {
+            description: Some(description.to_string()),
+            icon: None,
+            reference: None,
+            reference_hash: None,
+        }
     }
 
     #[test]
-    fn test_self_allowance_fail() {
-        let context = get_context(carol());
+    fn update_token_metadata() {
+        let mut contract = init_contract_with_tokens();
+        contract.update_token_metadata(test_token().token_id, description_update("Тестовый токен"));
+        let meta = contract.ft_metadata(test_token().token_id);
+        assert_eq!(meta.description, Some("Тестовый токен".to_string()));
+        assert_eq!(meta.symbol, test_token().meta.symbol);
+        assert_eq!(get_events("token_metadata_update").len(), 1);
+
+        let mut context = get_context(bob());
+        context.block_timestamp = NANOSEC_IN_DAY;
         testing_env!(context);
-        let total_supply = standart_token().supply;
-        let mut contract = Contract::new(carol());
+        contract.update_token_metadata(test_token().token_id, description_update("Еще раз"));
+        assert_eq!(contract.ft_metadata(test_token().token_id).description, Some("Еще раз".to_string()));
+    }
+
+    #[test]
+    fn update_token_metadata_cooldown_failed() {
+        let mut contract = init_contract_with_tokens();
+        contract.update_token_metadata(test_token().token_id, description_update("Тестовый токен"));
         catch_unwind_silent(move || {
-            contract.set_allowance(carol(), standart_token().token_id, (total_supply / 2).into());
-        })
-            .unwrap_err();
+            contract.update_token_metadata(test_token().token_id, description_update("Еще раз"));
+        }).unwrap_err();
     }
 
     #[test]
-    fn test_carol_add_new_token() {
-        // Acting as carol
+    fn update_token_metadata_by_non_issuer_failed() {
+        let mut contract = init_contract_with_tokens();
         testing_env!(get_context(carol()));
-        let total_supply = 10000;
-        let mut contract = Contract::new(carol());
-
-        contract.add_token(Token {
-            token_id: test_token().token_id,
-            supply: test_token().supply,
-            owner_id: carol(),
-            meta: None,
-        });
-        let balance = contract
-            .get_balance(carol(), test_token().token_id)
-            .0;
-        assert_eq!(balance, 10000u128);
-
-        let allowance = total_supply / 3;
-        let transfer_amount = allowance / 3;
-        contract.set_allowance(bob(), test_token().token_id, allowance.into());
-        assert_eq!(
-            contract
-                .get_allowance(carol(), bob(), test_token().token_id)
-                .0,
-            allowance
-        );
-        // Acting as bob now
-        testing_env!(get_context(bob()));
-        contract.transfer_from(
-            carol(),
-            alice(),
-            test_token().token_id,
-            transfer_amount.into(),
-        );
-        assert_eq!(
-            contract.get_balance(carol(), test_token().token_id).0,
-            total_supply - transfer_amount
-        );
-        assert_eq!(
-            contract.get_balance(alice(), test_token().token_id).0,
-            transfer_amount
-        );
-        assert_eq!(
-            contract.get_allowance(carol(), bob(), standart_token().token_id).0,
-            allowance - transfer_amount
-        );
+        catch_unwind_silent(move || {
+            contract.update_token_metadata(test_token().token_id, description_update("Скам"));
+        }).unwrap_err();
     }
 
     #[test]
-    fn test_carol_escrows_to_bob_transfers_to_alice() {
-        // Acting as carol
-        testing_env!(get_context(carol()));
-        let total_supply = standart_token().supply;
-        let mut contract = Contract::new(carol());
-        assert_eq!(contract.get_total_supply(), total_supply);
-        let allowance = total_supply / 3;
-        let transfer_amount = allowance / 3;
-        contract.set_allowance(bob(), standart_token().token_id, allowance.into());
-        assert_eq!(contract.get_allowance(carol(), bob(), standart_token().token_id).0, allowance);
-        // Acting as bob now
+    fn override_token_metadata() {
+        let mut contract = get_contract_with_request_and_staking();
         testing_env!(get_context(bob()));
-        contract.transfer_from(carol(), alice(), standart_token().token_id, transfer_amount.into());
-        assert_eq!(
-            contract.get_balance(carol(), standart_token().token_id).0,
-            total_supply - transfer_amount
-        );
-        assert_eq!(contract.get_balance(alice(), standart_token().token_id).0, transfer_amount);
-        assert_eq!(
-            contract.get_allowance(carol(), bob(), standart_token().token_id).0,
-            allowance - transfer_amount
-        );
+        contract.update_token_metadata(test_token().token_id, description_update("Оскорбление"));
+        let kind = RequestKind::OverrideTokenMetadata {
+            token_id: test_token().token_id,
+            meta: test_token().meta,
+        };
+        pass_proposal(&mut contract, kind, 0);
+        assert_eq!(contract.ft_metadata(test_token().token_id), test_token().meta);
+        let updates = get_events("token_metadata_update");
+        assert_eq!(updates[0]["updated_by"], alice());
     }
 
-    fn print_all_balances(contract: &Contract, account: AccountId) {
-        for (token, balance) in contract.get_balances(
-            account.clone(),
-            [standart_token().token_id, test_token().token_id].to_vec(),
-        ) {
-            println!("Acc:{} token: {} balance: {}", account, token, balance);
-        }
+    #[test]
+    fn override_token_metadata_of_unknown_token_failed() {
+        let mut contract = get_contract_with_request_and_staking();
+        testing_env!(get_extend_context(carol(), carol()));
+        let kind = RequestKind::OverrideTokenMetadata {
+            token_id: "UNKNOWN".to_string(),
+            meta: test_token().meta,
+        };
+        catch_unwind_silent(move || {
+            contract.add_proposal("Метаданные".to_string(), "".to_string(), kind);
+        }).unwrap_err();
     }
 
-    fn init_contract_with_tokens_and_limit_bids() -> Contract {
-        let mut contract = init_contract_with_tokens();
-        contract.new_limit_order(test_token().token_id, 5.0, 100, "Ask".to_string());
-        contract.new_limit_order(test_token().token_id, 6.0, 200, "Ask".to_string());
-        contract.new_limit_order(test_token().token_id, 10.0, 300, "Ask".to_string());
-        contract.new_limit_order(test_token().token_id, 11.0, 500, "Ask".to_string());
-        contract.new_limit_order(test_token().token_id, 16.0, 1000, "Ask".to_string());
-        contract.new_limit_order(test_token().token_id, 3.0, 20, "Bid".to_string());
-        contract.new_limit_order(test_token().token_id, 2.0, 40, "Bid".to_string());
-        contract.new_limit_order(test_token().token_id, 1.0, 100, "Bid".to_string());
-
-        let context = get_extend_context(carol(), carol());
-        testing_env!(context);
-        contract.new_limit_order(test_token().token_id, 5.0, 50, "Ask".to_string());
-        contract.new_limit_order(test_token().token_id, 4.0, 30, "Ask".to_string());
-        contract.new_limit_order(test_token().token_id, 1.0, 100, "Bid".to_string());
-
-        let spread = contract.get_current_spread(test_token().token_id);
-        println!("Spread => Ask: {}, Bid: {}", spread[0], spread[1]);
-        assert_eq!(spread[0], 4.0);
-        assert_eq!(spread[1], 3.0);
+    fn init_contract_with_swap_route() -> Contract {
+        let mut contract = init_contract_with_tokens_and_limit_bids();
+        testing_env!(get_context(bob()));
+        contract.add_token(Token {
+            token_id: "SECOND".to_string(),
+            owner_id: bob(),
+            total_supply: 10000,
+            max_supply: None,
+            meta: TokenMetadata::new("SECOND", "SECOND", 2),
+            origin: TokenOrigin::Native,
+        });
+        contract.new_limit_order("SECOND".to_string(), 4.0, 10, "Ask".to_string());
+        contract.new_limit_order("SECOND".to_string(), 5.0, 100, "Ask".to_string());
         contract
     }
 
     #[test]
-    fn new_limit_order() {
-        testing_env!(get_context(bob()));
-        let mut contract = init_contract_with_tokens();
-
-        // Currrent Spread
-        let spread = contract.get_current_spread(test_token().token_id);
-        println!("Spread => Ask: {}, Bid: {}", spread[0], spread[1]);
-        assert_eq!(spread[0], 0.0);
-        assert_eq!(spread[1], 0.0);
-
-        let std_balance = contract.get_balance(bob(), standart_token().token_id).0;
-        let test_balance = contract.get_balance(bob(), test_token().token_id).0;
-        // Ask Order
-        contract.new_limit_order(test_token().token_id, 1.25, 2, "Ask".to_string());
-        // Bid Order
-        contract.new_limit_order(test_token().token_id, 1.22, 50, "Bid".to_string());
-        contract.new_limit_order(test_token().token_id, 1.20, 50, "Bid".to_string());
+    fn swap_route() {
+        let mut contract = init_contract_with_swap_route();
+        testing_env!(get_extend_context(ivan(), ivan()));
 
-        assert_eq!(
-            contract.get_balance(alice(), standart_token().token_id).0 +
-                contract.get_balance(bob(), standart_token().token_id).0,
-            std_balance
-        );
-        assert_eq!(
-            contract.get_balance(alice(), test_token().token_id).0 +
-                contract.get_balance(bob(), test_token().token_id).0,
-            test_balance
+        let out = contract.swap_route(
+            vec![test_token().token_id, standart_token().token_id, "SECOND".to_string()],
+            U128(50),
+            U128(26),
         );
-        let spread = contract.get_current_spread(test_token().token_id);
-        println!("Spread => Ask: {}, Bid: {}", spread[0], spread[1]);
-        assert_eq!(spread[0], 1.25);
-        assert_eq!(spread[1], 1.22);
+        assert_eq!(out.0, 26);
+        assert_eq!(contract.get_balance(ivan(), test_token().token_id).0, 950);
+        assert_eq!(contract.get_balance(ivan(), standart_token().token_id).0, 1000);
+        assert_eq!(contract.get_balance(ivan(), "SECOND".to_string()).0, 26);
     }
 
     #[test]
-    fn matching_limit_order() {
-        let mut contract = init_contract_with_tokens_and_limit_bids();
-        let context = get_extend_context(ivan(), ivan());
-        testing_env!(context);
-
-        assert_eq!(contract.get_balance(ivan(), standart_token().token_id).0, 1000u128);
-        let balance_bob = contract.get_balance(bob(), test_token().token_id).0;
-        contract.new_limit_order(test_token().token_id, 1.0, 50, "Ask".to_string());
-        assert_eq!(contract.get_balance(ivan(), standart_token().token_id).0, 1120u128);
-        assert_eq!(contract.get_balance(bob(), test_token().token_id).0, balance_bob + 50);
-
-        let spread = contract.get_current_spread(test_token().token_id);
-        println!("Spread => Ask: {}, Bid: {}", spread[0], spread[1]);
-        assert_eq!(spread[0], 4.0);
-        assert_eq!(spread[1], 2.0);
+    fn swap_route_reserves_taker_fee() {
+        let mut contract = init_contract_with_swap_route();
+        contract.fees.trading_fee_bps = 1_000;
+        testing_env!(get_extend_context(ivan(), ivan()));
 
-        print_all_balances(&contract, ivan());
-        contract.new_limit_order(test_token().token_id, 5.0, 50, "Bid".to_string());
-        print_all_balances(&contract, bob());
-        print_all_balances(&contract, ivan());
-        assert_eq!(contract.get_balance(bob(), standart_token().token_id).0, 99999996860u128);
-        assert_eq!(contract.get_balance(ivan(), test_token().token_id).0, 1000u128);
-        let spread = contract.get_current_spread(test_token().token_id);
-        println!("Spread => Ask: {}, Bid: {}", spread[0], spread[1]);
-        assert_eq!(spread[0], 5.0);
-        assert_eq!(spread[1], 2.0);
+        let out = contract.swap_route(
+            vec![standart_token().token_id, "SECOND".to_string()],
+            U128(120),
+            U128(1),
+        );
+        // на заявки остается 108 из 120: 23 SECOND стоят 105 XDHO и еще 10 уходит в комиссию
+        assert_eq!(out.0, 23);
+        assert_eq!(contract.get_balance(ivan(), standart_token().token_id).0, 1000 - 105 - 10);
     }
 
     #[test]
-    fn matching_market_order() {
-        let mut contract = init_contract_with_tokens_and_limit_bids();
-        let context = get_extend_context(ivan(), ivan());
-        testing_env!(context);
-
-        assert_eq!(contract.get_balance(ivan(), standart_token().token_id).0, 1000u128);
-        assert_eq!(contract.get_balance(bob(), test_token().token_id).0, 5900u128);
-        contract.new_market_order(test_token().token_id, 50, "Ask".to_string());
-        assert_eq!(contract.get_balance(ivan(), standart_token().token_id).0, 1120u128);
-        assert_eq!(contract.get_balance(bob(), test_token().token_id).0, 5950u128);
-        print_all_balances(&contract, bob());
-        print_all_balances(&contract, ivan());
+    fn swap_route_min_out_failed() {
+        let mut contract = init_contract_with_swap_route();
+        testing_env!(get_extend_context(ivan(), ivan()));
 
-        let spread = contract.get_current_spread(test_token().token_id);
-        println!("Spread => Ask: {}, Bid: {}", spread[0], spread[1]);
-        assert_eq!(spread[0], 4.0);
-        assert_eq!(spread[1], 2.0);
+        catch_unwind_silent(move || {
+            contract.swap_route(
+                vec![test_token().token_id, standart_token().token_id, "SECOND".to_string()],
+                U128(50),
+                U128(27),
+            );
+        }).unwrap_err();
+    }
 
-        contract.new_market_order(test_token().token_id, 50, "Bid".to_string());
+    #[test]
+    fn swap_route_without_standard_token_failed() {
+        let mut contract = init_contract_with_swap_route();
+        testing_env!(get_extend_context(ivan(), ivan()));
 
-        print_all_balances(&contract, ivan());
-        let spread = contract.get_current_spread(test_token().token_id);
-        println!("Spread => Ask: {}, Bid: {}", spread[0], spread[1]);
-        assert_eq!(spread[0], 5.0);
-        assert_eq!(spread[1], 2.0);
+        catch_unwind_silent(move || {
+            contract.swap_route(
+                vec![test_token().token_id, "SECOND".to_string()],
+                U128(50),
+                U128(0),
+            );
+        }).unwrap_err();
     }
 
     #[test]
@@ -0,0 +1,337 @@
+use near_sdk::{AccountId, Balance, env, Timestamp};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet, Vector};
+
+use orderbook::Orderbook;
+
+use crate::Contract;
+use crate::access_control::AccessControl;
+use crate::ballot::{BallotHandler, LaunchPad, StakeInfo};
+use crate::delegation::Delegations;
+use crate::fees::FeeRates;
+use crate::governance::GovernanceConfig;
+use crate::pause::PauseFlags;
+use crate::request::{Listing, Request, RequestId, RequestKind, RequestStatus};
+use crate::secret_ballot::SecretBallots;
+use crate::token::{Token, TokenId, TokenMetadata, TokenOrigin};
+use crate::wallet::TokenWallet;
+
+/// Текущая версия раскладки состояния
+pub const STATE_VERSION: u32 = 2;
+/// Ключ, под которым хранится версия состояния. Состояние без версии - это V1.
+pub const STATE_VERSION_KEY: &[u8] = b"__state_version";
+
+/// Исходное состояние с единственным владельцем `owner_id`.
+/// Коллекции `ballot_handler` совпадают по раскладке с текущими, меняются только их элементы.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ContractV1 {
+    pub wallets: LookupMap<Vec<u8>, TokenWallet>,
+    pub order_books: LookupMap<TokenId, Orderbook>,
+    pub tokens: UnorderedMap<TokenId, TokenV1>,
+    pub ballot_handler: BallotHandler,
+    pub staking: UnorderedMap<Vec<u8>, StakeInfo>,
+    pub launchpad: UnorderedMap<TokenId, LaunchPadV1>,
+    pub owner_id: AccountId,
+}
+
+/// Метаданные токена версии 1, до NEP-148
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TokenMetadataV1 {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub icon: Option<String>,
+}
+
+/// Токен версии 1: без ограничения выпуска и происхождения, все токены выпущены на бирже
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TokenV1 {
+    pub token_id: TokenId,
+    pub owner_id: AccountId,
+    pub supply: Balance,
+    pub meta: Option<TokenMetadataV1>,
+}
+
+impl TokenV1 {
+    fn into_current(self) -> Token {
+        let meta = token_metadata_v1(&self.token_id, self.meta);
+        Token {
+            token_id: self.token_id,
+            owner_id: self.owner_id,
+            total_supply: self.supply,
+            max_supply: None,
+            meta,
+            origin: TokenOrigin::Native,
+        }
+    }
+}
+
+/// Листинг версии 1
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct LaunchPadV1 {
+    pub price: Balance,
+    pub sell_supply: u128,
+    pub launched_time: Timestamp,
+    pub token: TokenV1,
+}
+
+/// Заявка версии 1: все заявки - листинг, без метаданных и вестинга
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct RequestV1 {
+    pub id: RequestId,
+    pub token_id: String,
+    pub title: String,
+    pub description: String,
+    pub price: Balance,
+    pub supply: Balance,
+    pub status: RequestStatus,
+    pub created_time: Timestamp,
+    pub owner_id: AccountId,
+    pub hash: String,
+}
+
+/// Голос версии 1, без веса. Голоса всех заявок лежали в множествах с общим префиксом `vot`.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct VoteV1 {
+    pub owner_id: AccountId,
+    pub result: bool,
+}
+
+/// Метаданные NEP-148 из метаданных версии 1. Символом становится идентификатор токена,
+/// знаков после запятой у стандартного токена 2, у остальных 0, как считала биржа версии 1.
+/// Иконка переносится, только если это data URL.
+fn token_metadata_v1(token_id: &TokenId, meta: Option<TokenMetadataV1>) -> TokenMetadata {
+    let decimals = if token_id == "XDHO" { 2 } else { 0 };
+    let meta = match meta {
+        Some(meta) => meta,
+        None => return TokenMetadata::new(token_id, token_id, decimals),
+    };
+    TokenMetadata {
+        description: meta.description,
+        icon: meta.icon.filter(|icon| icon.starts_with("data:")),
+        ..TokenMetadata::new(&meta.title.unwrap_or_else(|| token_id.clone()), token_id, decimals)
+    }
+}
+
+/// Все известные раскладки состояния контракта
+pub enum VersionedContract {
+    V1(Box<ContractV1>),
+    V2(Box<Contract>),
+}
+
+impl VersionedContract {
+    /// Читает состояние в той раскладке, версия которой записана в `STATE_VERSION_KEY`
+    pub fn read() -> Self {
+        match read_state_version() {
+            1 => VersionedContract::V1(Box::new(read_state())),
+            2 => VersionedContract::V2(Box::new(read_state())),
+            _ => env::panic(b"Unknown state version"),
+        }
+    }
+
+    /// Поднимает состояние до текущей версии
+    pub fn into_current(self) -> Contract {
+        match self {
+            VersionedContract::V1(state) => migrate_v1(*state),
+            VersionedContract::V2(state) => *state,
+        }
+    }
+}
+
+/// Версия сохраненного состояния. Состояние без записанной версии - это V1.
+pub fn read_state_version() -> u32 {
+    env::storage_read(STATE_VERSION_KEY)
+        .map(|bytes| u32::try_from_slice(&bytes).expect("Invalid state version"))
+        .unwrap_or(1)
+}
+
+pub fn write_state_version() {
+    env::storage_write(STATE_VERSION_KEY, &STATE_VERSION.try_to_vec().unwrap());
+}
+
+/// Та же коллекция с другим типом элементов: раскладка коллекции от него не зависит
+fn retype<A: BorshSerialize, B: BorshDeserialize>(collection: &A) -> B {
+    B::try_from_slice(&collection.try_to_vec().unwrap()).unwrap()
+}
+
+fn read_state<T: BorshDeserialize>() -> T {
+    match env::state_read() {
+        Some(state) => state,
+        None => env::panic(b"The contract is not initialized"),
+    }
+}
+
+/// V1 -> V2: владелец становится Admin, токены, листинги и заявки переписываются в текущей
+/// раскладке, счета токенов - при первой записи (ключ счета содержит версию раскладки). Голоса версии 1 не отнести
+/// к заявкам, поэтому они удаляются, и по незавершенным заявкам голосуют заново: срок отсчитывается от миграции.
+/// Стейки целиком переходят со счета владельца на счет контракта: если баланса владельца не хватает,
+/// миграция не проходит. Резерв наград пуст.
+/// Параметры голосования и комиссии - по умолчанию, добавляется wNEAR.
+/// Индекс держателей заполняется всеми аккаунтами, которые видны в состоянии версии 1: эмитентами,
+/// авторами заявок, создателями ордеров и самой биржей. Счета версии 1 не перечислить, поэтому
+/// остальные держатели попадают в индекс при первом изменении баланса.
+fn migrate_v1(state: ContractV1) -> Contract {
+    let mut old_tokens: UnorderedMap<TokenId, TokenV1> = state.tokens;
+    let tokens: Vec<TokenV1> = old_tokens.values().collect();
+    old_tokens.clear();
+    let mut old_launchpad: UnorderedMap<TokenId, LaunchPadV1> = state.launchpad;
+    let pads: Vec<LaunchPadV1> = old_launchpad.values().collect();
+    old_launchpad.clear();
+    let mut old_requests: UnorderedMap<RequestId, RequestV1> = retype(&state.ballot_handler.requests);
+    let requests: Vec<RequestV1> = old_requests.values().collect();
+    old_requests.clear();
+
+    let mut ballot_handler = state.ballot_handler;
+    ballot_handler.requests = UnorderedMap::new(b"req".to_vec());
+    let request_ids: Vec<RequestId> = requests.iter().map(|request| request.id).collect();
+    let mut known_accounts: Vec<AccountId> = requests.iter().map(|request| request.owner_id.clone()).collect();
+    let mut open_requests: LookupMap<AccountId, u32> = LookupMap::new(b"or".to_vec());
+    let governance = GovernanceConfig::default();
+    for request in requests {
+        let pending = request.status == RequestStatus::PENDING;
+        let mut terms = governance.voting_terms();
+        if !pending {
+            terms.voting_end = request.created_time + governance.voting_period;
+        }
+        let approved_time = match request.status {
+            RequestStatus::PENDING | RequestStatus::REJECTED => None,
+            _ => Some(terms.voting_end),
+        };
+        ballot_handler.requests.insert(&request.id, &Request {
+            id: request.id,
+            title: request.title,
+            description: request.description,
+            kind: RequestKind::Listing(Listing {
+                meta: token_metadata_v1(&request.token_id, None),
+                token_id: request.token_id,
+                price: request.price,
+                supply: request.supply,
+                max_supply: None,
+                vesting: None,
+            }),
+            status: request.status,
+            created_time: request.created_time,
+            approved_time,
+            terms,
+            owner_id: request.owner_id.clone(),
+            hash: request.hash,
+        });
+        if pending {
+            let open_count = open_requests.get(&request.owner_id).unwrap_or(0);
+            open_requests.insert(&request.owner_id, &(open_count + 1));
+        }
+    }
+    clear_votes_v1(&request_ids);
+
+    let mut contract = Contract {
+        wallets: state.wallets,
+        order_books: state.order_books,
+        tokens: UnorderedMap::new(b"t".to_vec()),
+        ballot_handler,
+        staking: state.staking,
+        launchpad: UnorderedMap::new(b"launch".to_vec()),
+        bridged_tokens: LookupMap::new(b"b".to_vec()),
+        storage_accounts: LookupMap::new(b"sa".to_vec()),
+        metadata_updated: LookupMap::new(b"mu".to_vec()),
+        airdrops: UnorderedMap::new(b"ad".to_vec()),
+        next_airdrop_id: 0,
+        holders: LookupMap::new(b"hd".to_vec()),
+        holder_exits: LookupMap::new(b"hx".to_vec()),
+        snapshots: Vector::new(b"sn".to_vec()),
+        latest_snapshot: LookupMap::new(b"ls".to_vec()),
+        token_snapshots: LookupMap::new(b"ts".to_vec()),
+        snapshot_balances: LookupMap::new(b"sb".to_vec()),
+        dividend_per_share: LookupMap::new(b"dps".to_vec()),
+        dividend_accounts: LookupMap::new(b"da".to_vec()),
+        launchpad_vesting: None,
+        access: AccessControl::new(state.owner_id),
+        paused: PauseFlags::default(),
+        governance,
+        fees: FeeRates::default(),
+        treasury: UnorderedMap::new(b"tr".to_vec()),
+        markets: LookupMap::new(b"ms".to_vec()),
+        proposal_deposits: LookupMap::new(b"pd".to_vec()),
+        open_requests,
+        delegations: Delegations::new(),
+        secret_ballots: SecretBallots::new(),
+        staking_rewards: 0,
+        pending_votes: LookupMap::new(b"pv".to_vec()),
+        order_escrow: LookupMap::new(b"oe".to_vec()),
+    };
+    known_accounts.push(env::current_account_id());
+    for token in tokens {
+        let token = token.into_current();
+        contract.tokens.insert(&token.token_id, &token);
+        known_accounts.push(token.owner_id.clone());
+        if let Some(order_book) = contract.order_books.get(&token.token_id) {
+            for order in order_book.bid_queue.orders.values().chain(order_book.ask_queue.orders.values()) {
+                known_accounts.push(order.order_creator.clone());
+            }
+        }
+    }
+    known_accounts.sort();
+    known_accounts.dedup();
+    for token_id in contract.tokens.keys().collect::<Vec<TokenId>>() {
+        let mut holders = contract.get_holder_set(&token_id);
+        for account_id in &known_accounts {
+            if contract.get_account(account_id, token_id.clone()).balance > 0 {
+                holders.insert(account_id);
+            }
+        }
+        if !holders.is_empty() {
+            contract.holders.insert(&token_id, &holders);
+        }
+    }
+    for pad in pads {
+        let token = pad.token.into_current();
+        contract.launchpad.insert(&token.token_id.clone(), &LaunchPad {
+            price: pad.price,
+            sell_supply: pad.sell_supply,
+            launched_time: pad.launched_time,
+            token,
+            vesting: None,
+        });
+    }
+    // стейки версии 1 лежали на счете владельца, теперь они на счете контракта
+    let owner_id = contract.access.owner_id.clone();
+    let standard_token = contract.get_standard_token();
+    let escrow = contract.get_all_staked();
+    if escrow > contract.get_account(&owner_id, standard_token.clone()).balance {
+        env::panic(b"Owner balance does not cover the staked tokens");
+    }
+    if escrow > 0 {
+        contract.internal_transfer(&owner_id, &env::current_account_id(), &standard_token, escrow, None);
+    }
+    if contract.tokens.get(&contract.get_near_token()).is_none() {
+        contract.register_token(&Token {
+            token_id: contract.get_near_token(),
+            owner_id: env::current_account_id(),
+            total_supply: 0,
+            max_supply: None,
+            meta: TokenMetadata::new("Wrapped NEAR", "wNEAR", 24),
+            origin: TokenOrigin::Near,
+        });
+    }
+    contract
+}
+
+/// Голоса версии 1 всех заявок лежали в одном множестве `vot`, в записях заявок - только его длина
+/// на момент голосования. Удаляет записи заявок и само множество по ключам хранилища,
+/// не доверяя сохраненной длине.
+fn clear_votes_v1(request_ids: &[RequestId]) {
+    let mut votes: LookupMap<RequestId, UnorderedSet<VoteV1>> = LookupMap::new(b"v".to_vec());
+    for request_id in request_ids {
+        votes.remove(request_id);
+    }
+    for index in 0u64.. {
+        let mut element_key = b"vote".to_vec();
+        element_key.extend(&index.to_le_bytes());
+        let element = match env::storage_read(&element_key) {
+            Some(element) => element,
+            None => break,
+        };
+        let mut index_key = b"voti".to_vec();
+        index_key.extend(&element);
+        env::storage_remove(&index_key);
+        env::storage_remove(&element_key);
+    }
+}
@@ -0,0 +1,66 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::env;
+use near_sdk::serde::{Deserialize, Serialize};
+
+/// Подсистемы, которые можно поставить на паузу независимо друг от друга
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum Subsystem {
+    /// новые ордера и свопы
+    Trading,
+    /// переводы токенов между аккаунтами
+    Transfers,
+    Staking,
+    Voting,
+    /// запуск лаунчпада и покупки на нем
+    Launchpad,
+}
+
+/// Флаги паузы. Отмены ордеров, выводы и возвраты работают всегда.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PauseFlags {
+    pub trading: bool,
+    pub transfers: bool,
+    pub staking: bool,
+    pub voting: bool,
+    pub launchpad: bool,
+}
+
+impl PauseFlags {
+    pub fn is_paused(&self, subsystem: Subsystem) -> bool {
+        match subsystem {
+            Subsystem::Trading => self.trading,
+            Subsystem::Transfers => self.transfers,
+            Subsystem::Staking => self.staking,
+            Subsystem::Voting => self.voting,
+            Subsystem::Launchpad => self.launchpad,
+        }
+    }
+
+    pub fn set(&mut self, subsystem: Subsystem, paused: bool) {
+        match subsystem {
+            Subsystem::Trading => self.trading = paused,
+            Subsystem::Transfers => self.transfers = paused,
+            Subsystem::Staking => self.staking = paused,
+            Subsystem::Voting => self.voting = paused,
+            Subsystem::Launchpad => self.launchpad = paused,
+        }
+    }
+
+    pub fn assert_not_paused(&self, subsystem: Subsystem) {
+        if self.is_paused(subsystem) {
+            env::panic(format!("{:?} is paused", subsystem).as_bytes());
+        }
+    }
+}
+
+/// Состояние рынка отдельного токена. Рынок без записи работает.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum MarketStatus {
+    /// новые ордера не принимаются, пока торги не возобновят
+    Halted,
+    /// токен снят с торгов навсегда
+    Delisted,
+}
@@ -1,24 +1,120 @@
 use near_sdk::{AccountId, Balance, Timestamp};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::serde::Serialize;
+use near_sdk::json_types::{Base64VecU8, U128};
+use near_sdk::serde::{Deserialize, Serialize};
+
+use crate::fees::FeeRates;
+use crate::governance::{GovernanceConfig, VotingTerms};
+use crate::token::{TokenId, TokenMetadata};
+use crate::vesting::VestingTerms;
 
 #[derive(Clone, Debug, BorshDeserialize, BorshSerialize, Serialize, PartialEq)]
 pub enum RequestStatus {
     REJECTED,
     APPROVED,
     PENDING,
+    /// лаунчпад запущен, для обновления кода - код развернут
     LAUNCHED,
     TRADED,
+    /// заявку одобрили, но к завершению голосования ее уже нельзя исполнить
+    #[allow(non_camel_case_types)]
+    EXECUTION_FAILED,
 }
 
 pub type RequestId = u64;
 
+/// Условия листинга токена через лаунчпад
+#[derive(Clone, BorshDeserialize, BorshSerialize, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Listing {
+    pub token_id: TokenId,
+    pub price: Balance,
+    pub supply: Balance,
+    pub max_supply: Option<Balance>,
+    pub meta: TokenMetadata,
+    /// вестинг непроданных токенов эмитента, назначается при одобрении заявки
+    pub vesting: Option<VestingTerms>,
+}
+
+/// Что делает заявка после одобрения. У каждого вида свой обработчик в `Contract::execute_request`.
+#[derive(Clone, BorshDeserialize, BorshSerialize, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum RequestKind {
+    /// листинг токена, лаунчпад запускает автор заявки
+    Listing(Listing),
+    /// торги токеном прекращаются навсегда, остается только отмена ордеров и вывод
+    Delist { token_id: TokenId },
+    ChangeFees { fees: FeeRates },
+    /// новые параметры голосования
+    ChangeGovernanceConfig { config: GovernanceConfig },
+    /// выплата из казны биржи
+    TreasurySpend { receiver_id: AccountId, token_id: TokenId, amount: U128 },
+    /// остановка или возобновление торгов токеном
+    HaltMarket { token_id: TokenId, halted: bool },
+    /// обновление кода контракта, `code_hash` - sha256 нового wasm.
+    /// Код разворачивает `Contract::upgrade` после таймлока.
+    Upgrade { code_hash: Base64VecU8 },
+    /// замена метаданных токена в обход эмитента и `METADATA_UPDATE_COOLDOWN`
+    OverrideTokenMetadata { token_id: TokenId, meta: TokenMetadata },
+    /// вестинг непроданных токенов для листингов, одобренных после этой заявки
+    SetLaunchpadVesting { terms: Option<VestingTerms> },
+}
+
+/// Вид заявки без параметров, по нему настраивается голосование
+#[derive(Clone, Copy, BorshDeserialize, BorshSerialize, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ProposalType {
+    Listing,
+    Delist,
+    ChangeFees,
+    ChangeGovernanceConfig,
+    TreasurySpend,
+    HaltMarket,
+    Upgrade,
+    OverrideTokenMetadata,
+    SetLaunchpadVesting,
+}
+
+impl RequestKind {
+    pub fn proposal_type(&self) -> ProposalType {
+        match self {
+            RequestKind::Listing(_) => ProposalType::Listing,
+            RequestKind::Delist { .. } => ProposalType::Delist,
+            RequestKind::ChangeFees { .. } => ProposalType::ChangeFees,
+            RequestKind::ChangeGovernanceConfig { .. } => ProposalType::ChangeGovernanceConfig,
+            RequestKind::TreasurySpend { .. } => ProposalType::TreasurySpend,
+            RequestKind::HaltMarket { .. } => ProposalType::HaltMarket,
+            RequestKind::Upgrade { .. } => ProposalType::Upgrade,
+            RequestKind::OverrideTokenMetadata { .. } => ProposalType::OverrideTokenMetadata,
+            RequestKind::SetLaunchpadVesting { .. } => ProposalType::SetLaunchpadVesting,
+        }
+    }
+}
+
+/// Вариант голоса. Воздержавшиеся учитываются в кворуме, но не в одобрении.
+/// Порядок вариантов совпадает с прежним `bool` в borsh: `No` - false, `Yes` - true.
+#[derive(Clone, Copy, BorshDeserialize, BorshSerialize, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum VoteOption {
+    No,
+    Yes,
+    Abstain,
+}
+
+impl From<bool> for VoteOption {
+    fn from(result: bool) -> Self {
+        if result { VoteOption::Yes } else { VoteOption::No }
+    }
+}
+
 /// Metadata on the individual token level.
 #[derive(Clone, BorshDeserialize, BorshSerialize, Debug, Serialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct Vote {
     pub owner_id: AccountId,
-    pub result: bool,
+    pub result: VoteOption,
+    /// вес голосующего на момент голосования: его стейк и делегированный ему
+    pub weight: Balance,
 }
 
 impl PartialEq for Vote {
@@ -32,15 +128,16 @@ impl PartialEq for Vote {
 #[serde(crate = "near_sdk::serde")]
 pub struct Request {
     pub id: RequestId,
-    pub token_id: String,
     pub title: String,
     pub description: String,
-
-    pub price: Balance,
-    pub supply: Balance,
+    pub kind: RequestKind,
 
     pub status: RequestStatus,
     pub created_time: Timestamp,
+    /// срок, кворум и порог одобрения на момент подачи
+    pub terms: VotingTerms,
+    /// когда заявку одобрили при завершении голосования
+    pub approved_time: Option<Timestamp>,
     pub owner_id: AccountId,
     // полный текст заявки
     pub hash: String,
@@ -0,0 +1,106 @@
+use near_sdk::{AccountId, Balance, env};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, UnorderedMap};
+
+use crate::request::{RequestId, VoteOption};
+
+/// Скрытый голос: хеш голоса и вес голосующего на момент подачи хеша
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct VoteCommit {
+    pub hash: Vec<u8>,
+    pub weight: Balance,
+}
+
+/// Хеш скрытого голоса: sha256 от borsh-кортежа `(request_id, voter_id, вариант, соль)`,
+/// где вариант - байт 0 (No), 1 (Yes) или 2 (Abstain). Заявка и голосующий в хеше не дают
+/// повторить чужой хеш или перенести его на другую заявку.
+pub fn vote_hash(request_id: RequestId, voter_id: &AccountId, vote: VoteOption, salt: &str) -> Vec<u8> {
+    env::sha256(&(request_id, voter_id, vote as u8, salt).try_to_vec().unwrap())
+}
+
+/// Тайное голосование по заявкам: голоса лежат здесь хешами до раскрытия
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct SecretBallots {
+    /// заявки с тайным голосованием и длительность раскрытия, зафиксированная при подаче
+    reveal_periods: LookupMap<RequestId, u64>,
+    /// нераскрытые голоса, у каждой заявки свой префикс
+    commits: LookupMap<RequestId, UnorderedMap<AccountId, VoteCommit>>,
+}
+
+impl SecretBallots {
+    pub fn new() -> Self {
+        Self {
+            reveal_periods: LookupMap::new(b"cr".to_vec()),
+            commits: LookupMap::new(b"cm".to_vec()),
+        }
+    }
+
+    /// Переводит заявку в тайное голосование
+    pub fn open(&mut self, request_id: RequestId, reveal_period: u64) {
+        self.reveal_periods.insert(&request_id, &reveal_period);
+        self.commits.insert(&request_id, &self.get_commits(&request_id));
+    }
+
+    /// Сколько длится раскрытие голосов по заявке, `None` - голосование открытое
+    pub fn get_reveal_period(&self, request_id: RequestId) -> Option<u64> {
+        self.reveal_periods.get(&request_id)
+    }
+
+    /// Подает или заменяет скрытый голос `voter_id`
+    pub fn commit(&mut self, request_id: RequestId, voter_id: &AccountId, commit: VoteCommit) {
+        let mut commits = self.get_commits(&request_id);
+        commits.insert(voter_id, &commit);
+        self.commits.insert(&request_id, &commits);
+    }
+
+    pub fn is_committed(&self, request_id: RequestId, voter_id: &AccountId) -> bool {
+        self.get_commits(&request_id).get(voter_id).is_some()
+    }
+
+    /// Снимает скрытый голос, если `vote` и `salt` совпадают с его хешем. Возвращает вес голоса.
+    pub fn reveal(&mut self, request_id: RequestId, voter_id: &AccountId, vote: VoteOption, salt: &str) -> Balance {
+        let mut commits = self.get_commits(&request_id);
+        let commit = match commits.get(voter_id) {
+            Some(commit) => commit,
+            None => env::panic(b"There is no committed vote to reveal"),
+        };
+        if commit.hash != vote_hash(request_id, voter_id, vote, salt) {
+            env::panic(b"The vote doesn't match the committed hash");
+        }
+        commits.remove(voter_id);
+        self.commits.insert(&request_id, &commits);
+        commit.weight
+    }
+
+    /// Убирает нераскрытый голос `voter_id`
+    pub fn remove_commit(&mut self, request_id: RequestId, voter_id: &AccountId) {
+        let mut commits = self.get_commits(&request_id);
+        if commits.remove(voter_id).is_some() {
+            self.commits.insert(&request_id, &commits);
+        }
+    }
+
+    /// Аккаунты, не раскрывшие голос, с весом их голосов
+    pub fn get_unrevealed(&self, request_id: RequestId) -> Vec<(AccountId, Balance)> {
+        self.get_commits(&request_id)
+            .iter()
+            .map(|(voter_id, commit)| (voter_id, commit.weight))
+            .collect()
+    }
+
+    /// Забывает заявку после завершения голосования
+    pub fn close(&mut self, request_id: RequestId) {
+        self.reveal_periods.remove(&request_id);
+        if let Some(mut commits) = self.commits.remove(&request_id) {
+            commits.clear();
+        }
+    }
+
+    fn get_commits(&self, request_id: &RequestId) -> UnorderedMap<AccountId, VoteCommit> {
+        self.commits.get(request_id).unwrap_or_else(|| {
+            let mut prefix = b"cv".to_vec();
+            prefix.extend(&request_id.to_le_bytes());
+            UnorderedMap::new(prefix)
+        })
+    }
+}
@@ -0,0 +1,35 @@
+use near_sdk::{AccountId, Balance, BlockHeight, env, Timestamp};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+
+use crate::token::TokenId;
+
+pub type SnapshotId = u64;
+
+/// Снимок балансов токена. Балансы не копируются целиком: при первом изменении
+/// баланса после снимка старое значение сохраняется в `snapshot_balances`.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Snapshot {
+    pub token_id: TokenId,
+    pub block_index: BlockHeight,
+    pub timestamp: Timestamp,
+    pub total_supply: Balance,
+    /// длина журнала выбывших держателей токена на момент снимка: выбывшие позже держали токен в снимке
+    pub holder_exits: u64,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SnapshotView {
+    pub snapshot_id: SnapshotId,
+    pub token_id: TokenId,
+    pub block_index: BlockHeight,
+    pub timestamp: Timestamp,
+    pub total_supply: U128,
+}
+
+/// Ключ баланса `owner_id` на момент снимка `snapshot_id`
+pub fn snapshot_balance_key(snapshot_id: SnapshotId, owner_id: &AccountId) -> Vec<u8> {
+    env::sha256(format!("{}:{}", snapshot_id, owner_id).as_bytes())
+}
@@ -0,0 +1,60 @@
+use near_sdk::{Balance, env, StorageUsage};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+
+/// Сколько байт занимает сама регистрация аккаунта в хранилище
+pub const MIN_STORAGE_BYTES: StorageUsage = 200;
+
+/// Депозит аккаунта за хранение его данных в контракте
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct StorageAccount {
+    /// сколько yoctoNEAR внесено
+    pub deposit: Balance,
+    /// сколько байт занято кошельками, ордерами и заявками аккаунта
+    pub used_bytes: StorageUsage,
+}
+
+impl StorageAccount {
+    pub fn new(deposit: Balance) -> Self {
+        Self {
+            deposit,
+            used_bytes: 0,
+        }
+    }
+
+    /// Часть депозита, которая покрывает занятые байты
+    pub fn locked(&self) -> Balance {
+        (MIN_STORAGE_BYTES + self.used_bytes) as Balance * env::storage_byte_cost()
+    }
+
+    /// Часть депозита, которую можно вывести
+    pub fn available(&self) -> Balance {
+        self.deposit.saturating_sub(self.locked())
+    }
+}
+
+/// NEP-145 StorageBalance
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalance {
+    pub total: U128,
+    pub available: U128,
+}
+
+/// NEP-145 StorageBalanceBounds
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalanceBounds {
+    pub min: U128,
+    pub max: Option<U128>,
+}
+
+impl From<&StorageAccount> for StorageBalance {
+    fn from(account: &StorageAccount) -> Self {
+        Self {
+            total: U128(account.deposit),
+            available: U128(account.available()),
+        }
+    }
+}
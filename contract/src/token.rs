@@ -1,16 +1,111 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::serde::{Deserialize, Serialize};
-use near_sdk::{AccountId, Balance};
+use near_sdk::{AccountId, Balance, env};
+use near_sdk::json_types::Base64VecU8;
 
 pub type TokenId = String;
 
-/// Metadata on the individual token level.
+/// Версия стандарта метаданных NEP-148
+pub const FT_METADATA_SPEC: &str = "ft-1.0.0";
+
+/// Максимальная длина описания токена в байтах
+pub const MAX_DESCRIPTION_LEN: usize = 1024;
+
+/// Metadata on the individual token level (NEP-148).
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, BorshDeserialize, BorshSerialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct TokenMetadata {
-    pub title: Option<String>, // ex. "Arch Nemesis: Mail Carrier" or "Parcel #5055"
-    pub description: Option<String>, // free-form description
-    pub icon: Option<String>, // free-form description
+    pub spec: String, // "ft-1.0.0"
+    pub name: String, // ex. "Wrapped NEAR fungible token"
+    pub symbol: String, // ex. "wNEAR"
+    pub description: Option<String>, // описание от эмитента, не входит в NEP-148
+    pub icon: Option<String>, // data URL
+    pub reference: Option<String>, // ссылка на JSON с дополнительной информацией
+    pub reference_hash: Option<Base64VecU8>, // sha256 от JSON по ссылке `reference`
+    pub decimals: u8,
+}
+
+impl TokenMetadata {
+    pub fn new(name: &str, symbol: &str, decimals: u8) -> Self {
+        Self {
+            spec: FT_METADATA_SPEC.to_string(),
+            name: name.to_string(),
+            symbol: symbol.to_string(),
+            description: None,
+            icon: None,
+            reference: None,
+            reference_hash: None,
+            decimals,
+        }
+    }
+
+    /// Проверяет метаданные, паникует если они некорректны
+    pub fn assert_valid(&self) {
+        if self.spec != FT_METADATA_SPEC {
+            env::panic(b"Unsupported metadata spec, expected ft-1.0.0");
+        }
+        if self.name.is_empty() || self.symbol.is_empty() {
+            env::panic(b"Token name and symbol can't be empty");
+        }
+        if self.decimals > 24 {
+            env::panic(b"Token decimals can't be greater than 24");
+        }
+        if let Some(description) = &self.description {
+            if description.len() > MAX_DESCRIPTION_LEN {
+                env::panic(b"Token description is too long");
+            }
+        }
+        if let Some(icon) = &self.icon {
+            if !icon.starts_with("data:") {
+                env::panic(b"Token icon must be a data URL");
+            }
+        }
+        if self.reference.is_some() != self.reference_hash.is_some() {
+            env::panic(b"Reference and reference hash must be provided together");
+        }
+        if let Some(reference_hash) = &self.reference_hash {
+            if reference_hash.0.len() != 32 {
+                env::panic(b"Reference hash must be 32 bytes");
+            }
+        }
+    }
+
+    /// Применяет изменения эмитента и проверяет результат
+    pub fn apply(&mut self, update: TokenMetadataUpdate) {
+        if update.description.is_some() {
+            self.description = update.description;
+        }
+        if update.icon.is_some() {
+            self.icon = update.icon;
+        }
+        if update.reference.is_some() || update.reference_hash.is_some() {
+            self.reference = update.reference;
+            self.reference_hash = update.reference_hash;
+        }
+        self.assert_valid();
+    }
+}
+
+/// Изменения метаданных, которые может внести эмитент. `None` - оставить как есть.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenMetadataUpdate {
+    pub description: Option<String>,
+    pub icon: Option<String>,
+    pub reference: Option<String>,
+    pub reference_hash: Option<Base64VecU8>,
+}
+
+/// Откуда взялся токен
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, BorshDeserialize, BorshSerialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum TokenOrigin {
+    /// выпущен внутри биржи
+    Native,
+    /// внешний NEP-141 токен, который хранится на бирже
+    Bridged { contract_id: AccountId },
+    /// NEAR, внесенный на биржу
+    Near,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, BorshDeserialize, BorshSerialize)]
@@ -18,8 +113,12 @@ pub struct TokenMetadata {
 pub struct Token {
     pub token_id: TokenId,
     pub owner_id: AccountId,
-    pub supply: Balance,
-    pub meta: Option<TokenMetadata>,
+    /// текущий выпуск токена
+    pub total_supply: Balance,
+    /// максимальный выпуск, `None` - без ограничений
+    pub max_supply: Option<Balance>,
+    pub meta: TokenMetadata,
+    pub origin: TokenOrigin,
 }
 
 impl PartialEq for Token{
@@ -0,0 +1,74 @@
+use near_sdk::{Balance, env, Timestamp};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+
+/// Условия вестинга без привязки к сумме и времени начала
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, BorshDeserialize, BorshSerialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VestingTerms {
+    /// до конца этого срока от начала ничего не разблокируется
+    pub cliff_duration: u64,
+    /// за этот срок от начала разблокируется вся сумма
+    pub duration: u64,
+    /// может ли эмитент забрать еще не разблокированные токены
+    pub revocable: bool,
+}
+
+impl VestingTerms {
+    pub fn assert_valid(&self) {
+        if self.duration == 0 || self.cliff_duration > self.duration {
+            env::panic(b"Vesting cliff must not be longer than the vesting duration");
+        }
+    }
+}
+
+/// Вестинг на счете токена: `total` токенов разблокируются линейно после клиффа
+#[derive(Debug, Clone, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct VestingSchedule {
+    pub total: Balance,
+    pub start: Timestamp,
+    pub terms: VestingTerms,
+}
+
+impl VestingSchedule {
+    /// Сколько токенов еще заблокировано на момент `now`
+    pub fn locked(&self, now: Timestamp) -> Balance {
+        if now < self.start + self.terms.cliff_duration {
+            return self.total;
+        }
+        let elapsed = now - self.start;
+        if elapsed >= self.terms.duration {
+            return 0;
+        }
+        // total * elapsed / duration без переполнения: остаток от деления меньше duration
+        let duration = self.terms.duration as u128;
+        let elapsed = elapsed as u128;
+        let unlocked = self.total / duration * elapsed + self.total % duration * elapsed / duration;
+        self.total - unlocked
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VestingView {
+    pub total: U128,
+    pub start: Timestamp,
+    pub cliff_end: Timestamp,
+    pub end: Timestamp,
+    pub revocable: bool,
+    pub locked: U128,
+}
+
+impl From<&VestingSchedule> for VestingView {
+    fn from(schedule: &VestingSchedule) -> Self {
+        Self {
+            total: U128(schedule.total),
+            start: schedule.start,
+            cliff_end: schedule.start + schedule.terms.cliff_duration,
+            end: schedule.start + schedule.terms.duration,
+            revocable: schedule.terms.revocable,
+            locked: U128(schedule.locked(env::block_timestamp())),
+        }
+    }
+}
@@ -1,13 +1,17 @@
-use crate::account::TokenAccount;
+use crate::account::{TokenAccount, TokenAccountV1};
 use crate::token::TokenId;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::collections::LookupMap;
-use near_sdk::Balance;
+use near_sdk::{AccountId, Balance};
+
+/// Версия раскладки счетов, которые записывает этот код
+pub const ACCOUNT_VERSION: u8 = 2;
 
 #[derive(BorshDeserialize, BorshSerialize)]
 pub struct TokenWallet {
-    /// хранит для каждого токена его баланс
-    pub accounts: LookupMap<TokenId, TokenAccount>,
+    /// хранит для каждого токена его баланс. Ключ включает версию раскладки счета,
+    /// счета версии 1 лежат под тем же префиксом с ключом из одного токена.
+    pub accounts: LookupMap<(TokenId, u8), TokenAccount>,
 }
 
 impl TokenWallet {
@@ -21,9 +25,9 @@ impl TokenWallet {
     pub fn get_balances(&self, token_ids: Vec<TokenId>) -> Vec<(TokenId, Balance)> {
         let mut array: Vec<(TokenId, Balance)> = Vec::new();
         for token in token_ids {
-            let balance = match self.get_account(&token) {
-                Some(b) => b.balance,
-                None => 0u128,
+            let balance = match self.accounts.get(&(token.clone(), ACCOUNT_VERSION)) {
+                Some(account) => account.balance,
+                None => self.get_v1_accounts().get(&token).map_or(0u128, |account| account.balance),
             };
             array.push((token, balance));
         }
@@ -31,12 +35,24 @@ impl TokenWallet {
     }
 
     /// Helper method to get the account details for `owner_id`.
-    pub fn get_account(&self, token_id: &TokenId) -> Option<TokenAccount> {
-        self.accounts.get(&token_id)
+    /// Счет версии 1 переводится в текущую раскладку.
+    pub fn get_account(&self, owner_id: &AccountId, token_id: &TokenId) -> Option<TokenAccount> {
+        self.accounts.get(&(token_id.clone(), ACCOUNT_VERSION)).or_else(|| {
+            self.get_v1_accounts()
+                .get(token_id)
+                .map(|account| account.into_current(owner_id, token_id))
+        })
     }
 
     /// Helper method to set the account details for `owner_id` to the state.
+    /// Счет версии 1, если он был, удаляется: дальше читается записанный.
     pub fn set_account(&mut self, token_id: &TokenId, account: &TokenAccount) {
-        self.accounts.insert(token_id, account);
+        self.accounts.insert(&(token_id.clone(), ACCOUNT_VERSION), account);
+        self.get_v1_accounts().remove_raw(&token_id.try_to_vec().unwrap());
+    }
+
+    /// Счета версии 1: их ключ - только токен, поэтому с текущими они не пересекаются
+    fn get_v1_accounts(&self) -> LookupMap<TokenId, TokenAccountV1> {
+        LookupMap::try_from_slice(&self.accounts.try_to_vec().unwrap()).unwrap()
     }
 }
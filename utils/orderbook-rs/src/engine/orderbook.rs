@@ -214,6 +214,26 @@ impl Orderbook {
         Some((bid, ask))
     }
 
+    /// Get quantity of `order_asset` which market bid could buy spending `budget` of `price_asset`
+    pub fn market_bid_qty(&self, budget: u128) -> u128 {
+        let mut asks: Vec<&Order> = self.ask_queue.orders.values().collect();
+        asks.sort_by(|a, b| a.price.partial_cmp(&b.price).unwrap());
+
+        let mut left = budget;
+        let mut qty = 0u128;
+        for ask in asks {
+            let cost = (ask.price * ask.qty as f64) as u128;
+            if cost <= left {
+                left -= cost;
+                qty += ask.qty;
+            } else {
+                qty += (left as f64 / ask.price) as u128;
+                break;
+            }
+        }
+        qty
+    }
+
     /* Processing logic */
 
     fn process_market_order(
@@ -673,6 +693,28 @@ mod test {
         assert_eq!(ord.first().unwrap().qty, 45);
     }
 
+    #[test]
+    fn market_bid_qty() {
+        testing_env!(get_context(carol()));
+        let mut orderbook = Orderbook::new("BTC".to_string(), "USD".to_string());
+        for (price, qty) in [(5.0, 100), (4.0, 10)] {
+            let request = orders::new_limit_order_request(
+                "BTC".to_string(),
+                "USD".to_string(),
+                OrderSide::Ask,
+                price, qty,
+                "lena".to_string(),
+                get_current_time()
+            );
+            orderbook.process_order(request);
+        }
+
+        assert_eq!(orderbook.market_bid_qty(0), 0);
+        assert_eq!(orderbook.market_bid_qty(40), 10);
+        assert_eq!(orderbook.market_bid_qty(120), 26);
+        assert_eq!(orderbook.market_bid_qty(10_000), 110);
+    }
+
     #[test]
     fn cancel_nonexisting() {
         let mut orderbook = Orderbook::new("BTC".to_string(), "USD".to_string());